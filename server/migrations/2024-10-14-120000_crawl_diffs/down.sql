@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS crawl_diffs;
+DROP TABLE IF EXISTS crawl_indexed_pages;
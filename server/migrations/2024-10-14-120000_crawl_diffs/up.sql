@@ -0,0 +1,23 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS crawl_indexed_pages (
+    id UUID PRIMARY KEY,
+    crawl_id UUID NOT NULL,
+    dataset_id UUID NOT NULL REFERENCES datasets(id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    content_hash TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS crawl_indexed_pages_crawl_id_idx ON crawl_indexed_pages (crawl_id);
+CREATE INDEX IF NOT EXISTS crawl_indexed_pages_dataset_id_idx ON crawl_indexed_pages (dataset_id, created_at);
+
+CREATE TABLE IF NOT EXISTS crawl_diffs (
+    id UUID PRIMARY KEY,
+    dataset_id UUID NOT NULL REFERENCES datasets(id) ON DELETE CASCADE,
+    old_crawl_id UUID NOT NULL,
+    new_crawl_id UUID NOT NULL,
+    diff JSONB NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS crawl_diffs_new_crawl_id_idx ON crawl_diffs (new_crawl_id);
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE crawl_requests DROP COLUMN IF EXISTS archive_key;
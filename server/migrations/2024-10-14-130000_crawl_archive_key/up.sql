@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE crawl_requests ADD COLUMN IF NOT EXISTS archive_key TEXT;
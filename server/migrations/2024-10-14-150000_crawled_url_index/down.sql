@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS crawl_errors;
+DROP TABLE IF EXISTS crawled_url_index;
@@ -0,0 +1,24 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS crawled_url_index (
+    id UUID PRIMARY KEY,
+    organization_id UUID NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    dataset_id UUID NOT NULL REFERENCES datasets(id) ON DELETE CASCADE,
+    url_hash TEXT NOT NULL,
+    url TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (organization_id, url_hash, dataset_id)
+);
+
+CREATE INDEX IF NOT EXISTS crawled_url_index_org_url_hash_idx ON crawled_url_index (organization_id, url_hash);
+
+CREATE TABLE IF NOT EXISTS crawl_errors (
+    id UUID PRIMARY KEY,
+    crawl_id UUID NOT NULL,
+    dataset_id UUID NOT NULL REFERENCES datasets(id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    error_type TEXT NOT NULL,
+    message TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS crawl_errors_crawl_id_idx ON crawl_errors (crawl_id, error_type);
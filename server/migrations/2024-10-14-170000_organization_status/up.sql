@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE organizations ADD COLUMN IF NOT EXISTS status TEXT NOT NULL DEFAULT 'active';
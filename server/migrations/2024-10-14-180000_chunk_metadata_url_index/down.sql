@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX CONCURRENTLY IF EXISTS idx_chunk_metadata_url_trgm;
+DROP INDEX CONCURRENTLY IF EXISTS idx_chunk_metadata_dataset_id_url;
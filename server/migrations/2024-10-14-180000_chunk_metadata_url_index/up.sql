@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+-- Equality lookups of the chunks of a page
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_chunk_metadata_dataset_id_url ON chunk_metadata (dataset_id, (metadata->>'url'));
+-- Prefix and substring lookups (LIKE and ILIKE) of the chunks under a url
+CREATE INDEX CONCURRENTLY IF NOT EXISTS idx_chunk_metadata_url_trgm ON chunk_metadata USING gin ((metadata->>'url') gin_trgm_ops);
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS audit_crawl_events (
+    id UUID PRIMARY KEY,
+    crawl_request_id UUID NOT NULL,
+    dataset_id UUID NOT NULL,
+    scrape_id UUID NOT NULL,
+    user_id UUID,
+    action TEXT NOT NULL,
+    old_value JSONB,
+    new_value JSONB,
+    occurred_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS audit_crawl_events_crawl_request_id_idx ON audit_crawl_events (crawl_request_id, occurred_at);
+CREATE INDEX IF NOT EXISTS audit_crawl_events_dataset_id_scrape_id_idx ON audit_crawl_events (dataset_id, scrape_id);
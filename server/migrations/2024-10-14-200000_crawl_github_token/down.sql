@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+UPDATE crawl_requests
+SET crawl_options = jsonb_set(crawl_options, '{scrape_options,token}', to_jsonb(github_token))
+WHERE github_token IS NOT NULL AND crawl_options ? 'scrape_options';
+
+ALTER TABLE crawl_requests DROP COLUMN IF EXISTS github_token;
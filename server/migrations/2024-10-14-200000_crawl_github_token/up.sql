@@ -0,0 +1,23 @@
+-- Your SQL goes here
+ALTER TABLE crawl_requests ADD COLUMN IF NOT EXISTS github_token TEXT;
+
+-- GitHub tokens were stored in the crawl options, which are returned by the api
+UPDATE crawl_requests
+SET github_token = crawl_options->'scrape_options'->>'token',
+    crawl_options = crawl_options #- '{scrape_options,token}'
+WHERE crawl_options->'scrape_options' ? 'token';
+
+UPDATE crawl_requests
+SET settings_history = (
+    SELECT jsonb_agg(snapshot #- '{previous_options,scrape_options,token}' ORDER BY position)
+    FROM jsonb_array_elements(settings_history) WITH ORDINALITY AS snapshots(snapshot, position)
+)
+WHERE jsonb_array_length(settings_history) > 0;
+
+UPDATE audit_crawl_events
+SET old_value = old_value #- '{scrape_options,token}'
+WHERE old_value->'scrape_options' ? 'token';
+
+UPDATE audit_crawl_events
+SET new_value = new_value #- '{scrape_options,token}'
+WHERE new_value->'scrape_options' ? 'token';
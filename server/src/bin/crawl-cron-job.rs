@@ -1,9 +1,12 @@
 use diesel_async::pooled_connection::{AsyncDieselConnectionManager, ManagerConfig};
 use tracing_subscriber::{prelude::*, EnvFilter, Layer};
 use trieve_server::{
+    data::models::ScrapeOptions,
     errors::ServiceError,
     establish_connection, get_env,
-    operators::crawl_operator::{crawl_site, get_crawl_requests_to_rerun, update_scrape_id},
+    operators::crawl_operator::{
+        crawl_site, get_crawl_requests_to_rerun, purge_old_crawl_requests, update_scrape_id,
+    },
 };
 
 #[allow(clippy::print_stdout)]
@@ -80,13 +83,18 @@ async fn main() -> Result<(), ServiceError> {
 
     let pool = actix_web::web::Data::new(pool.clone());
 
-    let new_requests = get_crawl_requests_to_rerun(pool.clone()).await?;
+    let redis_pool = actix_web::web::Data::new(redis_pool);
+
+    let new_requests = get_crawl_requests_to_rerun(pool.clone(), redis_pool.clone()).await?;
 
     for request in new_requests {
         log::info!("Re-crawling site: {}", request.url);
-        let new_scrape_id = crawl_site(request.crawl_options.clone())
-            .await
-            .expect("Failed to crawl site");
+        let new_scrape_id = match request.crawl_options.scrape_options {
+            Some(ScrapeOptions::GitHub(_)) => uuid::Uuid::new_v4(),
+            _ => crawl_site(request.crawl_options.clone())
+                .await
+                .expect("Failed to crawl site"),
+        };
 
         let updated_request = update_scrape_id(request.scrape_id, new_scrape_id, pool.clone())
             .await
@@ -106,5 +114,36 @@ async fn main() -> Result<(), ServiceError> {
             .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
     }
 
+    // The cron job may run more often than daily, only purge once per night
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let should_purge: bool = redis::cmd("SET")
+        .arg("crawl_purge_lock")
+        .arg(chrono::Utc::now().timestamp())
+        .arg("NX")
+        .arg("EX")
+        .arg(60 * 60 * 24)
+        .query_async::<redis::aio::MultiplexedConnection, Option<String>>(&mut *redis_conn)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?
+        .is_some();
+
+    if should_purge {
+        let older_than_days: u32 = std::env::var("CRAWL_PURGE_OLDER_THAN_DAYS")
+            .unwrap_or("30".to_string())
+            .parse()
+            .unwrap_or(30);
+
+        let deleted = purge_old_crawl_requests(older_than_days, pool.clone()).await?;
+        log::info!(
+            "Purged {} crawl requests older than {} days",
+            deleted,
+            older_than_days
+        );
+    }
+
     Ok(())
 }
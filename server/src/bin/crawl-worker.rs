@@ -3,6 +3,7 @@ use diesel_async::pooled_connection::{AsyncDieselConnectionManager, ManagerConfi
 use sentry::{Hub, SentryFutureExt};
 use serde::{Deserialize, Serialize};
 use signal_hook::consts::SIGTERM;
+use std::collections::HashSet;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
@@ -12,24 +13,56 @@ use trieve_server::{
     data::models::{self, WorkerEvent},
     operators::{
         clickhouse_operator::{ClickHouseEvent, EventQueue},
+        completion_operator::{
+            dispatch_completion_actions, error_rate_above_threshold, CrawlCompletionPayload,
+            ERROR_THRESHOLD_EXCEEDED_ALERT,
+        },
         dataset_operator::get_dataset_by_id_query,
         user_operator::hash_function,
     },
 };
 use trieve_server::{
     data::models::{
-        CrawlRequest, CrawlShopifyOptions, DatasetConfiguration, RedisPool, ScrapeOptions,
+        CrawlError, CrawlGitHubOptions, CrawlIndexedPage, CrawlOptions, CrawlRequest,
+        CrawlShopifyOptions, DatasetConfiguration, LinkedDocumentMessage, RedisPool, ScrapeOptions,
+        UnifiedId,
+    },
+    operators::crawl_operator::{
+        archive_completed_crawl_data, clear_firecrawl_progress, crawl_site,
+        get_cached_firecrawl_docs, get_crawl_request_by_id, get_document_kind, is_crawl_reindex,
+        resume_interrupted_crawl, save_firecrawl_exclude_url_patterns, CircuitBreaker, Document,
+        DocumentKind, IngestResult, Status,
     },
-    operators::crawl_operator::{get_crawl_from_firecrawl, Status},
+    operators::file_operator::get_aws_bucket,
+    operators::github_operator::crawl_github_repo,
 };
 use trieve_server::{
     data::models::{CrawlStatus, Pool},
     errors::ServiceError,
     establish_connection, get_env,
-    operators::crawl_operator::{get_tags, update_crawl_status},
+    operators::crawl_operator::{
+        compile_url_patterns, compute_crawl_diff, crawled_url_hash, delete_stale_chunks_for_crawl,
+        get_previous_crawl_id, get_tags, get_urls_indexed_in_other_datasets, record_crawl_errors,
+        record_crawl_indexed_pages, record_crawled_urls, update_crawl_duration,
+        update_crawl_status, url_has_allowed_scheme, url_matches_any_pattern,
+        DEDUPLICATED_CRAWL_ERROR,
+    },
 };
 use trieve_server::{
-    handlers::chunk_handler::ChunkReqPayload, operators::crawl_operator::chunk_html,
+    handlers::chunk_handler::ChunkReqPayload,
+    operators::crawl_operator::{
+        apply_title_extraction_strategy, chunk_faqs, chunk_figures, chunk_page_html,
+        detect_language, detect_page_language, enqueue_linked_documents, enrich_chunks_with_openai,
+        extract_breadcrumbs, extract_favicon_url, extract_glossary, filter_page_metadata,
+        get_external_links, get_linked_document_urls, glossary_to_html, linked_document_types,
+        replace_mathml_with_latex, scrape_single_page, truncate_html, FAQ_CHUNK_WEIGHT,
+        MAX_PRESERVED_CHUNK_HTML_BYTES,
+    },
+    operators::parse_operator::{
+        convert_html_to_text, detect_paywall, escape_html, markdown_to_html,
+        normalize_whitespace_in_text, sanitize_html, ChunkDeduplicator, SanitizeConfig,
+    },
+    operators::pdf_operator::{fetch_pdf_document, pdfium},
 };
 use trieve_server::{
     handlers::chunk_handler::{FullTextBoost, SemanticBoost},
@@ -41,6 +74,7 @@ use ureq::json;
 struct ScrapeReport {
     request_id: uuid::Uuid,
     pages_scraped: usize,
+    pages_failed: usize,
     chunks_created: usize,
 }
 
@@ -153,7 +187,7 @@ fn create_chunk_req_payload(
 
 async fn get_chunks_from_shopify(
     scrape_request: CrawlRequest,
-) -> Result<(Vec<ChunkReqPayload>, usize), ServiceError> {
+) -> Result<(Vec<ChunkReqPayload>, usize, usize), ServiceError> {
     let mut chunks: Vec<ChunkReqPayload> = Vec::new();
     let mut cur_page = 1;
 
@@ -193,15 +227,207 @@ async fn get_chunks_from_shopify(
         cur_page += 1;
     }
 
-    Ok((chunks, cur_page))
+    Ok((chunks, cur_page, 0))
+}
+
+/// Polls the Firecrawl crawl until it is no longer scraping
+async fn wait_for_firecrawl_crawl(
+    scrape_id: uuid::Uuid,
+    crawl_options: &CrawlOptions,
+    firecrawl_circuit_breaker: web::Data<CircuitBreaker>,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<IngestResult, ServiceError> {
+    save_firecrawl_exclude_url_patterns(scrape_id, crawl_options, redis_pool.clone()).await?;
+    loop {
+        let ingest_result = resume_interrupted_crawl(
+            scrape_id,
+            firecrawl_circuit_breaker.clone(),
+            redis_pool.clone(),
+        )
+        .await
+        .map_err(|e| {
+            log::error!("Error getting scrape request: {:?}", e);
+            ServiceError::InternalServerError("Error getting scrape request".to_string())
+        })?;
+
+        if ingest_result.status != Status::Scraping {
+            return Ok(ingest_result);
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+    }
+}
+
+/// Crawls each seed url of the request as a separate Firecrawl crawl, since Firecrawl only
+/// starts a crawl from a single url. A seed crawl which fails is logged and skipped.
+async fn get_documents_from_seed_urls(
+    scrape_request: &CrawlRequest,
+    firecrawl_circuit_breaker: web::Data<CircuitBreaker>,
+    redis_pool: web::Data<RedisPool>,
+) -> Vec<Option<Document>> {
+    let mut documents = vec![];
+    for seed_url in scrape_request
+        .crawl_options
+        .seed_urls
+        .clone()
+        .unwrap_or_default()
+    {
+        let seed_scrape_id = match crawl_site(CrawlOptions {
+            site_url: Some(seed_url.clone()),
+            seed_urls: None,
+            ..scrape_request.crawl_options.clone()
+        })
+        .await
+        {
+            Ok(seed_scrape_id) => seed_scrape_id,
+            Err(e) => {
+                log::error!("Error starting crawl of seed url {}: {:?}", seed_url, e);
+                continue;
+            }
+        };
+
+        match wait_for_firecrawl_crawl(
+            seed_scrape_id,
+            &scrape_request.crawl_options,
+            firecrawl_circuit_breaker.clone(),
+            redis_pool.clone(),
+        )
+        .await
+        {
+            Ok(ingest_result)
+                if matches!(ingest_result.status, Status::Completed | Status::Partial) =>
+            {
+                documents.extend(ingest_result.data.unwrap_or_default());
+            }
+            Ok(ingest_result) => {
+                log::error!(
+                    "Crawl of seed url {} ended with status {:?}",
+                    seed_url,
+                    ingest_result.status
+                );
+            }
+            Err(e) => {
+                log::error!("Error getting crawl of seed url {}: {:?}", seed_url, e);
+            }
+        }
+        if let Err(e) = clear_firecrawl_progress(seed_scrape_id, redis_pool.clone()).await {
+            log::error!("Failed to clear firecrawl progress: {:?}", e);
+        }
+    }
+
+    documents
+}
+
+/// Waits for the Firecrawl crawl of the request to finish and returns the crawled documents,
+/// followed by the pages of its seed urls which the crawl did not find
+async fn get_documents_from_firecrawl(
+    scrape_request: CrawlRequest,
+    firecrawl_circuit_breaker: web::Data<CircuitBreaker>,
+    pool: web::Data<Pool>,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<Vec<Option<Document>>, ServiceError> {
+    let ingest_result = wait_for_firecrawl_crawl(
+        scrape_request.scrape_id,
+        &scrape_request.crawl_options,
+        firecrawl_circuit_breaker.clone(),
+        redis_pool.clone(),
+    )
+    .await?;
+    if !matches!(ingest_result.status, Status::Completed | Status::Partial) {
+        update_crawl_status(
+            scrape_request.scrape_id,
+            CrawlStatus::Failed,
+            pool.clone(),
+            redis_pool.clone(),
+        )
+        .await
+        .map_err(|e| {
+            log::error!("Error updating crawl status: {:?}", e);
+            ServiceError::InternalServerError("Error updating crawl status".to_string())
+        })?;
+
+        return Err(ServiceError::InternalServerError(
+            "Scrape failed".to_string(),
+        ));
+    }
+
+    let mut documents = ingest_result.data.unwrap_or_default();
+    let mut crawled_urls: HashSet<String> = documents
+        .iter()
+        .flatten()
+        .filter_map(|document| document.metadata.source_url.clone())
+        .collect();
+    for document in get_documents_from_seed_urls(
+        &scrape_request,
+        firecrawl_circuit_breaker,
+        redis_pool.clone(),
+    )
+    .await
+    {
+        let source_url = document
+            .as_ref()
+            .and_then(|document| document.metadata.source_url.clone());
+        match source_url {
+            Some(source_url) if !crawled_urls.insert(source_url) => {}
+            _ => documents.push(document),
+        }
+    }
+
+    update_crawl_status(
+        scrape_request.scrape_id,
+        CrawlStatus::GotResponseBackFromFirecrawl,
+        pool.clone(),
+        redis_pool.clone(),
+    )
+    .await
+    .map_err(|e| {
+        log::error!("Error updating crawl status: {:?}", e);
+        ServiceError::InternalServerError("Error updating crawl status".to_string())
+    })?;
+
+    log::info!(
+        "Got response back from firecrawl for scrape_id: {}",
+        scrape_request.id
+    );
+
+    Ok(documents)
+}
+
+/// Folds a near-duplicate chunk of the page into the chunk it duplicates instead of indexing the
+/// same text twice. The page is added to the `duplicate_urls` metadata of the chunk and to its
+/// groups, so that the chunk is still found through the page.
+fn merge_near_duplicate_chunk(chunk: &mut ChunkReqPayload, page_link: &str) {
+    if chunk.link.as_deref() == Some(page_link) {
+        return;
+    }
+
+    let metadata = chunk.metadata.get_or_insert_with(|| json!({}));
+    match metadata["duplicate_urls"].as_array_mut() {
+        Some(duplicate_urls) => {
+            if !duplicate_urls
+                .iter()
+                .any(|url| url.as_str() == Some(page_link))
+            {
+                duplicate_urls.push(json!(page_link));
+            }
+        }
+        None => metadata["duplicate_urls"] = json!([page_link]),
+    }
+
+    let group_tracking_ids = chunk.group_tracking_ids.get_or_insert_with(Vec::new);
+    if !group_tracking_ids.iter().any(|id| id == page_link) {
+        group_tracking_ids.push(page_link.to_string());
+    }
 }
 
 #[allow(clippy::print_stdout)]
 async fn get_chunks_with_firecrawl(
     scrape_request: CrawlRequest,
+    organization_id: uuid::Uuid,
+    reindex: bool,
+    firecrawl_circuit_breaker: web::Data<CircuitBreaker>,
     pool: web::Data<Pool>,
-) -> Result<(Vec<ChunkReqPayload>, usize), ServiceError> {
-    let mut chunks = vec![];
+    redis_pool: web::Data<RedisPool>,
+) -> Result<(Vec<ChunkReqPayload>, usize, usize), ServiceError> {
     let mut spec = None;
 
     if let Some(ScrapeOptions::OpenApi(openapi_options)) =
@@ -239,54 +465,107 @@ async fn get_chunks_with_firecrawl(
         }
     }
 
-    let ingest_result;
-    loop {
-        let temp_result = get_crawl_from_firecrawl(scrape_request.scrape_id)
-            .await
-            .map_err(|e| {
-                log::error!("Error getting scrape request: {:?}", e);
-                ServiceError::InternalServerError("Error getting scrape request".to_string())
-            })?;
-        if temp_result.status == Status::Completed {
-            ingest_result = temp_result;
-            break;
-        } else if temp_result.status == Status::Scraping {
-            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-        } else if temp_result.status == Status::Failed {
-            update_crawl_status(scrape_request.id, CrawlStatus::Failed, pool.clone())
-                .await
-                .map_err(|e| {
-                    log::error!("Error updating crawl status: {:?}", e);
-                    ServiceError::InternalServerError("Error updating crawl status".to_string())
-                })?;
+    let data: Vec<Option<Document>> = if let Some(ScrapeOptions::GitHub(ref github_options)) =
+        scrape_request.crawl_options.scrape_options
+    {
+        // The token is not sent over the scrape queue, it is read back from the crawl request
+        let github_token = get_crawl_request_by_id(scrape_request.id, pool.clone())
+            .await?
+            .crawl_options
+            .github_token();
+        crawl_github_repo(&CrawlGitHubOptions {
+            token: github_token,
+            ..github_options.clone()
+        })
+        .await?
+        .into_iter()
+        .map(Some)
+        .collect()
+    } else if reindex {
+        // The documents were restored from the crawl archive by reindex_crawl
+        get_cached_firecrawl_docs(scrape_request.scrape_id, redis_pool.clone()).await?
+    } else {
+        get_documents_from_firecrawl(
+            scrape_request.clone(),
+            firecrawl_circuit_breaker,
+            pool.clone(),
+            redis_pool.clone(),
+        )
+        .await?
+    };
 
-            return Err(ServiceError::InternalServerError(
-                "Scrape failed".to_string(),
-            ));
-        }
+    if scrape_request
+        .crawl_options
+        .index_linked_documents
+        .unwrap_or(false)
+    {
+        // Linked documents are scraped by their own jobs, the links they contain are not followed
+        let linked_document_urls =
+            get_linked_document_urls(&data, &linked_document_types(&scrape_request.crawl_options));
+        enqueue_linked_documents(linked_document_urls, &scrape_request, redis_pool.clone()).await?;
     }
 
-    update_crawl_status(
-        scrape_request.id,
-        CrawlStatus::GotResponseBackFromFirecrawl,
-        pool.clone(),
-    )
-    .await
-    .map_err(|e| {
-        log::error!("Error updating crawl status: {:?}", e);
-        ServiceError::InternalServerError("Error updating crawl status".to_string())
-    })?;
-
-    log::info!(
-        "Got response back from firecrawl for scrape_id: {}",
-        scrape_request.id
-    );
+    get_chunks_from_documents(data, spec, &scrape_request, organization_id, true, pool).await
+}
 
-    let data = ingest_result.data.unwrap_or_default();
+/// Chunks the crawled documents and records them as the indexed pages of the crawl. Pdfs are
+/// downloaded and converted with pdfium when `fetch_pdfs` is set, since Firecrawl only returns
+/// their text.
+#[allow(clippy::print_stdout)]
+async fn get_chunks_from_documents(
+    data: Vec<Option<Document>>,
+    spec: Option<oas3::OpenApiV3Spec>,
+    scrape_request: &CrawlRequest,
+    organization_id: uuid::Uuid,
+    fetch_pdfs: bool,
+    pool: web::Data<Pool>,
+) -> Result<(Vec<ChunkReqPayload>, usize, usize), ServiceError> {
+    let mut chunks = vec![];
+    let skip_url_patterns = compile_url_patterns(
+        &scrape_request
+            .crawl_options
+            .skip_urls_matching
+            .clone()
+            .unwrap_or_default(),
+    )?;
 
     log::info!("Processing {} documents from scrape", data.len());
 
     let page_count = data.len();
+    let mut indexed_pages = vec![];
+    let normalize_whitespace = scrape_request
+        .crawl_options
+        .normalize_whitespace
+        .unwrap_or(true);
+    let mut crawl_errors = vec![];
+    let mut failed_page_count = 0;
+    let mut chunk_deduplicator = scrape_request
+        .crawl_options
+        .smart_dedup_strategy
+        .as_ref()
+        .map(ChunkDeduplicator::from);
+
+    let already_indexed_url_hashes = if scrape_request
+        .crawl_options
+        .deduplicate_across_datasets
+        .unwrap_or(false)
+    {
+        let url_hashes = data
+            .iter()
+            .flatten()
+            .filter_map(|page| page.metadata.source_url.as_deref())
+            .map(crawled_url_hash)
+            .collect();
+        get_urls_indexed_in_other_datasets(
+            organization_id,
+            scrape_request.dataset_id,
+            url_hashes,
+            pool.clone(),
+        )
+        .await?
+    } else {
+        HashSet::new()
+    };
 
     for page in data {
         let page = match page {
@@ -296,12 +575,7 @@ async fn get_chunks_with_firecrawl(
 
         if page.metadata.status_code != Some(200) {
             log::error!("Error getting page metadata for chunk: {:?}", page.metadata);
-            update_crawl_status(scrape_request.id, CrawlStatus::Failed, pool.clone())
-                .await
-                .map_err(|e| {
-                    log::error!("Error updating crawl status: {:?}", e);
-                    ServiceError::InternalServerError("Error updating crawl status".to_string())
-                })?;
+            failed_page_count += 1;
         }
 
         let page_link = page.metadata.source_url.clone().unwrap_or_default();
@@ -313,10 +587,156 @@ async fn get_chunks_with_firecrawl(
             continue;
         }
 
+        if !url_has_allowed_scheme(
+            &page_link,
+            scrape_request.crawl_options.allowed_schemes.as_deref(),
+        ) {
+            log::info!(
+                "Skipping page with a scheme not in allowed_schemes: {}",
+                page_link
+            );
+            continue;
+        }
+
+        if url_matches_any_pattern(&page_link, &skip_url_patterns) {
+            log::info!("Skipping page matching skip_urls_matching: {}", page_link);
+            continue;
+        }
+
+        if already_indexed_url_hashes.contains(&crawled_url_hash(&page_link)) {
+            log::info!(
+                "Skipping page already indexed in another dataset: {}",
+                page_link
+            );
+            crawl_errors.push(CrawlError::from_details(
+                scrape_request.scrape_id,
+                scrape_request.dataset_id,
+                page_link,
+                DEDUPLICATED_CRAWL_ERROR,
+                Some("Url is already indexed in another dataset of the organization".to_string()),
+            ));
+            continue;
+        }
+
+        let mut page = match get_document_kind(&page) {
+            DocumentKind::Pdf if fetch_pdfs && pdfium().is_ok() => match fetch_pdf_document(
+                &page_link,
+                scrape_request
+                    .crawl_options
+                    .include_pdf_images
+                    .unwrap_or(false),
+            )
+            .await
+            {
+                Ok(pdf_page) => pdf_page,
+                Err(e) => {
+                    log::error!("Error converting pdf {}: {:?}", page_link, e);
+                    page
+                }
+            },
+            DocumentKind::Markdown if page.html.is_none() => Document {
+                html: page.markdown.as_deref().map(markdown_to_html),
+                ..page
+            },
+            DocumentKind::Json if page.html.is_none() => Document {
+                html: page
+                    .raw_html
+                    .as_deref()
+                    .or(page.markdown.as_deref())
+                    .map(|json| format!("<pre>{}</pre>", escape_html(json))),
+                ..page
+            },
+            _ => page,
+        };
+
+        let is_paywalled = detect_paywall(
+            page.raw_html
+                .as_deref()
+                .or(page.html.as_deref())
+                .unwrap_or_default(),
+        );
+        page.metadata.is_paywalled = Some(is_paywalled);
+        page.metadata.favicon_url = page
+            .raw_html
+            .as_deref()
+            .or(page.html.as_deref())
+            .and_then(|html| extract_favicon_url(html, &page_link));
+        page.metadata.breadcrumbs = page
+            .raw_html
+            .as_deref()
+            .or(page.html.as_deref())
+            .map(|html| extract_breadcrumbs(html, &page_link))
+            .filter(|breadcrumbs| !breadcrumbs.is_empty());
+        if page.metadata.language.is_none()
+            && scrape_request
+                .crawl_options
+                .auto_detect_language
+                .unwrap_or(false)
+        {
+            page.metadata.language = page
+                .html
+                .as_deref()
+                .and_then(|html| detect_page_language(&convert_html_to_text(html)));
+        }
+        if is_paywalled {
+            log::warn!("Page looks paywalled: {}", page_link);
+            if scrape_request.crawl_options.skip_paywalled.unwrap_or(false) {
+                continue;
+            }
+        }
+
         let page_title = page.metadata.og_title.clone().unwrap_or_default();
         let page_description = page.metadata.og_description.clone().unwrap_or_default();
         let page_html = page.html.clone().unwrap_or_default();
-        let page_tags = get_tags(page_link.clone());
+        let mut page_tags = get_tags(
+            page_link.clone(),
+            scrape_request.crawl_options.tag_prefix.as_deref(),
+        );
+        if scrape_request
+            .crawl_options
+            .breadcrumbs_as_tags
+            .unwrap_or(false)
+        {
+            for breadcrumb in page.metadata.breadcrumbs.iter().flatten() {
+                let tag = format!(
+                    "{}{}",
+                    scrape_request
+                        .crawl_options
+                        .tag_prefix
+                        .as_deref()
+                        .unwrap_or_default(),
+                    breadcrumb.name
+                );
+                if !page_tags.contains(&tag) {
+                    page_tags.push(tag);
+                }
+            }
+        }
+        let indexed_page_metadata = scrape_request
+            .crawl_options
+            .metadata_fields_to_index
+            .as_ref()
+            .map(|fields| filter_page_metadata(&page.metadata, fields))
+            .unwrap_or_default();
+        let external_links = if scrape_request
+            .crawl_options
+            .extract_links_to_metadata
+            .unwrap_or(false)
+        {
+            Some(get_external_links(
+                &page_link,
+                &page.links.clone().unwrap_or_default(),
+            ))
+        } else {
+            None
+        };
+
+        indexed_pages.push(CrawlIndexedPage::from_details(
+            scrape_request.scrape_id,
+            scrape_request.dataset_id,
+            page_link.clone(),
+            hash_function(&page_html),
+        ));
 
         if let Some(spec) = &spec {
             if let Some(ScrapeOptions::OpenApi(ref openapi_options)) =
@@ -475,17 +895,44 @@ async fn get_chunks_with_firecrawl(
             }
         }
 
-        let chunked_html = chunk_html(&page_html.clone());
+        let sanitized_html = sanitize_html(
+            &page_html,
+            &SanitizeConfig::from(&scrape_request.crawl_options),
+        );
+        let mut chunked_html = chunk_page_html(&sanitized_html, &scrape_request.crawl_options);
+        apply_title_extraction_strategy(
+            &mut chunked_html,
+            &scrape_request.crawl_options,
+            &page_html,
+            &page_title,
+            &page_link,
+        );
 
-        for chunk in chunked_html {
-            let heading = chunk.0.clone();
-            let chunk_html = chunk.1.clone();
+        for (heading, chunk_html, chunk_text) in chunked_html {
+            let (chunk_html, math_ml) = replace_mathml_with_latex(&chunk_html);
+            let chunk_text = if math_ml.is_empty() {
+                chunk_text
+            } else {
+                convert_html_to_text(&chunk_html)
+            };
 
             if chunk_html.is_empty() {
                 println!("Skipping empty chunk for page: {}", page_link);
                 continue;
             }
 
+            if let Some(duplicate_of) = chunk_deduplicator
+                .as_mut()
+                .and_then(|deduplicator| deduplicator.find_duplicate(&chunk_text, chunks.len()))
+            {
+                log::info!(
+                    "Merging near-duplicate chunk for page {} into an earlier chunk",
+                    page_link
+                );
+                merge_near_duplicate_chunk(&mut chunks[duplicate_of], &page_link);
+                continue;
+            }
+
             let mut metadata = json!({
                 "url": page_link.clone(),
             });
@@ -505,11 +952,63 @@ async fn get_chunks_with_firecrawl(
 
                 metadata["description"] = json!(page_description.clone());
             }
+            for (key, value) in indexed_page_metadata.iter() {
+                if metadata.get(key).is_none() {
+                    metadata[key.as_str()] = value.clone();
+                }
+            }
+            if let Some(ref extract) = page.extract {
+                metadata["ai_extract"] = extract.clone();
+            }
+            if let Some(ref external_links) = external_links {
+                metadata["external_links"] = json!(external_links.clone());
+            }
+            if let Some(ref favicon_url) = page.metadata.favicon_url {
+                metadata["favicon_url"] = json!(favicon_url);
+            }
+            if let Some(ref pdf_image_counts) = page.metadata.pdf_image_counts {
+                metadata["pdf_image_counts"] = json!(pdf_image_counts);
+            }
+            if let Some(ref breadcrumbs) = page.metadata.breadcrumbs {
+                metadata["breadcrumbs"] = json!(breadcrumbs);
+            }
+            if !math_ml.is_empty() {
+                metadata["math_ml"] = json!(math_ml);
+            }
+            if scrape_request
+                .crawl_options
+                .preserve_html_in_chunk
+                .unwrap_or(false)
+            {
+                metadata["chunk_html"] =
+                    json!(truncate_html(&chunk_html, MAX_PRESERVED_CHUNK_HTML_BYTES));
+            }
+
+            let mut tag_set = page_tags.clone();
+            let mut group_tracking_ids = vec![page_link.clone()];
+            if scrape_request
+                .crawl_options
+                .split_by_language
+                .unwrap_or(false)
+            {
+                if let Some(language) = detect_language(&chunk_text) {
+                    metadata["language"] = json!(language.clone());
+                    group_tracking_ids = vec![format!("{}#lang={}", page_link, language)];
+                    tag_set.push(language);
+                }
+            }
+
+            let semantic_content = if normalize_whitespace {
+                Some(normalize_whitespace_in_text(&chunk_text))
+            } else {
+                None
+            };
 
             let chunk = ChunkReqPayload {
                 chunk_html: Some(chunk_html.clone()),
+                semantic_content,
                 link: Some(page_link.clone()),
-                tag_set: Some(page_tags.clone()),
+                tag_set: Some(tag_set),
                 metadata: Some(json!(metadata)),
                 tracking_id: Some(hash_function(&format!(
                     "{}{}",
@@ -517,7 +1016,7 @@ async fn get_chunks_with_firecrawl(
                     heading.clone()
                 ))),
                 upsert_by_tracking_id: Some(true),
-                group_tracking_ids: Some(vec![page_link.clone()]),
+                group_tracking_ids: Some(group_tracking_ids),
                 fulltext_boost: if scrape_request.crawl_options.boost_titles.unwrap_or(true) {
                     Some(FullTextBoost {
                         phrase: fulltext_boost_phrase,
@@ -539,33 +1038,230 @@ async fn get_chunks_with_firecrawl(
             };
             chunks.push(chunk);
         }
+
+        if let (Some(_), Some(extract)) = (
+            scrape_request
+                .crawl_options
+                .structured_output_schema
+                .as_ref(),
+            page.extract.as_ref(),
+        ) {
+            let structured_json = serde_json::to_string_pretty(extract).map_err(|e| {
+                log::error!("Error serializing structured output: {:?}", e);
+                ServiceError::InternalServerError("Error serializing structured output".to_string())
+            })?;
+
+            let mut metadata = json!({
+                "url": page_link.clone(),
+                "chunk_type": "structured",
+                "structured_output": extract.clone(),
+            });
+            if !page_title.is_empty() {
+                metadata["title"] = json!(page_title.clone());
+            }
+
+            let mut tag_set = page_tags.clone();
+            tag_set.push("structured".to_string());
+
+            chunks.push(ChunkReqPayload {
+                chunk_html: Some(structured_json),
+                link: Some(page_link.clone()),
+                tag_set: Some(tag_set),
+                metadata: Some(metadata),
+                tracking_id: Some(hash_function(&format!(
+                    "{}structured",
+                    page_link.trim_end_matches("/")
+                ))),
+                upsert_by_tracking_id: Some(true),
+                group_tracking_ids: Some(vec![page_link.clone()]),
+                convert_html_to_text: Some(false),
+                ..Default::default()
+            });
+        }
+
+        if scrape_request.crawl_options.extract_faq.unwrap_or(false) {
+            for faq in chunk_faqs(&sanitized_html) {
+                let mut metadata = json!({
+                    "url": page_link.clone(),
+                    "heading": faq.question.clone(),
+                    "chunk_type": "faq",
+                });
+                if !page_title.is_empty() {
+                    metadata["title"] = json!(page_title.clone());
+                }
+                for (key, value) in indexed_page_metadata.iter() {
+                    if metadata.get(key).is_none() {
+                        metadata[key.as_str()] = value.clone();
+                    }
+                }
+
+                let mut tag_set = page_tags.clone();
+                tag_set.push("faq".to_string());
+
+                let faq_html =
+                    format!("<h3>{}</h3>{}", escape_html(&faq.question), faq.answer_html);
+                let chunk = ChunkReqPayload {
+                    semantic_content: if normalize_whitespace {
+                        Some(normalize_whitespace_in_text(&convert_html_to_text(
+                            &faq_html,
+                        )))
+                    } else {
+                        None
+                    },
+                    chunk_html: Some(faq_html),
+                    link: Some(page_link.clone()),
+                    tag_set: Some(tag_set),
+                    metadata: Some(metadata),
+                    tracking_id: Some(hash_function(&format!(
+                        "{}faq{}",
+                        page_link.trim_end_matches("/"),
+                        faq.question
+                    ))),
+                    upsert_by_tracking_id: Some(true),
+                    group_tracking_ids: Some(vec![page_link.clone()]),
+                    weight: Some(FAQ_CHUNK_WEIGHT),
+                    convert_html_to_text: Some(true),
+                    ..Default::default()
+                };
+                chunks.push(chunk);
+            }
+        }
+
+        if scrape_request
+            .crawl_options
+            .include_figures
+            .unwrap_or(false)
+        {
+            for figure in chunk_figures(&sanitized_html) {
+                let mut metadata = json!({
+                    "url": page_link.clone(),
+                    "heading": figure.heading.clone(),
+                });
+                if let Some(ref image_url) = figure.image_url {
+                    metadata["image_url"] = json!(image_url.clone());
+                }
+                if !page_title.is_empty() {
+                    metadata["title"] = json!(page_title.clone());
+                }
+                for (key, value) in indexed_page_metadata.iter() {
+                    if metadata.get(key).is_none() {
+                        metadata[key.as_str()] = value.clone();
+                    }
+                }
+
+                let mut tag_set = page_tags.clone();
+                tag_set.push("figure".to_string());
+
+                let chunk = ChunkReqPayload {
+                    chunk_html: Some(figure.caption_html.clone()),
+                    semantic_content: if normalize_whitespace {
+                        Some(normalize_whitespace_in_text(&convert_html_to_text(
+                            &figure.caption_html,
+                        )))
+                    } else {
+                        None
+                    },
+                    link: Some(page_link.clone()),
+                    tag_set: Some(tag_set),
+                    metadata: Some(metadata),
+                    tracking_id: Some(hash_function(&format!(
+                        "{}figure{}",
+                        page_link.trim_end_matches("/"),
+                        figure.caption_html
+                    ))),
+                    upsert_by_tracking_id: Some(true),
+                    group_tracking_ids: Some(vec![page_link.clone()]),
+                    image_urls: figure.image_url.clone().map(|image_url| vec![image_url]),
+                    convert_html_to_text: Some(true),
+                    ..Default::default()
+                };
+                chunks.push(chunk);
+            }
+        }
+
+        if scrape_request
+            .crawl_options
+            .extract_glossary
+            .unwrap_or(false)
+        {
+            let glossary = extract_glossary(&sanitized_html);
+            if !glossary.is_empty() {
+                let mut metadata = json!({
+                    "url": page_link.clone(),
+                    "heading": "Glossary",
+                    "chunk_type": "glossary",
+                    "glossary": glossary.clone(),
+                });
+                if !page_title.is_empty() {
+                    metadata["title"] = json!(page_title.clone());
+                }
+
+                let mut tag_set = page_tags.clone();
+                tag_set.push("glossary".to_string());
+
+                let glossary_html = glossary_to_html(&glossary);
+                chunks.push(ChunkReqPayload {
+                    semantic_content: if normalize_whitespace {
+                        Some(normalize_whitespace_in_text(&convert_html_to_text(
+                            &glossary_html,
+                        )))
+                    } else {
+                        None
+                    },
+                    chunk_html: Some(glossary_html),
+                    link: Some(page_link.clone()),
+                    tag_set: Some(tag_set),
+                    metadata: Some(metadata),
+                    tracking_id: Some(hash_function(&format!(
+                        "{}glossary",
+                        page_link.trim_end_matches("/")
+                    ))),
+                    upsert_by_tracking_id: Some(true),
+                    group_tracking_ids: Some(vec![page_link.clone()]),
+                    convert_html_to_text: Some(true),
+                    ..Default::default()
+                });
+            }
+        }
     }
 
-    Ok((chunks, page_count))
-}
+    let crawled_urls = indexed_pages.iter().map(|page| page.url.clone()).collect();
 
-#[allow(clippy::print_stdout)]
-async fn crawl(
-    scrape_request: CrawlRequest,
-    pool: web::Data<Pool>,
-    redis_pool: web::Data<RedisPool>,
-) -> Result<ScrapeReport, ServiceError> {
-    let dataset = get_dataset_by_id_query(
-        trieve_server::data::models::UnifiedId::TrieveUuid(scrape_request.dataset_id),
+    record_crawl_indexed_pages(
+        scrape_request.scrape_id,
+        scrape_request.dataset_id,
+        indexed_pages,
         pool.clone(),
     )
-    .await
-    .map_err(|e| {
-        log::error!("Error getting dataset config: {:?}", e);
-        ServiceError::InternalServerError("Error getting dataset config".to_string())
-    })?;
+    .await?;
 
-    let dataset_config = DatasetConfiguration::from_json(dataset.server_configuration.clone());
+    record_crawled_urls(
+        organization_id,
+        scrape_request.dataset_id,
+        crawled_urls,
+        pool.clone(),
+    )
+    .await?;
 
-    // Use shopify specific logic to get chunks or firecrawl
-    let (chunks, page_count) = match scrape_request.crawl_options.scrape_options {
-        Some(ScrapeOptions::Shopify(_)) => get_chunks_from_shopify(scrape_request.clone()).await?,
-        _ => get_chunks_with_firecrawl(scrape_request.clone(), pool.clone()).await?,
+    record_crawl_errors(crawl_errors, pool.clone()).await?;
+
+    Ok((chunks, page_count, failed_page_count))
+}
+
+/// Enriches the chunks of the crawl when `enrich_with_openai` is set and queues them for
+/// ingestion in batches of 120. Returns the uploaded chunks.
+async fn upload_chunks(
+    chunks: Vec<ChunkReqPayload>,
+    scrape_request: &CrawlRequest,
+    dataset_config: &DatasetConfiguration,
+    pool: web::Data<Pool>,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<Vec<ChunkReqPayload>, ServiceError> {
+    let chunks = match scrape_request.crawl_options.enrich_with_openai {
+        Some(ref enrich_config) => {
+            enrich_chunks_with_openai(chunks, enrich_config, dataset_config).await?
+        }
+        None => chunks,
     };
 
     let chunks_to_upload = chunks.chunks(120);
@@ -599,23 +1295,151 @@ async fn crawl(
         }
     }
 
-    update_crawl_status(
-        scrape_request.scrape_id,
-        CrawlStatus::Completed,
+    Ok(chunks)
+}
+
+#[allow(clippy::print_stdout)]
+async fn crawl(
+    scrape_request: CrawlRequest,
+    firecrawl_circuit_breaker: web::Data<CircuitBreaker>,
+    pool: web::Data<Pool>,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<ScrapeReport, ServiceError> {
+    let dataset = get_dataset_by_id_query(
+        trieve_server::data::models::UnifiedId::TrieveUuid(scrape_request.dataset_id),
         pool.clone(),
     )
-    .await?;
+    .await
+    .map_err(|e| {
+        log::error!("Error getting dataset config: {:?}", e);
+        ServiceError::InternalServerError("Error getting dataset config".to_string())
+    })?;
 
-    update_next_crawl_at(
-        scrape_request.scrape_id,
-        scrape_request.next_crawl_at + scrape_request.interval,
+    let dataset_config = DatasetConfiguration::from_json(dataset.server_configuration.clone());
+    let reindex = is_crawl_reindex(scrape_request.scrape_id, redis_pool.clone()).await?;
+
+    // Use shopify specific logic to get chunks or firecrawl
+    let (chunks, page_count, failed_page_count) = match scrape_request.crawl_options.scrape_options
+    {
+        Some(ScrapeOptions::Shopify(_)) => get_chunks_from_shopify(scrape_request.clone()).await?,
+        _ => {
+            get_chunks_with_firecrawl(
+                scrape_request.clone(),
+                dataset.organization_id,
+                reindex,
+                firecrawl_circuit_breaker,
+                pool.clone(),
+                redis_pool.clone(),
+            )
+            .await?
+        }
+    };
+
+    let chunks = upload_chunks(
+        chunks,
+        &scrape_request,
+        &dataset_config,
         pool.clone(),
+        redis_pool.clone(),
     )
     .await?;
 
+    if reindex {
+        let kept_tracking_ids = chunks
+            .iter()
+            .filter_map(|chunk| chunk.tracking_id.clone())
+            .collect();
+        let deleted_chunks = delete_stale_chunks_for_crawl(
+            scrape_request.scrape_id,
+            scrape_request.dataset_id,
+            kept_tracking_ids,
+            pool.clone(),
+        )
+        .await?;
+        log::info!(
+            "Deleted {} chunks of crawl {} which the reindex no longer produces",
+            deleted_chunks,
+            scrape_request.scrape_id
+        );
+    }
+
+    // The final status is set by scrape_worker from the returned ScrapeReport
+
+    // Reindexing does not crawl the site, so the next scheduled crawl stays as it was
+    if !reindex {
+        update_next_crawl_at(
+            scrape_request.scrape_id,
+            scrape_request.next_crawl_at + scrape_request.interval,
+            pool.clone(),
+        )
+        .await?;
+    }
+
+    if !matches!(
+        scrape_request.crawl_options.scrape_options,
+        Some(ScrapeOptions::Shopify(_))
+    ) {
+        match get_previous_crawl_id(
+            scrape_request.scrape_id,
+            scrape_request.dataset_id,
+            pool.clone(),
+        )
+        .await
+        {
+            Ok(Some(previous_crawl_id)) => {
+                match compute_crawl_diff(previous_crawl_id, scrape_request.scrape_id, pool.clone())
+                    .await
+                {
+                    Ok(diff) => log::info!(
+                        "Crawl diff for {}: {} added, {} removed, {} modified",
+                        scrape_request.scrape_id,
+                        diff.added.len(),
+                        diff.removed.len(),
+                        diff.modified.len()
+                    ),
+                    Err(err) => log::error!("Failed to compute crawl diff: {:?}", err),
+                }
+            }
+            Ok(None) => {}
+            Err(err) => log::error!("Failed to get previous crawl: {:?}", err),
+        }
+    }
+
+    // Only Firecrawl crawls cache the crawled documents to archive
+    if !matches!(
+        scrape_request.crawl_options.scrape_options,
+        Some(ScrapeOptions::Shopify(_)) | Some(ScrapeOptions::GitHub(_))
+    ) {
+        let archive_result = match get_aws_bucket() {
+            Ok(bucket) => {
+                archive_completed_crawl_data(
+                    scrape_request.scrape_id,
+                    pool.clone(),
+                    redis_pool.clone(),
+                    bucket,
+                )
+                .await
+            }
+            Err(err) => Err(err),
+        };
+
+        match archive_result {
+            Ok(archive_key) => log::info!("Archived crawl data to {}", archive_key),
+            Err(err) => {
+                log::error!("Failed to archive crawl data: {:?}", err);
+                if let Err(err) =
+                    clear_firecrawl_progress(scrape_request.scrape_id, redis_pool.clone()).await
+                {
+                    log::error!("Failed to clear firecrawl progress: {:?}", err);
+                }
+            }
+        }
+    }
+
     Ok(ScrapeReport {
         request_id: scrape_request.id,
         pages_scraped: page_count,
+        pages_failed: failed_page_count,
         chunks_created: chunks.len(),
     })
 }
@@ -626,6 +1450,7 @@ async fn scrape_worker(
     redis_pool: web::Data<RedisPool>,
     pool: web::Data<Pool>,
     event_queue: actix_web::web::Data<EventQueue>,
+    firecrawl_circuit_breaker: web::Data<CircuitBreaker>,
 ) {
     log::info!("Starting scrape worker service thread");
 
@@ -700,8 +1525,15 @@ async fn scrape_worker(
         let transaction = sentry::start_transaction(processing_chunk_ctx);
         let crawl_request: CrawlRequest =
             serde_json::from_str(&serialized_message).expect("Failed to parse file message");
+        let started_at = chrono::Utc::now().naive_utc();
 
-        match update_crawl_status(crawl_request.scrape_id, CrawlStatus::Pending, pool.clone()).await
+        match update_crawl_status(
+            crawl_request.scrape_id,
+            CrawlStatus::Pending,
+            pool.clone(),
+            redis_pool.clone(),
+        )
+        .await
         {
             Ok(_) => {}
             Err(err) => {
@@ -710,10 +1542,47 @@ async fn scrape_worker(
             }
         }
 
-        match crawl(crawl_request.clone(), pool.clone(), redis_pool.clone()).await {
+        match crawl(
+            crawl_request.clone(),
+            firecrawl_circuit_breaker.clone(),
+            pool.clone(),
+            redis_pool.clone(),
+        )
+        .await
+        {
             Ok(scrape_report) => {
                 log::info!("Scrape job completed: {:?}", scrape_report);
 
+                if let Some(ref actions) = crawl_request.crawl_options.on_completion_action {
+                    let error_rate = error_rate_above_threshold(
+                        scrape_report.pages_failed,
+                        scrape_report.pages_scraped,
+                        crawl_request.crawl_options.notify_on_error_threshold,
+                    );
+                    if let Some(error_rate) = error_rate {
+                        log::warn!(
+                            "Crawl {} exceeded its error threshold with an error rate of {}",
+                            crawl_request.scrape_id,
+                            error_rate
+                        );
+                    }
+
+                    dispatch_completion_actions(
+                        actions,
+                        &CrawlCompletionPayload {
+                            scrape_id: crawl_request.scrape_id,
+                            dataset_id: crawl_request.dataset_id,
+                            url: crawl_request.url.clone(),
+                            pages_crawled: scrape_report.pages_scraped,
+                            pages_failed: scrape_report.pages_failed,
+                            chunks_created: scrape_report.chunks_created,
+                            alert: error_rate.map(|_| ERROR_THRESHOLD_EXCEEDED_ALERT.to_string()),
+                            error_rate,
+                        },
+                    )
+                    .await;
+                }
+
                 event_queue
                     .send(ClickHouseEvent::WorkerEvent(
                         WorkerEvent::from_details(
@@ -730,9 +1599,13 @@ async fn scrape_worker(
                     .await;
 
                 match update_crawl_status(
-                    scrape_report.request_id,
-                    CrawlStatus::Completed,
+                    crawl_request.scrape_id,
+                    CrawlStatus::from_page_counts(
+                        scrape_report.pages_scraped,
+                        scrape_report.pages_failed,
+                    ),
                     pool.clone(),
+                    redis_pool.clone(),
                 )
                 .await
                 {
@@ -743,6 +1616,14 @@ async fn scrape_worker(
                     }
                 }
 
+                let crawl_duration_ms =
+                    (chrono::Utc::now().naive_utc() - started_at).num_milliseconds();
+                if let Err(err) =
+                    update_crawl_duration(crawl_request.id, crawl_duration_ms, pool.clone()).await
+                {
+                    log::error!("Failed to update crawl duration: {:?}", err);
+                }
+
                 let _ = redis::cmd("LREM")
                     .arg("scrape_processing")
                     .arg(1)
@@ -775,6 +1656,114 @@ async fn scrape_worker(
     }
 }
 
+/// Scrapes a document linked from a page of a crawl and indexes it in the dataset of the crawl.
+/// Returns the number of chunks created.
+async fn index_linked_document(
+    message: LinkedDocumentMessage,
+    pool: web::Data<Pool>,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<usize, ServiceError> {
+    let scrape_request = message.crawl_request;
+    let document = match scrape_single_page(
+        &message.url,
+        &linked_document_types(&scrape_request.crawl_options),
+        scrape_request
+            .crawl_options
+            .include_pdf_images
+            .unwrap_or(false),
+    )
+    .await?
+    {
+        Some(document) => document,
+        None => {
+            log::info!("Skipping linked document {} of another type", message.url);
+            return Ok(0);
+        }
+    };
+
+    let dataset = get_dataset_by_id_query(
+        UnifiedId::TrieveUuid(scrape_request.dataset_id),
+        pool.clone(),
+    )
+    .await?;
+    let dataset_config = DatasetConfiguration::from_json(dataset.server_configuration.clone());
+
+    // The pdfs were already converted from the bytes scrape_single_page downloaded
+    let (chunks, _, _) = get_chunks_from_documents(
+        vec![Some(document)],
+        None,
+        &scrape_request,
+        dataset.organization_id,
+        false,
+        pool.clone(),
+    )
+    .await?;
+    let chunks = upload_chunks(chunks, &scrape_request, &dataset_config, pool, redis_pool).await?;
+
+    Ok(chunks.len())
+}
+
+/// Processes the single page scrape jobs of the documents linked from crawled pages, see
+/// `crawl_operator::enqueue_linked_documents`
+async fn linked_document_worker(
+    should_terminate: Arc<AtomicBool>,
+    redis_pool: web::Data<RedisPool>,
+    pool: web::Data<Pool>,
+) {
+    log::info!("Starting linked document worker service thread");
+
+    while !should_terminate.load(Ordering::Relaxed) {
+        let mut redis_conn = match redis_pool.get().await {
+            Ok(redis_conn) => redis_conn,
+            Err(err) => {
+                log::error!("Failed to get redis connection: {:?}", err);
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+
+        let payload = match redis::cmd("brpoplpush")
+            .arg("linked_document_queue")
+            .arg("linked_document_processing")
+            .arg(1.0)
+            .query_async::<redis::aio::MultiplexedConnection, Vec<String>>(&mut *redis_conn)
+            .await
+        {
+            Ok(payload) => payload,
+            Err(err) => {
+                log::error!("Unable to process linked documents: {:?}", err);
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        let Some(serialized_message) = payload.first() else {
+            continue;
+        };
+
+        match serde_json::from_str::<LinkedDocumentMessage>(serialized_message) {
+            Ok(message) => {
+                let url = message.url.clone();
+                match index_linked_document(message, pool.clone(), redis_pool.clone()).await {
+                    Ok(chunks_created) => log::info!(
+                        "Indexed linked document {} with {} chunks",
+                        url,
+                        chunks_created
+                    ),
+                    Err(err) => log::error!("Failed to index linked document {}: {:?}", url, err),
+                }
+            }
+            Err(err) => log::error!("Failed to parse linked document message: {:?}", err),
+        }
+
+        let _ = redis::cmd("LREM")
+            .arg("linked_document_processing")
+            .arg(1)
+            .arg(serialized_message)
+            .query_async::<redis::aio::MultiplexedConnection, usize>(&mut *redis_conn)
+            .await;
+    }
+}
+
 fn main() {
     dotenvy::dotenv().ok();
     let sentry_url = std::env::var("SENTRY_URL");
@@ -813,6 +1802,10 @@ fn main() {
         None
     };
 
+    if let Err(e) = pdfium() {
+        log::warn!("Pdfs are indexed from the Firecrawl markdown: {}", e);
+    }
+
     let database_url = get_env!("DATABASE_URL", "DATABASE_URL is not set");
 
     let mut config = ManagerConfig::default();
@@ -890,7 +1883,18 @@ fn main() {
                     EventQueue::default()
                 };
                 let web_event_queue = actix_web::web::Data::new(event_queue);
-                scrape_worker(should_terminate, web_redis_pool, web_pool, web_event_queue).await
+                let web_firecrawl_circuit_breaker =
+                    actix_web::web::Data::new(CircuitBreaker::new("Firecrawl"));
+                futures::join!(
+                    scrape_worker(
+                        should_terminate.clone(),
+                        web_redis_pool.clone(),
+                        web_pool.clone(),
+                        web_event_queue,
+                        web_firecrawl_circuit_breaker
+                    ),
+                    linked_document_worker(should_terminate, web_redis_pool, web_pool)
+                );
             }
             .bind_hub(Hub::new_from_top(Hub::current())),
         );
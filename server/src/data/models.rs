@@ -2649,6 +2649,7 @@ impl DatasetAndOrgWithSubAndPlan {
     "created_at": "2021-01-01 00:00:00.000",
     "updated_at": "2021-01-01 00:00:00.000",
     "registerable": true,
+    "status": "active",
 }))]
 #[diesel(table_name = organizations)]
 pub struct Organization {
@@ -2658,6 +2659,8 @@ pub struct Organization {
     pub updated_at: chrono::NaiveDateTime,
     pub registerable: Option<bool>,
     pub deleted: i32,
+    /// Either `active` or `suspended`. The crawls of suspended organizations are not rerun.
+    pub status: String,
 }
 
 impl Organization {
@@ -2669,6 +2672,7 @@ impl Organization {
             updated_at: chrono::Utc::now().naive_local(),
             registerable: Some(true),
             deleted: 0,
+            status: "active".to_string(),
         }
     }
 
@@ -6427,22 +6431,105 @@ impl WordDataset {
 pub enum CrawlStatus {
     Pending,
     GotResponseBackFromFirecrawl,
+    /// Every page of the crawl was indexed
     Completed,
+    /// The crawl finished but some of its pages failed, the other pages were indexed
+    Partial,
     Failed,
 }
 
 impl From<String> for CrawlStatus {
     fn from(status: String) -> Self {
+        // Statuses are stored through Display, accept both that and the snake_case names
         match status.as_str() {
-            "pending" => CrawlStatus::Pending,
-            "got_response_back_from_firecrawl" => CrawlStatus::GotResponseBackFromFirecrawl,
-            "completed" => CrawlStatus::Completed,
-            "failed" => CrawlStatus::Failed,
+            "pending" | "Pending" => CrawlStatus::Pending,
+            "got_response_back_from_firecrawl" | "GotResponseBackFromFirecrawl" => {
+                CrawlStatus::GotResponseBackFromFirecrawl
+            }
+            "completed" | "Completed" => CrawlStatus::Completed,
+            "partial" | "Partial" => CrawlStatus::Partial,
+            "failed" | "Failed" => CrawlStatus::Failed,
             _ => CrawlStatus::Pending,
         }
     }
 }
 
+impl CrawlStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CrawlStatus::Pending => "pending",
+            CrawlStatus::GotResponseBackFromFirecrawl => "got_response_back_from_firecrawl",
+            CrawlStatus::Completed => "completed",
+            CrawlStatus::Partial => "partial",
+            CrawlStatus::Failed => "failed",
+        }
+    }
+
+    /// Status of a crawl once its pages are processed: `Completed` if none failed, `Partial` if
+    /// only some of them failed and `Failed` if all of them did
+    pub fn from_page_counts(pages_crawled: usize, pages_failed: usize) -> Self {
+        if pages_failed == 0 {
+            CrawlStatus::Completed
+        } else if pages_failed < pages_crawled {
+            CrawlStatus::Partial
+        } else {
+            CrawlStatus::Failed
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CrawlEventType {
+    Created,
+    StatusChanged,
+}
+
+/// A state transition of a crawl request, recorded in the crawl's event stream.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[schema(example=json!({
+    "event": "status_changed",
+    "scrape_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "from": "pending",
+    "to": "completed",
+    "timestamp": "2021-01-01 00:00:00.000",
+}))]
+pub struct CrawlEvent {
+    pub event: CrawlEventType,
+    pub scrape_id: uuid::Uuid,
+    /// Status of the crawl request before the transition, absent for newly created crawl requests
+    pub from: Option<String>,
+    /// Status of the crawl request after the transition
+    pub to: String,
+    pub timestamp: chrono::NaiveDateTime,
+}
+
+impl CrawlEvent {
+    pub fn created(scrape_id: uuid::Uuid, status: &CrawlStatus) -> Self {
+        CrawlEvent {
+            event: CrawlEventType::Created,
+            scrape_id,
+            from: None,
+            to: status.as_str().to_string(),
+            timestamp: chrono::Utc::now().naive_utc(),
+        }
+    }
+
+    pub fn status_changed(
+        scrape_id: uuid::Uuid,
+        from: Option<&CrawlStatus>,
+        to: &CrawlStatus,
+    ) -> Self {
+        CrawlEvent {
+            event: CrawlEventType::StatusChanged,
+            scrape_id,
+            from: from.map(|from| from.as_str().to_string()),
+            to: to.as_str().to_string(),
+            timestamp: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone, ToSchema)]
 #[diesel(table_name = crawl_requests)]
 pub struct CrawlRequestPG {
@@ -6455,6 +6542,10 @@ pub struct CrawlRequestPG {
     pub scrape_id: uuid::Uuid,
     pub dataset_id: uuid::Uuid,
     pub created_at: chrono::NaiveDateTime,
+    pub crawl_duration_ms: Option<i64>,
+    /// Kept out of `crawl_options` so the token is never returned with them
+    #[serde(skip_serializing)]
+    pub github_token: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
@@ -6463,28 +6554,45 @@ pub struct CrawlRequest {
     pub url: String,
     pub status: CrawlStatus,
     pub next_crawl_at: chrono::NaiveDateTime,
+    #[schema(value_type = Object)]
     pub interval: std::time::Duration,
     pub crawl_options: CrawlOptions,
     pub scrape_id: uuid::Uuid,
     pub dataset_id: uuid::Uuid,
     pub created_at: chrono::NaiveDateTime,
     pub attempt_number: i32,
+    /// How long the crawl worker took to process the crawl, set once it completes
+    pub crawl_duration_ms: Option<i64>,
 }
 
-impl From<CrawlRequestPG> for CrawlRequest {
-    fn from(crawl_request: CrawlRequestPG) -> Self {
-        Self {
+/// Single page scrape job for a document linked from a page of the crawl, indexed by the crawl
+/// worker in the dataset of the crawl, see `CrawlOptions::index_linked_documents`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LinkedDocumentMessage {
+    pub url: String,
+    pub crawl_request: CrawlRequest,
+}
+
+impl TryFrom<CrawlRequestPG> for CrawlRequest {
+    type Error = ServiceError;
+
+    /// Fails when the stored crawl options can't be migrated, e.g. when they were written by a
+    /// newer version
+    fn try_from(crawl_request: CrawlRequestPG) -> Result<Self, Self::Error> {
+        Ok(Self {
             id: crawl_request.id,
             url: crawl_request.url,
             status: crawl_request.status.into(),
             next_crawl_at: crawl_request.next_crawl_at,
             interval: std::time::Duration::from_secs(crawl_request.interval as u64),
-            crawl_options: serde_json::from_value(crawl_request.crawl_options).unwrap(),
+            crawl_options: CrawlOptionsMigrator::migrate(crawl_request.crawl_options)?
+                .with_github_token(crawl_request.github_token),
             scrape_id: crawl_request.scrape_id,
             dataset_id: crawl_request.dataset_id,
             created_at: crawl_request.created_at,
             attempt_number: 0,
-        }
+            crawl_duration_ms: crawl_request.crawl_duration_ms,
+        })
     }
 }
 
@@ -6496,16 +6604,282 @@ impl From<CrawlRequest> for CrawlRequestPG {
             status: crawl_request.status.to_string(),
             next_crawl_at: crawl_request.next_crawl_at,
             interval: crawl_request.interval.as_secs() as i32,
+            github_token: crawl_request.crawl_options.github_token(),
             crawl_options: serde_json::to_value(crawl_request.crawl_options).unwrap(),
             scrape_id: crawl_request.scrape_id,
             dataset_id: crawl_request.dataset_id,
             created_at: crawl_request.created_at,
+            crawl_duration_ms: crawl_request.crawl_duration_ms,
         }
     }
 }
 
-/// Options for setting up the crawl which will populate the dataset.
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone)]
+#[diesel(table_name = crawl_indexed_pages)]
+pub struct CrawlIndexedPage {
+    pub id: uuid::Uuid,
+    pub crawl_id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub url: String,
+    pub content_hash: String,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+impl CrawlIndexedPage {
+    pub fn from_details(
+        crawl_id: uuid::Uuid,
+        dataset_id: uuid::Uuid,
+        url: String,
+        content_hash: String,
+    ) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4(),
+            crawl_id,
+            dataset_id,
+            url,
+            content_hash,
+            created_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone)]
+#[diesel(table_name = crawled_url_index)]
+pub struct CrawledUrlIndex {
+    pub id: uuid::Uuid,
+    pub organization_id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub url_hash: String,
+    pub url: String,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+impl CrawledUrlIndex {
+    pub fn from_details(
+        organization_id: uuid::Uuid,
+        dataset_id: uuid::Uuid,
+        url_hash: String,
+        url: String,
+    ) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4(),
+            organization_id,
+            dataset_id,
+            url_hash,
+            url,
+            created_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+/// Kind of change recorded in the audit log of a crawl request.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CrawlAuditAction {
+    Created,
+    StatusChanged,
+    SettingsUpdated,
+    UrlMigrated,
+    ScrapeIdChanged,
+    Deleted,
+}
+
+impl CrawlAuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CrawlAuditAction::Created => "created",
+            CrawlAuditAction::StatusChanged => "status_changed",
+            CrawlAuditAction::SettingsUpdated => "settings_updated",
+            CrawlAuditAction::UrlMigrated => "url_migrated",
+            CrawlAuditAction::ScrapeIdChanged => "scrape_id_changed",
+            CrawlAuditAction::Deleted => "deleted",
+        }
+    }
+}
+
+/// A change made to a crawl request. Events are kept after the crawl request is deleted.
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone, ToSchema)]
+#[schema(example=json!({
+    "id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "crawl_request_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "dataset_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "scrape_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "user_id": "e3e3e3e3-e3e3-e3e3-e3e3-e3e3e3e3e3e3",
+    "action": "status_changed",
+    "old_value": "pending",
+    "new_value": "completed",
+    "occurred_at": "2021-01-01 00:00:00.000",
+}))]
+#[diesel(table_name = audit_crawl_events)]
+pub struct AuditCrawlEvent {
+    pub id: uuid::Uuid,
+    pub crawl_request_id: uuid::Uuid,
+    /// Dataset of the crawl request, kept so the log can be authorized once the request is deleted
+    pub dataset_id: uuid::Uuid,
+    /// Scrape id of the crawl request when the change was made
+    pub scrape_id: uuid::Uuid,
+    /// Id of the user who made the change, null for changes made by the crawl worker
+    pub user_id: Option<uuid::Uuid>,
+    /// One of `created`, `status_changed`, `settings_updated`, `url_migrated`, `scrape_id_changed` or `deleted`
+    pub action: String,
+    /// Status, crawl options, url or scrape id before the change
+    pub old_value: Option<serde_json::Value>,
+    /// Status, crawl options, url or scrape id after the change
+    pub new_value: Option<serde_json::Value>,
+    pub occurred_at: chrono::NaiveDateTime,
+}
+
+impl AuditCrawlEvent {
+    pub fn from_details(
+        crawl_request_id: uuid::Uuid,
+        dataset_id: uuid::Uuid,
+        scrape_id: uuid::Uuid,
+        user_id: Option<uuid::Uuid>,
+        action: CrawlAuditAction,
+        old_value: Option<serde_json::Value>,
+        new_value: Option<serde_json::Value>,
+    ) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4(),
+            crawl_request_id,
+            dataset_id,
+            scrape_id,
+            user_id,
+            action: action.as_str().to_string(),
+            old_value,
+            new_value,
+            occurred_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone)]
+#[diesel(table_name = crawl_errors)]
+pub struct CrawlError {
+    pub id: uuid::Uuid,
+    pub crawl_id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub url: String,
+    pub error_type: String,
+    pub message: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+impl CrawlError {
+    pub fn from_details(
+        crawl_id: uuid::Uuid,
+        dataset_id: uuid::Uuid,
+        url: String,
+        error_type: &str,
+        message: Option<String>,
+    ) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4(),
+            crawl_id,
+            dataset_id,
+            url,
+            error_type: error_type.to_string(),
+            message,
+            created_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+/// The URLs which changed between two runs of a crawl for the same dataset.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, ToSchema)]
+pub struct CrawlDiff {
+    /// URLs indexed by the new crawl which were not indexed by the old crawl
+    pub added: Vec<String>,
+    /// URLs indexed by the old crawl which are no longer present in the new crawl
+    pub removed: Vec<String>,
+    /// URLs present in both crawls whose content changed
+    pub modified: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone)]
+#[diesel(table_name = crawl_diffs)]
+pub struct CrawlDiffPG {
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub old_crawl_id: uuid::Uuid,
+    pub new_crawl_id: uuid::Uuid,
+    pub diff: serde_json::Value,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+/// A previous version of a dataset's crawl options, recorded whenever the crawl options are updated.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct CrawlOptionsSnapshot {
+    /// Timestamp at which the crawl options were changed
+    pub changed_at: chrono::NaiveDateTime,
+    /// Id of the user who changed the crawl options
+    pub changed_by: Option<uuid::Uuid>,
+    /// The crawl options as they were before the change
+    #[serde(deserialize_with = "deserialize_stored_crawl_options")]
+    pub previous_options: CrawlOptions,
+}
+
+/// Counts and processing times of the crawls of the instance.
 #[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[schema(example=json!({
+    "total_crawls": 250,
+    "completed_crawls": 231,
+    "failed_crawls": 12,
+    "p50_duration_ms": 94000,
+    "p95_duration_ms": 612000,
+}))]
+pub struct CrawlSummary {
+    pub total_crawls: i64,
+    pub completed_crawls: i64,
+    pub failed_crawls: i64,
+    /// Median processing time of the crawls with a recorded duration
+    pub p50_duration_ms: Option<i64>,
+    /// 95th percentile processing time of the crawls with a recorded duration
+    pub p95_duration_ms: Option<i64>,
+}
+
+/// Whether crawl scheduling is paused for the whole instance, see
+/// `crawl_operator::set_crawl_maintenance_mode`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+#[schema(example=json!({
+    "enabled": true,
+    "reason": "Database migration",
+    "enabled_at": "2021-01-01 00:00:00.000",
+}))]
+pub struct CrawlMaintenanceMode {
+    /// While enabled, the crawl cron job does not rerun any crawl
+    pub enabled: bool,
+    pub reason: Option<String>,
+    pub enabled_at: Option<NaiveDateTime>,
+}
+
+/// Outcome of moving a crawl to a new base url, see `crawl_operator::migrate_crawl_to_new_url`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MigrationResult {
+    /// Number of chunks of the dataset whose urls were rewritten to the new base url
+    pub updated_chunks: u64,
+    /// Scrape id of the crawl started at the new base url
+    pub new_scrape_id: uuid::Uuid,
+}
+
+/// Predicted size and cost of a crawl, based on the urls the crawler can discover for the site.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+#[schema(example=json!({
+    "estimated_pages": 120,
+    "estimated_credits": 120,
+    "estimated_duration_minutes": 6,
+}))]
+pub struct CrawlCostEstimate {
+    /// Number of pages the crawl is expected to index, capped at the crawl limit
+    pub estimated_pages: u32,
+    /// Number of crawler credits the crawl is expected to use
+    pub estimated_credits: u32,
+    /// Expected time for the crawler to finish, in minutes
+    pub estimated_duration_minutes: u32,
+}
+
+/// Options for setting up the crawl which will populate the dataset.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
 #[schema(example=json!({
     "site_url": "https://example.com",
     "interval": "daily",
@@ -6525,6 +6899,8 @@ pub struct CrawlOptions {
     pub limit: Option<i32>,
     /// URL Patterns to exclude from the crawl
     pub exclude_paths: Option<Vec<String>>,
+    /// Regex patterns for URLs to skip. Any URL matching one of these patterns will not be crawled or indexed.
+    pub skip_urls_matching: Option<Vec<String>>,
     /// URL Patterns to include in the crawl
     pub include_paths: Option<Vec<String>>,
     /// How many levels deep to crawl, defaults to 10
@@ -6541,9 +6917,200 @@ pub struct CrawlOptions {
     pub ignore_sitemap: Option<bool>,
     /// Options for including an openapi spec in the crawl
     pub scrape_options: Option<ScrapeOptions>,
+    /// Prompt for Firecrawl's LLM extraction. When set, the extracted data for each page is stored in the chunk metadata under `ai_extract`.
+    pub ai_extract_prompt: Option<String>,
+    /// JSON schema the LLM extraction output should conform to. Can be used with or without `ai_extract_prompt`.
+    pub ai_extract_schema: Option<serde_json::Value>,
+    /// Index the caption of each `<figure>` element as its own chunk tagged `figure`, with the image src stored in the chunk metadata. Defaults to false since this can produce many small chunks.
+    pub include_figures: Option<bool>,
+    /// Actions to run after the crawl finishes and its chunks have been queued for ingestion.
+    pub on_completion_action: Option<Vec<CompletionAction>>,
+    /// Detect the language of each chunk and group the chunks of a page by language. Each chunk is tagged with the ISO 639-1 code of its detected language, the same format as auto_detect_language. Defaults to false.
+    pub split_by_language: Option<bool>,
+    /// Prefix prepended to the tags generated from the url path segments of each page, e.g. `crawl:` gives tags like `crawl:blog`. Cannot contain spaces and must be at most 32 characters.
+    pub tag_prefix: Option<String>,
+    /// Page metadata fields, as returned by the crawler (e.g. `ogSiteName`, `publishedTime`), to add to the metadata of each chunk. Unknown field names are ignored.
+    pub metadata_fields_to_index: Option<Vec<String>>,
+    /// Store the deduplicated external links of each page, up to 50, as `external_links` in the metadata of its chunks. Defaults to false.
+    pub extract_links_to_metadata: Option<bool>,
+    /// Also split pages into chunks at `<hr>` elements, for pages which separate sections with horizontal rules rather than headings. Defaults to false.
+    pub split_on_horizontal_rule: Option<bool>,
+    /// Regex patterns for urls which should never be indexed. Unlike exclude_paths these are not sent to the crawler, pages whose url matches any of them are dropped after they are crawled.
+    pub exclude_url_patterns: Option<Vec<String>>,
+    /// Skip pages whose url was already crawled into another dataset of the same organization. Skipped urls can be listed with the deduplicated-urls route of the crawl. Defaults to false.
+    pub deduplicate_across_datasets: Option<bool>,
+    /// Skip pages which look like they are behind a paywall instead of indexing their teaser. Defaults to false, paywalled pages are still indexed with `is_paywalled` set in their metadata.
+    pub skip_paywalled: Option<bool>,
+    /// How pages are split into chunks, defaults to heading_based.
+    pub chunk_strategy: Option<ChunkStrategy>,
+    /// Url schemes which may be crawled, defaults to `["https", "http"]`. Set to `["https"]` to never index pages served over plain http.
+    pub allowed_schemes: Option<Vec<String>>,
+    /// CSS selector, e.g. `section[id]`, for elements which should each become one chunk headed by their id. The rest of the page is chunked with the chunk strategy.
+    pub chunk_by_element_id: Option<String>,
+    /// Collapse repeated spaces and blank lines in the text of each chunk before it is embedded. Defaults to true.
+    pub normalize_whitespace: Option<bool>,
+    /// Actions to run on each page, in order, before it is captured. Use these to reveal content which is only shown after interacting with the page, e.g. infinite scroll or tabs.
+    pub page_js_actions: Option<Vec<JsAction>>,
+    /// JSON schema of structured data to extract from each page. When set, the extracted JSON of each page is indexed as an additional chunk with `chunk_type: "structured"` in its metadata and the JSON stored under `structured_output`, so it can be filtered on. Used as the schema of Firecrawl's LLM extraction when `ai_extract_schema` is not set.
+    pub structured_output_schema: Option<serde_json::Value>,
+    /// Generate a summary and tags for each chunk with an OpenAI model before it is indexed. The summary is stored as `ai_summary` in the chunk metadata and the tags are added to the tag set of the chunk. Uses the LLM_BASE_URL and LLM_API_KEY of the dataset, defaulting to OpenAI.
+    pub enrich_with_openai: Option<OpenAIEnrichConfig>,
+    /// Index the fallback content of `<noscript>` elements, such as "Please enable JavaScript" messages. Defaults to false, `<noscript>` elements are removed before pages are chunked.
+    pub include_noscript_content: Option<bool>,
+    /// Additional urls to start the crawl from, for sections of the site which are not linked from `site_url`, e.g. a changelog or API reference. Once the crawl of `site_url` has finished, each seed url is crawled as a separate Firecrawl crawl with the same options, including `limit`, and pages found by more than one crawl are indexed once. `site_url` is still the url the crawl is identified by.
+    pub seed_urls: Option<Vec<String>>,
+    /// Html tags kept when crawled pages are sanitized before they are chunked. Other tags are removed but their text is kept, except for `script`, `style`, `iframe`, `object` and similar tags which are always removed with their content. Defaults to common formatting and structural tags, including headings, `section` and MathML.
+    pub sanitize_allowed_tags: Option<Vec<String>>,
+    /// Html attributes kept on every tag when crawled pages are sanitized before they are chunked. Defaults to `id`, `class`, `lang` and `title`, plus the MathML attributes used to convert equations to LaTeX. Links keep their `href` and images their `src` regardless.
+    pub sanitize_allowed_attributes: Option<Vec<String>>,
+    /// Share of failed pages, between 0.0 and 1.0, above which the completion actions of the crawl are sent an alert. The payload then includes `"alert": "error_threshold_exceeded"` and the `error_rate` of the crawl. Not checked by default.
+    pub notify_on_error_threshold: Option<f32>,
+    /// Add the alt text and captions of the images of crawled pdfs to the chunk text as `[Image: description]`, and store the number of images found on each page as `pdf_image_counts` in the chunk metadata. Only tagged pdfs describe their images. Defaults to false.
+    pub include_pdf_images: Option<bool>,
+    /// How the heading of a chunk is derived when the chunk has no heading of its own. Defaults to first_heading, which leaves such chunks without a heading.
+    pub title_extraction_strategy: Option<TitleExtractionStrategy>,
+    /// Also index each question and answer of the FAQ sections of a page, from `<details>` elements and from headings ending with `?` followed by a paragraph, as its own chunk with `chunk_type: "faq"` in its metadata. FAQ chunks are indexed with a higher weight. Defaults to false.
+    pub extract_faq: Option<bool>,
+    /// Add the names of the breadcrumbs of each page, which are always stored as `breadcrumbs` in the metadata of its chunks, to the tag set of its chunks. The tag_prefix is applied to them as well. Defaults to false.
+    pub breadcrumbs_as_tags: Option<bool>,
+    /// Merge chunks which are near-duplicates of a chunk indexed earlier in the same crawl, such as the same article with minor edits, into that chunk. The pages of the merged chunks are stored as `duplicate_urls` in its metadata and it is added to their groups. Not checked by default.
+    pub smart_dedup_strategy: Option<SmartDedupStrategy>,
+    /// Also index the documents on other sites linked from the crawled pages, such as specification pdfs, whose mime type is one of linked_document_types. Each linked document is scraped by its own single page job, separately from the crawl, and is only indexed when the content type of its response matches. Only links of the crawled pages are followed, not the links of the linked documents, and at most 100 linked documents are indexed per crawl. Defaults to false.
+    pub index_linked_documents: Option<bool>,
+    /// Mime types of the linked documents indexed when index_linked_documents is set, defaults to `["application/pdf", "text/markdown"]`.
+    pub linked_document_types: Option<Vec<String>>,
+    /// Also index a glossary of each page, built from the terms defined with `<abbr title="...">` and `<dfn>` elements, as one chunk with `chunk_type: "glossary"` and the terms and their definitions as `glossary` in its metadata. Defaults to false.
+    pub extract_glossary: Option<bool>,
+    /// Further split the chunks which contain several elements with an `id` attribute, such as anchored sections, into one chunk per element headed by its id. Defaults to false.
+    pub chunk_by_dom_id: Option<bool>,
+    /// Also store the sanitized html of each chunk, with its headings, formatting and links, as `chunk_html` in the chunk metadata for clients which render it. At most 16 KB of html is stored per chunk. Defaults to false.
+    pub preserve_html_in_chunk: Option<bool>,
+    /// Add words of the neighbouring chunks of each page to every chunk, so that each chunk is embedded with some of its surrounding context. No overlap is added by default.
+    pub chunk_overlap_strategy: Option<OverlapStrategy>,
+    /// Detect the language of pages which do not declare one from the start of their text, and store its ISO 639-1 code as the page's `language`. It is added to the chunk metadata when `language` is in metadata_fields_to_index. Defaults to false.
+    pub auto_detect_language: Option<bool>,
+    /// Version of the crawl options format, used to migrate crawl options stored by older versions. Always set to the current version when crawl options are saved.
+    #[serde(default = "current_crawl_options_version")]
+    pub version: u32,
+}
+
+/// Current version of the [`CrawlOptions`] format. Bump it and add a step to
+/// [`CrawlOptionsMigrator::migrate`] when a change to `CrawlOptions` needs stored crawl options to
+/// be transformed.
+pub const CRAWL_OPTIONS_VERSION: u32 = 1;
+
+fn current_crawl_options_version() -> u32 {
+    CRAWL_OPTIONS_VERSION
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone, PartialEq)]
+/// Configuration for generating a summary and tags for crawled chunks with an OpenAI model
+pub struct OpenAIEnrichConfig {
+    /// Model to use, e.g. `gpt-4o-mini`
+    pub model: String,
+    /// Prompt used to summarize each chunk. The text of the chunk is sent after the prompt.
+    pub summary_prompt: Option<String>,
+    /// Prompt used to generate tags for each chunk, the model should reply with a comma separated list. The text of the chunk is sent after the prompt.
+    pub tag_prompt: Option<String>,
+    /// Maximum number of tokens to generate for each summary and list of tags
+    pub max_tokens: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone, PartialEq)]
+/// Browser action to run on a page before it is captured
+pub struct JsAction {
+    #[serde(rename = "type")]
+    pub type_: ActionType,
+    /// CSS selector of the element to act on, required for `click` and `wait_for_selector`
+    pub selector: Option<String>,
+    /// Time to wait after the action, in milliseconds
+    pub delay_ms: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionType {
+    /// Click the element matching the selector
+    Click,
+    /// Scroll to the bottom of the page
+    ScrollToBottom,
+    /// Wait until an element matching the selector is present
+    WaitForSelector,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+/// Strategy for splitting crawled pages into chunks
+pub enum ChunkStrategy {
+    /// Start a new chunk at every heading
+    HeadingBased,
+    /// Split the text of the page into chunks of a fixed number of words
+    FixedSize {
+        /// Number of words per chunk, must be greater than 0
+        words: usize,
+    },
+    /// Split the text of the page into chunks of whole sentences
+    SentenceBased {
+        /// Maximum number of sentences per chunk, must be greater than 0
+        max_sentences: usize,
+    },
 }
 
-#[derive(Serialize, Deserialize, Debug, ToSchema, Clone)]
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+/// How the heading of a chunk without a heading of its own is derived
+pub enum TitleExtractionStrategy {
+    /// Only use the headings of the chunk, leaving the heading empty
+    FirstHeading,
+    /// Use the title of the page
+    MetadataTitle,
+    /// Use the path of the page's url, or its host for the root page
+    UrlPath,
+    /// Use the start of the first sentence of the chunk
+    FirstSentence,
+    /// Use the text of the first element matching the CSS selector in the chunk, or else in the page
+    Custom(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone, PartialEq)]
+/// Near-duplicate detection for the chunks of a crawl
+pub struct SmartDedupStrategy {
+    /// Similarity between 0.0 and 1.0 from which a chunk is a near-duplicate of a chunk seen before. For sim_hash this is the share of identical bits of the two hashes, for min_hash the estimated Jaccard similarity of their word shingles.
+    pub similarity_threshold: f32,
+    pub method: SimilarityMethod,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+/// How the similarity of two chunks is estimated
+pub enum SimilarityMethod {
+    /// Compare 64 bit SimHashes of the words of the chunks
+    SimHash,
+    /// Compare MinHash signatures of the 3 word shingles of the chunks
+    MinHash,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone, PartialEq)]
+/// Overlap between the neighbouring chunks of a page
+pub struct OverlapStrategy {
+    pub direction: OverlapDirection,
+    /// Number of words taken from each neighbouring chunk, must be greater than 0
+    pub words: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+/// Which neighbouring chunks the overlap of a chunk is taken from
+pub enum OverlapDirection {
+    /// Start each chunk with the last words of the previous chunk
+    Forward,
+    /// End each chunk with the first words of the next chunk
+    Backward,
+    /// Both start each chunk with the end of the previous chunk and end it with the start of the next chunk
+    Bidirectional,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone, PartialEq)]
 #[serde(tag = "type")]
 /// Options for including an openapi spec or shopify settigns
 pub enum ScrapeOptions {
@@ -6553,9 +7120,38 @@ pub enum ScrapeOptions {
     /// Shopify Scrape Options
     #[serde(rename = "shopify")]
     Shopify(CrawlShopifyOptions),
+    /// GitHub Scrape Options
+    #[serde(rename = "github")]
+    GitHub(CrawlGitHubOptions),
 }
 
-#[derive(Serialize, Deserialize, Debug, ToSchema, Clone)]
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+/// Action to run after a crawl finishes
+pub enum CompletionAction {
+    /// POST a JSON summary of the completed crawl to a url
+    Webhook(WebhookConfig),
+    /// Send a message summarizing the completed crawl to a Slack incoming webhook
+    SlackNotification(SlackConfig),
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone, PartialEq)]
+/// Configuration for a webhook called after a crawl finishes
+pub struct WebhookConfig {
+    /// The url to POST the crawl summary to
+    pub url: String,
+    /// Additional headers to send with the request, e.g. for authentication
+    pub headers: Option<HashMap<String, String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone, PartialEq)]
+/// Configuration for a Slack notification sent after a crawl finishes
+pub struct SlackConfig {
+    /// The Slack incoming webhook url to post the message to
+    pub webhook_url: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone, PartialEq)]
 #[schema(title = "CrawlShopifyOptions")]
 /// Options for Crawling Shopify
 pub struct CrawlShopifyOptions {
@@ -6563,7 +7159,38 @@ pub struct CrawlShopifyOptions {
     pub group_variants: Option<bool>,
 }
 
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone, PartialEq)]
+#[schema(title = "CrawlGitHubOptions")]
+/// Options for crawling the markdown files of a GitHub repository instead of a site
+pub struct CrawlGitHubOptions {
+    /// The repository to crawl, in the form `owner/name`
+    pub repo: String,
+    /// The branch to read the markdown files from
+    pub branch: String,
+    /// Only crawl markdown files under this directory of the repository, e.g. `docs`. Crawls the whole repository if not specified.
+    pub path_prefix: Option<String>,
+    /// GitHub token used to authenticate requests, required for private repositories and recommended to avoid the low rate limit of unauthenticated requests. The token is stored apart from the crawl options and is never returned.
+    #[serde(default, skip_serializing)]
+    pub token: Option<String>,
+}
+
 impl CrawlOptions {
+    /// GitHub token of the crawl, if it crawls a GitHub repository
+    pub fn github_token(&self) -> Option<String> {
+        match &self.scrape_options {
+            Some(ScrapeOptions::GitHub(github_options)) => github_options.token.clone(),
+            _ => None,
+        }
+    }
+
+    /// Puts back the GitHub token, which is stored apart from the serialized crawl options
+    pub fn with_github_token(mut self, token: Option<String>) -> Self {
+        if let Some(ScrapeOptions::GitHub(ref mut github_options)) = self.scrape_options {
+            github_options.token = github_options.token.take().or(token);
+        }
+        self
+    }
+
     pub fn merge(&self, other: CrawlOptions) -> CrawlOptions {
         CrawlOptions {
             site_url: self.site_url.clone().or(other.site_url.clone()),
@@ -6573,15 +7200,172 @@ impl CrawlOptions {
             exclude_tags: self.exclude_tags.clone().or(other.exclude_tags.clone()),
             include_paths: self.include_paths.clone().or(other.include_paths.clone()),
             exclude_paths: self.exclude_paths.clone().or(other.exclude_paths.clone()),
+            skip_urls_matching: self
+                .skip_urls_matching
+                .clone()
+                .or(other.skip_urls_matching.clone()),
             max_depth: self.max_depth.or(other.max_depth),
             ignore_sitemap: self.ignore_sitemap.or(other.ignore_sitemap),
             boost_titles: self.boost_titles.or(other.boost_titles),
-            scrape_options: self.scrape_options.clone(),
+            scrape_options: match (self.scrape_options.clone(), &other.scrape_options) {
+                // The token is never returned, so options read back and sent again keep it
+                (
+                    Some(ScrapeOptions::GitHub(github_options)),
+                    Some(ScrapeOptions::GitHub(other_github_options)),
+                ) if github_options.repo == other_github_options.repo => {
+                    Some(ScrapeOptions::GitHub(CrawlGitHubOptions {
+                        token: github_options.token.or(other_github_options.token.clone()),
+                        ..github_options
+                    }))
+                }
+                (scrape_options, _) => scrape_options,
+            },
             allow_external_links: self.allow_external_links.or(other.allow_external_links),
+            ai_extract_prompt: self
+                .ai_extract_prompt
+                .clone()
+                .or(other.ai_extract_prompt.clone()),
+            ai_extract_schema: self
+                .ai_extract_schema
+                .clone()
+                .or(other.ai_extract_schema.clone()),
+            include_figures: self.include_figures.or(other.include_figures),
+            on_completion_action: self
+                .on_completion_action
+                .clone()
+                .or(other.on_completion_action.clone()),
+            split_by_language: self.split_by_language.or(other.split_by_language),
+            tag_prefix: self.tag_prefix.clone().or(other.tag_prefix.clone()),
+            metadata_fields_to_index: self
+                .metadata_fields_to_index
+                .clone()
+                .or(other.metadata_fields_to_index.clone()),
+            extract_links_to_metadata: self
+                .extract_links_to_metadata
+                .or(other.extract_links_to_metadata),
+            split_on_horizontal_rule: self
+                .split_on_horizontal_rule
+                .or(other.split_on_horizontal_rule),
+            exclude_url_patterns: self
+                .exclude_url_patterns
+                .clone()
+                .or(other.exclude_url_patterns.clone()),
+            deduplicate_across_datasets: self
+                .deduplicate_across_datasets
+                .or(other.deduplicate_across_datasets),
+            skip_paywalled: self.skip_paywalled.or(other.skip_paywalled),
+            chunk_strategy: self.chunk_strategy.clone().or(other.chunk_strategy.clone()),
+            allowed_schemes: self
+                .allowed_schemes
+                .clone()
+                .or(other.allowed_schemes.clone()),
+            chunk_by_element_id: self
+                .chunk_by_element_id
+                .clone()
+                .or(other.chunk_by_element_id.clone()),
+            normalize_whitespace: self.normalize_whitespace.or(other.normalize_whitespace),
+            page_js_actions: self
+                .page_js_actions
+                .clone()
+                .or(other.page_js_actions.clone()),
+            structured_output_schema: self
+                .structured_output_schema
+                .clone()
+                .or(other.structured_output_schema.clone()),
+            enrich_with_openai: self
+                .enrich_with_openai
+                .clone()
+                .or(other.enrich_with_openai.clone()),
+            include_noscript_content: self
+                .include_noscript_content
+                .or(other.include_noscript_content),
+            seed_urls: self.seed_urls.clone().or(other.seed_urls.clone()),
+            sanitize_allowed_tags: self
+                .sanitize_allowed_tags
+                .clone()
+                .or(other.sanitize_allowed_tags.clone()),
+            sanitize_allowed_attributes: self
+                .sanitize_allowed_attributes
+                .clone()
+                .or(other.sanitize_allowed_attributes.clone()),
+            notify_on_error_threshold: self
+                .notify_on_error_threshold
+                .or(other.notify_on_error_threshold),
+            include_pdf_images: self.include_pdf_images.or(other.include_pdf_images),
+            title_extraction_strategy: self
+                .title_extraction_strategy
+                .clone()
+                .or(other.title_extraction_strategy.clone()),
+            extract_faq: self.extract_faq.or(other.extract_faq),
+            breadcrumbs_as_tags: self.breadcrumbs_as_tags.or(other.breadcrumbs_as_tags),
+            smart_dedup_strategy: self
+                .smart_dedup_strategy
+                .clone()
+                .or(other.smart_dedup_strategy.clone()),
+            index_linked_documents: self.index_linked_documents.or(other.index_linked_documents),
+            linked_document_types: self
+                .linked_document_types
+                .clone()
+                .or(other.linked_document_types.clone()),
+            extract_glossary: self.extract_glossary.or(other.extract_glossary),
+            chunk_by_dom_id: self.chunk_by_dom_id.or(other.chunk_by_dom_id),
+            preserve_html_in_chunk: self.preserve_html_in_chunk.or(other.preserve_html_in_chunk),
+            chunk_overlap_strategy: self
+                .chunk_overlap_strategy
+                .clone()
+                .or(other.chunk_overlap_strategy.clone()),
+            auto_detect_language: self.auto_detect_language.or(other.auto_detect_language),
+            version: CRAWL_OPTIONS_VERSION,
+        }
+    }
+}
+
+/// Upgrades crawl options stored in the `crawl_options` column to the current [`CrawlOptions`]
+/// format.
+pub struct CrawlOptionsMigrator;
+
+impl CrawlOptionsMigrator {
+    /// Applies the migrations between the version of the stored crawl options and
+    /// [`CRAWL_OPTIONS_VERSION`] before deserializing them. Crawl options stored before they were
+    /// versioned have no `version` and are treated as version 0.
+    pub fn migrate(mut value: serde_json::Value) -> Result<CrawlOptions, ServiceError> {
+        let options = value.as_object_mut().ok_or_else(|| {
+            ServiceError::InternalServerError("Stored crawl options are not an object".to_string())
+        })?;
+
+        let version = options
+            .get("version")
+            .and_then(|version| version.as_u64())
+            .unwrap_or(0) as u32;
+        if version > CRAWL_OPTIONS_VERSION {
+            return Err(ServiceError::InternalServerError(format!(
+                "Stored crawl options have version {} which is newer than the supported version {}",
+                version, CRAWL_OPTIONS_VERSION
+            )));
         }
+
+        if version < 1 {
+            // Version 1 only added the version field itself
+            options.insert("version".to_string(), serde_json::json!(1));
+        }
+
+        serde_json::from_value(value).map_err(|e| {
+            log::error!("Failed to deserialize stored crawl options: {:?}", e);
+            ServiceError::InternalServerError(
+                "Failed to deserialize stored crawl options".to_string(),
+            )
+        })
     }
 }
 
+fn deserialize_stored_crawl_options<'de, D>(deserializer: D) -> Result<CrawlOptions, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = serde_json::Value::deserialize(deserializer)?;
+    CrawlOptionsMigrator::migrate(value).map_err(serde::de::Error::custom)
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct FirecrawlCrawlRequest {
@@ -6613,13 +7397,105 @@ pub struct FirecrawlScraperOptions {
     pub wait_for: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub formats: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extract: Option<FirecrawlExtractOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actions: Option<Vec<FirecrawlAction>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FirecrawlAction {
+    Wait {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        milliseconds: Option<u32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        selector: Option<String>,
+    },
+    Click {
+        selector: String,
+    },
+    #[serde(rename = "executeJavascript")]
+    ExecuteJavascript {
+        script: String,
+    },
+}
+
+impl FirecrawlAction {
+    fn from_js_action(js_action: JsAction) -> Vec<Self> {
+        let action = match js_action.type_ {
+            ActionType::Click => FirecrawlAction::Click {
+                selector: js_action.selector.unwrap_or_default(),
+            },
+            // Firecrawl's scroll action only moves by one screen
+            ActionType::ScrollToBottom => FirecrawlAction::ExecuteJavascript {
+                script: "window.scrollTo(0, document.body.scrollHeight)".to_string(),
+            },
+            ActionType::WaitForSelector => FirecrawlAction::Wait {
+                milliseconds: None,
+                selector: js_action.selector,
+            },
+        };
+
+        match js_action.delay_ms {
+            Some(delay_ms) => vec![
+                action,
+                FirecrawlAction::Wait {
+                    milliseconds: Some(delay_ms),
+                    selector: None,
+                },
+            ],
+            None => vec![action],
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FirecrawlExtractOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema: Option<serde_json::Value>,
 }
 
 impl From<CrawlOptions> for FirecrawlCrawlRequest {
     fn from(crawl_options: CrawlOptions) -> Self {
+        let exclude_paths = match (
+            crawl_options.exclude_paths,
+            crawl_options.skip_urls_matching,
+        ) {
+            (Some(exclude_paths), Some(skip_urls_matching)) => {
+                Some([exclude_paths, skip_urls_matching].concat())
+            }
+            (exclude_paths, skip_urls_matching) => exclude_paths.or(skip_urls_matching),
+        };
+
+        let extract_schema = crawl_options
+            .ai_extract_schema
+            .or(crawl_options.structured_output_schema);
+        let extract = if crawl_options.ai_extract_prompt.is_some() || extract_schema.is_some() {
+            Some(FirecrawlExtractOptions {
+                prompt: crawl_options.ai_extract_prompt,
+                schema: extract_schema,
+            })
+        } else {
+            None
+        };
+
+        let mut formats = vec!["html".to_string(), "rawHtml".to_string()];
+        if extract.is_some() {
+            formats.push("extract".to_string());
+        }
+        if crawl_options.extract_links_to_metadata.unwrap_or(false)
+            || crawl_options.index_linked_documents.unwrap_or(false)
+        {
+            formats.push("links".to_string());
+        }
+
         Self {
             url: crawl_options.site_url,
-            exclude_paths: crawl_options.exclude_paths,
+            exclude_paths,
             include_paths: crawl_options.include_paths,
             max_depth: Some(crawl_options.max_depth.unwrap_or(10)),
             ignore_sitemap: crawl_options.ignore_sitemap,
@@ -6628,9 +7504,207 @@ impl From<CrawlOptions> for FirecrawlCrawlRequest {
             scrape_options: Some(FirecrawlScraperOptions {
                 include_tags: crawl_options.include_tags,
                 exclude_tags: crawl_options.exclude_tags,
-                formats: Some(vec!["html".to_string(), "rawHtml".to_string()]),
+                formats: Some(formats),
                 wait_for: Some(1000),
+                extract,
+                actions: crawl_options.page_js_actions.map(|js_actions| {
+                    js_actions
+                        .into_iter()
+                        .flat_map(FirecrawlAction::from_js_action)
+                        .collect()
+                }),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Every field is set so that a field missing from `merge` or a serde attribute which drops or
+    /// renames a value on the way back fails the test. Adding a field to `CrawlOptions` requires
+    /// setting it here.
+    fn fully_populated_crawl_options() -> CrawlOptions {
+        CrawlOptions {
+            site_url: Some("https://roundtrip.example.com".to_string()),
+            interval: Some(CrawlInterval::Weekly),
+            limit: Some(250),
+            exclude_paths: Some(vec!["/blog/*".to_string()]),
+            skip_urls_matching: Some(vec![".*/print/.*".to_string()]),
+            include_paths: Some(vec!["/docs/*".to_string()]),
+            max_depth: Some(4),
+            include_tags: Some(vec!["main".to_string()]),
+            exclude_tags: Some(vec!["#footer".to_string()]),
+            boost_titles: Some(false),
+            allow_external_links: Some(true),
+            ignore_sitemap: Some(true),
+            scrape_options: Some(ScrapeOptions::GitHub(CrawlGitHubOptions {
+                repo: "example/roundtrip".to_string(),
+                branch: "main".to_string(),
+                path_prefix: Some("docs".to_string()),
+                token: Some("token".to_string()),
+            })),
+            ai_extract_prompt: Some("Extract the price".to_string()),
+            ai_extract_schema: Some(serde_json::json!({"type": "object"})),
+            include_figures: Some(true),
+            on_completion_action: Some(vec![
+                CompletionAction::Webhook(WebhookConfig {
+                    url: "https://hooks.example.com/crawl".to_string(),
+                    headers: Some(HashMap::from([(
+                        "Authorization".to_string(),
+                        "Bearer secret".to_string(),
+                    )])),
+                }),
+                CompletionAction::SlackNotification(SlackConfig {
+                    webhook_url: "https://hooks.slack.com/services/roundtrip".to_string(),
+                }),
+            ]),
+            split_by_language: Some(true),
+            tag_prefix: Some("docs:".to_string()),
+            metadata_fields_to_index: Some(vec!["author".to_string()]),
+            extract_links_to_metadata: Some(true),
+            split_on_horizontal_rule: Some(true),
+            exclude_url_patterns: Some(vec![r"\?page=\d+".to_string()]),
+            deduplicate_across_datasets: Some(true),
+            skip_paywalled: Some(true),
+            chunk_strategy: Some(ChunkStrategy::SentenceBased { max_sentences: 5 }),
+            allowed_schemes: Some(vec!["https".to_string()]),
+            chunk_by_element_id: Some("content".to_string()),
+            normalize_whitespace: Some(true),
+            page_js_actions: Some(vec![JsAction {
+                type_: ActionType::WaitForSelector,
+                selector: Some("#loaded".to_string()),
+                delay_ms: Some(500),
+            }]),
+            structured_output_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {"price": {"type": "number"}},
+            })),
+            enrich_with_openai: Some(OpenAIEnrichConfig {
+                model: "gpt-4o-mini".to_string(),
+                summary_prompt: Some("Summarize".to_string()),
+                tag_prompt: Some("Tag".to_string()),
+                max_tokens: 64,
+            }),
+            include_noscript_content: Some(true),
+            seed_urls: Some(vec!["https://roundtrip.example.com/changelog".to_string()]),
+            sanitize_allowed_tags: Some(vec!["mark".to_string()]),
+            sanitize_allowed_attributes: Some(vec!["data-id".to_string()]),
+            notify_on_error_threshold: Some(0.25),
+            include_pdf_images: Some(true),
+            title_extraction_strategy: Some(TitleExtractionStrategy::Custom(
+                ".page-title".to_string(),
+            )),
+            extract_faq: Some(true),
+            breadcrumbs_as_tags: Some(true),
+            smart_dedup_strategy: Some(SmartDedupStrategy {
+                similarity_threshold: 0.9,
+                method: SimilarityMethod::MinHash,
             }),
+            index_linked_documents: Some(true),
+            linked_document_types: Some(vec!["application/pdf".to_string()]),
+            extract_glossary: Some(true),
+            chunk_by_dom_id: Some(true),
+            preserve_html_in_chunk: Some(true),
+            chunk_overlap_strategy: Some(OverlapStrategy {
+                direction: OverlapDirection::Bidirectional,
+                words: 20,
+            }),
+            auto_detect_language: Some(true),
+            version: CRAWL_OPTIONS_VERSION,
+        }
+    }
+
+    #[test]
+    fn crawl_options_migrator_upgrades_unversioned_options() {
+        let options = CrawlOptionsMigrator::migrate(serde_json::json!({
+            "site_url": "https://docs.example.com",
+            "limit": 10,
+        }))
+        .unwrap();
+        assert_eq!(options.version, CRAWL_OPTIONS_VERSION);
+        assert_eq!(options.limit, Some(10));
+
+        assert!(matches!(
+            CrawlOptionsMigrator::migrate(serde_json::json!({
+                "site_url": "https://docs.example.com",
+                "version": CRAWL_OPTIONS_VERSION + 1,
+            })),
+            Err(ServiceError::InternalServerError(_))
+        ));
+    }
+
+    #[test]
+    fn crawl_requests_with_unreadable_options_fail_to_convert() {
+        let crawl_request = CrawlRequestPG {
+            id: uuid::Uuid::new_v4(),
+            url: "https://example.com".to_string(),
+            status: "pending".to_string(),
+            next_crawl_at: chrono::Utc::now().naive_utc(),
+            interval: 60 * 60 * 24,
+            crawl_options: serde_json::json!({ "version": CRAWL_OPTIONS_VERSION + 1 }),
+            scrape_id: uuid::Uuid::new_v4(),
+            dataset_id: uuid::Uuid::new_v4(),
+            created_at: chrono::Utc::now().naive_utc(),
+            crawl_duration_ms: None,
+            github_token: None,
+        };
+
+        assert!(matches!(
+            CrawlRequest::try_from(crawl_request),
+            Err(ServiceError::InternalServerError(_))
+        ));
+    }
+
+    #[test]
+    fn crawl_options_round_trip_through_json_and_merge() {
+        let options = fully_populated_crawl_options();
+
+        // The GitHub token is never serialized, it is stored apart from the crawl options
+        let serialized = serde_json::to_string(&options).unwrap();
+        assert!(!serialized.contains("\"token\""));
+        let deserialized: CrawlOptions = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized.github_token(), None);
+        assert_eq!(
+            deserialized.with_github_token(options.github_token()),
+            options
+        );
+        assert_eq!(
+            CrawlOptionsMigrator::migrate(serde_json::from_str(&serialized).unwrap())
+                .unwrap()
+                .with_github_token(options.github_token()),
+            options
+        );
+
+        let empty: CrawlOptions = serde_json::from_value(serde_json::json!({})).unwrap();
+        assert_eq!(options.merge(empty.clone()), options);
+        // The scrape options are only taken from the options being merged into, so they can be
+        // cleared
+        assert_eq!(
+            empty.merge(options.clone()),
+            CrawlOptions {
+                scrape_options: None,
+                ..options.clone()
+            }
+        );
+    }
+
+    #[test]
+    fn merge_keeps_the_github_token_of_the_same_repository() {
+        let options = fully_populated_crawl_options();
+        let mut update = options.clone();
+        if let Some(ScrapeOptions::GitHub(ref mut github_options)) = update.scrape_options {
+            github_options.token = None;
+        }
+        assert_eq!(
+            update.merge(options.clone()).github_token(),
+            options.github_token()
+        );
+
+        if let Some(ScrapeOptions::GitHub(ref mut github_options)) = update.scrape_options {
+            github_options.repo = "other/repo".to_string();
         }
+        assert_eq!(update.merge(options).github_token(), None);
     }
 }
@@ -1,5 +1,19 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    audit_crawl_events (id) {
+        id -> Uuid,
+        crawl_request_id -> Uuid,
+        dataset_id -> Uuid,
+        scrape_id -> Uuid,
+        user_id -> Nullable<Uuid>,
+        action -> Text,
+        old_value -> Nullable<Jsonb>,
+        new_value -> Nullable<Jsonb>,
+        occurred_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     chunk_group (id) {
         id -> Uuid,
@@ -51,6 +65,40 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    crawl_diffs (id) {
+        id -> Uuid,
+        dataset_id -> Uuid,
+        old_crawl_id -> Uuid,
+        new_crawl_id -> Uuid,
+        diff -> Jsonb,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    crawl_errors (id) {
+        id -> Uuid,
+        crawl_id -> Uuid,
+        dataset_id -> Uuid,
+        url -> Text,
+        error_type -> Text,
+        message -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    crawl_indexed_pages (id) {
+        id -> Uuid,
+        crawl_id -> Uuid,
+        dataset_id -> Uuid,
+        url -> Text,
+        content_hash -> Text,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     crawl_requests (id) {
         id -> Uuid,
@@ -62,6 +110,21 @@ diesel::table! {
         dataset_id -> Uuid,
         created_at -> Timestamp,
         crawl_options -> Jsonb,
+        archive_key -> Nullable<Text>,
+        settings_history -> Jsonb,
+        crawl_duration_ms -> Nullable<Int8>,
+        github_token -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    crawled_url_index (id) {
+        id -> Uuid,
+        organization_id -> Uuid,
+        dataset_id -> Uuid,
+        url_hash -> Text,
+        url -> Text,
+        created_at -> Timestamp,
     }
 }
 
@@ -185,6 +248,7 @@ diesel::table! {
         updated_at -> Timestamp,
         registerable -> Nullable<Bool>,
         deleted -> Int4,
+        status -> Text,
     }
 }
 
@@ -285,7 +349,12 @@ diesel::joinable!(chunk_group_bookmarks -> chunk_metadata (chunk_metadata_id));
 diesel::joinable!(chunk_metadata -> datasets (dataset_id));
 diesel::joinable!(chunk_metadata_tags -> chunk_metadata (chunk_metadata_id));
 diesel::joinable!(chunk_metadata_tags -> dataset_tags (tag_id));
+diesel::joinable!(crawl_diffs -> datasets (dataset_id));
+diesel::joinable!(crawl_errors -> datasets (dataset_id));
+diesel::joinable!(crawl_indexed_pages -> datasets (dataset_id));
 diesel::joinable!(crawl_requests -> datasets (dataset_id));
+diesel::joinable!(crawled_url_index -> datasets (dataset_id));
+diesel::joinable!(crawled_url_index -> organizations (organization_id));
 diesel::joinable!(dataset_event_counts -> datasets (dataset_uuid));
 diesel::joinable!(dataset_tags -> datasets (dataset_id));
 diesel::joinable!(dataset_usage_counts -> datasets (dataset_id));
@@ -305,11 +374,16 @@ diesel::joinable!(user_organizations -> organizations (organization_id));
 diesel::joinable!(user_organizations -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    audit_crawl_events,
     chunk_group,
     chunk_group_bookmarks,
     chunk_metadata,
     chunk_metadata_tags,
+    crawl_diffs,
+    crawl_errors,
+    crawl_indexed_pages,
     crawl_requests,
+    crawled_url_index,
     dataset_event_counts,
     dataset_group_counts,
     dataset_tags,
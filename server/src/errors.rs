@@ -40,6 +40,12 @@ pub enum ServiceError {
 
     #[display(fmt = "Payload Too Large")]
     PayloadTooLarge(String),
+
+    #[display(fmt = "Service Unavailable: {_0}")]
+    ServiceUnavailable(String),
+
+    #[display(fmt = "Precondition Failed: {_0}")]
+    PreconditionFailed(String),
 }
 
 // impl ResponseError trait allows to convert our errors into http responses with appropriate data
@@ -82,6 +88,14 @@ impl ResponseError for ServiceError {
                     message: message.to_string(),
                 })
             }
+            ServiceError::ServiceUnavailable(ref message) => HttpResponse::ServiceUnavailable()
+                .json(ErrorResponseBody {
+                    message: message.to_string(),
+                }),
+            ServiceError::PreconditionFailed(ref message) => HttpResponse::PreconditionFailed()
+                .json(ErrorResponseBody {
+                    message: message.to_string(),
+                }),
         }
     }
 }
@@ -1,5 +1,6 @@
 use crate::data::models::{Organization, RedisPool, StripePlan, UserRole};
 use crate::get_env;
+use crate::middleware::csrf_middleware::CSRF_SESSION_KEY;
 use crate::operators::dittofeed_operator::{get_user_ditto_identity, send_user_ditto_identity};
 use crate::operators::invitation_operator::check_inv_valid;
 use crate::operators::organization_operator::{get_org_from_id_query, get_user_org_count};
@@ -25,6 +26,8 @@ use oauth2::{
 };
 use openidconnect::core::{CoreAuthenticationFlow, CoreClient, CoreProviderMetadata};
 use openidconnect::{AccessTokenHash, ClientId, IssuerUrl, Nonce};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
 use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -613,6 +616,43 @@ pub async fn get_me(
     Ok(HttpResponse::Ok().json(SlimUser::from_details(user.0, user.1, user.2)))
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct CsrfTokenResponse {
+    /// Token to send in the `X-CSRF-Token` header of state changing crawl and dataset requests made with the session cookie
+    pub csrf_token: String,
+}
+
+/// Get CSRF Token
+///
+/// Generate a new CSRF token for the current session, replacing any previous token. Requests which change crawls, crawl options or datasets and are made with the session cookie must send it in the `X-CSRF-Token` header, along with an `Origin` or `Referer` header. Requests authenticated with an api key do not need a token.
+#[utoipa::path(
+    get,
+    path = "/auth/csrf",
+    context_path = "/api",
+    tag = "Auth",
+    responses(
+        (status = 200, description = "CSRF token for the current session", body = CsrfTokenResponse),
+        (status = 401, description = "Error message indicitating you are not currently signed in", body = ErrorResponseBody),
+    ),
+)]
+#[tracing::instrument(skip(session))]
+pub async fn get_csrf_token(
+    _logged_user: LoggedUser,
+    session: Session,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_token: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+
+    session
+        .insert(CSRF_SESSION_KEY, csrf_token.clone())
+        .map_err(|_| ServiceError::InternalServerError("Could not set CSRF Session".into()))?;
+
+    Ok(HttpResponse::Ok().json(CsrfTokenResponse { csrf_token }))
+}
+
 /// Health Check
 ///
 /// Confirmation that the service is healthy and can make embedding vectors
@@ -2755,7 +2755,7 @@ pub fn check_completion_param_validity(
     Ok(())
 }
 
-#[derive(Serialize, Deserialize, Debug, ToSchema, Clone)]
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone, PartialEq)]
 /// Options for including an openapi spec in the crawl
 #[schema(title = "CrawlOpenAPIOptions")]
 pub struct CrawlOpenAPIOptions {
@@ -2765,7 +2765,7 @@ pub struct CrawlOpenAPIOptions {
     pub openapi_tag: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, ToSchema, Clone)]
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone, PartialEq)]
 #[serde(rename_all = "snake_case")]
 /// Interval at which specified site should be re-scraped
 pub enum CrawlInterval {
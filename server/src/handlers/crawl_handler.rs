@@ -0,0 +1,577 @@
+use super::{auth_handler::AdminOnly, metrics_handler::check_x_api_access};
+use crate::{
+    data::models::{
+        AuditCrawlEvent, CrawlEvent, CrawlMaintenanceMode, CrawlOptions, CrawlOptionsSnapshot,
+        CrawlRequest, DatasetAndOrgWithSubAndPlan, Pool, RedisPool,
+    },
+    errors::ServiceError,
+    operators::{
+        crawl_operator::{
+            delete_chunks_for_crawl, delete_crawl_request_query, estimate_crawl_cost,
+            force_crawl_now, get_audit_crawl_events, get_crawl_archive_key, get_crawl_events,
+            get_crawl_maintenance_mode, get_crawl_request, get_crawl_settings_history,
+            get_crawl_summary, get_deduplicated_urls, purge_old_crawl_requests, reindex_crawl,
+            set_crawl_maintenance_mode, validate_crawl_options, FirecrawlConfig,
+        },
+        file_operator::get_aws_bucket,
+    },
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+/// Looks up a crawl of the dataset of the request. Crawls of other datasets are reported as not
+/// found, the same as crawls which don't exist.
+async fn get_dataset_crawl_request(
+    crawl_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<CrawlRequest, ServiceError> {
+    let crawl_request = get_crawl_request(crawl_id, pool).await?;
+
+    if crawl_request.dataset_id != dataset_id {
+        return Err(ServiceError::NotFound("Crawl not found".to_string()));
+    }
+
+    Ok(crawl_request)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct GetCrawlArchiveResponse {
+    /// Signed url to download the gzipped JSON array of documents collected by the crawl
+    pub signed_url: String,
+}
+
+/// Get Crawl Archive
+///
+/// Get a signed url to download the archived documents of a completed crawl. Archives are gzipped JSON arrays of the raw documents returned by the crawler. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/crawl/{crawl_id}/archive",
+    context_path = "/api",
+    tag = "Crawl",
+    responses(
+        (status = 200, description = "Signed url to download the crawl archive", body = GetCrawlArchiveResponse),
+        (status = 400, description = "Service error relating to getting the crawl archive", body = ErrorResponseBody),
+        (status = 404, description = "Crawl or archive not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("crawl_id" = uuid::Uuid, Path, description = "The scrape id of the crawl to get the archive for"),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+#[tracing::instrument(skip(pool))]
+pub async fn get_crawl_archive(
+    crawl_id: web::Path<uuid::Uuid>,
+    pool: web::Data<Pool>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, ServiceError> {
+    let crawl_id = crawl_id.into_inner();
+    get_dataset_crawl_request(crawl_id, dataset_org_plan_sub.dataset.id, pool.clone()).await?;
+
+    let archive_key =
+        get_crawl_archive_key(crawl_id, pool)
+            .await?
+            .ok_or(ServiceError::NotFound(
+                "No archive found for this crawl".to_string(),
+            ))?;
+
+    let bucket = get_aws_bucket()?;
+    let signed_url = bucket
+        .presign_get(archive_key, 300, None)
+        .await
+        .map_err(|e| {
+            log::error!("Error getting signed url for crawl archive: {}", e);
+            ServiceError::BadRequest(format!("Error getting signed url: {}", e))
+        })?;
+
+    Ok(HttpResponse::Ok().json(GetCrawlArchiveResponse { signed_url }))
+}
+
+/// Get Crawl Settings History
+///
+/// Get the change log of the crawl options for a crawl, most recent change first. Only the last 10 changes are kept. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/crawl/{crawl_id}/history",
+    context_path = "/api",
+    tag = "Crawl",
+    responses(
+        (status = 200, description = "Previous crawl options of the crawl", body = Vec<CrawlOptionsSnapshot>),
+        (status = 400, description = "Service error relating to getting the crawl settings history", body = ErrorResponseBody),
+        (status = 404, description = "Crawl not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("crawl_id" = uuid::Uuid, Path, description = "The scrape id of the crawl to get the settings history for"),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+#[tracing::instrument(skip(pool))]
+pub async fn get_crawl_history(
+    crawl_id: web::Path<uuid::Uuid>,
+    pool: web::Data<Pool>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, ServiceError> {
+    let crawl_id = crawl_id.into_inner();
+    get_dataset_crawl_request(crawl_id, dataset_org_plan_sub.dataset.id, pool.clone()).await?;
+
+    let settings_history = get_crawl_settings_history(crawl_id, pool).await?;
+
+    Ok(HttpResponse::Ok().json(settings_history))
+}
+
+/// Get Crawl Events
+///
+/// Get the state transitions of a crawl, oldest first. Events are kept for 30 days. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/crawl/{crawl_id}/events",
+    context_path = "/api",
+    tag = "Crawl",
+    responses(
+        (status = 200, description = "State transitions of the crawl", body = Vec<CrawlEvent>),
+        (status = 400, description = "Service error relating to getting the crawl events", body = ErrorResponseBody),
+        (status = 404, description = "Crawl not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("crawl_id" = uuid::Uuid, Path, description = "The scrape id of the crawl to get the events for"),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+#[tracing::instrument(skip(pool, redis_pool))]
+pub async fn get_crawl_events_handler(
+    crawl_id: web::Path<uuid::Uuid>,
+    pool: web::Data<Pool>,
+    redis_pool: web::Data<RedisPool>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, ServiceError> {
+    let crawl_id = crawl_id.into_inner();
+    get_dataset_crawl_request(crawl_id, dataset_org_plan_sub.dataset.id, pool).await?;
+
+    let events = get_crawl_events(crawl_id, redis_pool).await?;
+
+    Ok(HttpResponse::Ok().json(events))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct GetDeduplicatedUrlsResponse {
+    /// Urls which were skipped because they were already indexed in another dataset of the organization
+    pub urls: Vec<String>,
+}
+
+/// Get Deduplicated Urls
+///
+/// Get the urls a crawl skipped because they were already indexed in another dataset of the same organization. Urls are only skipped when `deduplicate_across_datasets` is set on the crawl options. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/crawl/{crawl_id}/deduplicated-urls",
+    context_path = "/api",
+    tag = "Crawl",
+    responses(
+        (status = 200, description = "Urls skipped by the crawl", body = GetDeduplicatedUrlsResponse),
+        (status = 400, description = "Service error relating to getting the deduplicated urls", body = ErrorResponseBody),
+        (status = 404, description = "Crawl not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("crawl_id" = uuid::Uuid, Path, description = "The scrape id of the crawl to get the deduplicated urls for"),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+#[tracing::instrument(skip(pool))]
+pub async fn get_deduplicated_urls_handler(
+    crawl_id: web::Path<uuid::Uuid>,
+    pool: web::Data<Pool>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, ServiceError> {
+    let crawl_id = crawl_id.into_inner();
+    get_dataset_crawl_request(crawl_id, dataset_org_plan_sub.dataset.id, pool.clone()).await?;
+
+    let urls = get_deduplicated_urls(crawl_id, pool).await?;
+
+    Ok(HttpResponse::Ok().json(GetDeduplicatedUrlsResponse { urls }))
+}
+
+/// Force Crawl
+///
+/// Start a new crawl of the site right away instead of waiting for the next scheduled crawl. A crawl can only be forced once every 15 minutes per dataset. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    post,
+    path = "/crawl/{crawl_id}/force",
+    context_path = "/api",
+    tag = "Crawl",
+    responses(
+        (status = 204, description = "The crawl was started"),
+        (status = 400, description = "Service error relating to forcing the crawl, or a crawl was already forced in the last 15 minutes", body = ErrorResponseBody),
+        (status = 404, description = "Crawl not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("crawl_id" = uuid::Uuid, Path, description = "The scrape id of the crawl to run again"),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+#[tracing::instrument(skip(pool, redis_pool))]
+pub async fn force_crawl_handler(
+    crawl_id: web::Path<uuid::Uuid>,
+    pool: web::Data<Pool>,
+    redis_pool: web::Data<RedisPool>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, ServiceError> {
+    let crawl_id = crawl_id.into_inner();
+    get_dataset_crawl_request(crawl_id, dataset_org_plan_sub.dataset.id, pool.clone()).await?;
+
+    force_crawl_now(crawl_id, pool, redis_pool).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Reindex Crawl
+///
+/// Chunk the pages of a finished crawl again with its current crawl options, without crawling the site again. The pages are restored from the crawl archive, so only crawls which have been archived and are completed, partial or failed can be reindexed. The crawl worker indexes the archived pages in the background, updating the chunks of the crawl in place and deleting the chunks which are no longer produced. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    post,
+    path = "/crawl/{crawl_id}/reindex",
+    context_path = "/api",
+    tag = "Crawl",
+    responses(
+        (status = 204, description = "The crawl was queued to be reindexed"),
+        (status = 400, description = "Service error relating to reindexing the crawl, the crawl is still running or has no archive", body = ErrorResponseBody),
+        (status = 404, description = "Crawl not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("crawl_id" = uuid::Uuid, Path, description = "The scrape id of the crawl to reindex"),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+#[tracing::instrument(skip(pool, redis_pool))]
+pub async fn reindex_crawl_handler(
+    crawl_id: web::Path<uuid::Uuid>,
+    pool: web::Data<Pool>,
+    redis_pool: web::Data<RedisPool>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, ServiceError> {
+    let crawl_id = crawl_id.into_inner();
+    get_dataset_crawl_request(crawl_id, dataset_org_plan_sub.dataset.id, pool.clone()).await?;
+
+    reindex_crawl(crawl_id, pool, redis_pool).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Get Crawl Audit Log
+///
+/// Get the changes made to a crawl: when it was created, each change of its status, each update of its crawl options, url or scrape id and its deletion, oldest first. The audit log is kept after the crawl is deleted. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/crawl/{crawl_id}/audit",
+    context_path = "/api",
+    tag = "Crawl",
+    responses(
+        (status = 200, description = "The audit log of the crawl", body = Vec<AuditCrawlEvent>),
+        (status = 400, description = "Service error relating to getting the audit log", body = ErrorResponseBody),
+        (status = 404, description = "Crawl not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("crawl_id" = uuid::Uuid, Path, description = "The scrape id of the crawl to get the audit log of"),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+#[tracing::instrument(skip(pool))]
+pub async fn get_crawl_audit_handler(
+    crawl_id: web::Path<uuid::Uuid>,
+    pool: web::Data<Pool>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, ServiceError> {
+    // Authorized against the dataset stored on the audit rows, so the log of a deleted crawl
+    // can still be read
+    let events =
+        get_audit_crawl_events(crawl_id.into_inner(), dataset_org_plan_sub.dataset.id, pool)
+            .await?;
+    if events.is_empty() {
+        return Err(ServiceError::NotFound("Crawl not found".to_string()));
+    }
+
+    Ok(HttpResponse::Ok().json(events))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, IntoParams)]
+pub struct DeleteCrawlRequestQuery {
+    /// Also delete the chunks created from the pages of the crawl. Defaults to false.
+    pub delete_chunks: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct DeleteCrawlRequestResponse {
+    /// Number of chunks which were deleted, 0 unless `delete_chunks` was set
+    pub deleted_chunks: u64,
+}
+
+/// Delete Crawl
+///
+/// Delete a crawl so it is no longer rerun. Set `delete_chunks` to also delete the chunks created from the pages it indexed. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    delete,
+    path = "/crawl/{crawl_id}",
+    context_path = "/api",
+    tag = "Crawl",
+    responses(
+        (status = 200, description = "The crawl was deleted", body = DeleteCrawlRequestResponse),
+        (status = 400, description = "Service error relating to deleting the crawl", body = ErrorResponseBody),
+        (status = 404, description = "Crawl not found", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("crawl_id" = uuid::Uuid, Path, description = "The scrape id of the crawl to delete"),
+        DeleteCrawlRequestQuery,
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+#[tracing::instrument(skip(pool))]
+pub async fn delete_crawl_request(
+    crawl_id: web::Path<uuid::Uuid>,
+    query: web::Query<DeleteCrawlRequestQuery>,
+    pool: web::Data<Pool>,
+    user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, ServiceError> {
+    let crawl_id = crawl_id.into_inner();
+    let crawl_request =
+        get_dataset_crawl_request(crawl_id, dataset_org_plan_sub.dataset.id, pool.clone()).await?;
+
+    let deleted_chunks = if query.delete_chunks.unwrap_or(false) {
+        delete_chunks_for_crawl(crawl_id, crawl_request.dataset_id, pool.clone()).await?
+    } else {
+        0
+    };
+
+    delete_crawl_request_query(crawl_id, Some(user.0.id), pool).await?;
+
+    Ok(HttpResponse::Ok().json(DeleteCrawlRequestResponse { deleted_chunks }))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct EstimateCrawlCostReqPayload {
+    /// The crawl options to estimate the cost of
+    pub crawl_options: CrawlOptions,
+}
+
+/// Estimate Crawl Cost
+///
+/// Estimate how many pages a crawl would index and how many crawler credits it would use, without starting it. The estimate is based on the urls the crawler can discover for the site, so the actual crawl can differ. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    post,
+    path = "/crawl/estimate",
+    context_path = "/api",
+    tag = "Crawl",
+    request_body(content = EstimateCrawlCostReqPayload, description = "JSON request payload to estimate the cost of a crawl", content_type = "application/json"),
+    responses(
+        (status = 200, description = "Estimated cost of the crawl", body = CrawlCostEstimate),
+        (status = 400, description = "Service error relating to estimating the crawl cost", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+#[tracing::instrument]
+pub async fn estimate_crawl_cost_handler(
+    data: web::Json<EstimateCrawlCostReqPayload>,
+    _user: AdminOnly,
+    _dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, ServiceError> {
+    let crawl_options = data.into_inner().crawl_options;
+    validate_crawl_options(&crawl_options)?;
+
+    let estimate = estimate_crawl_cost(&crawl_options, &FirecrawlConfig::from_env()).await?;
+
+    Ok(HttpResponse::Ok().json(estimate))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema, IntoParams)]
+pub struct PurgeCrawlRequestsQuery {
+    /// Finished crawl requests created more than this many days ago are deleted
+    pub older_than_days: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct PurgeCrawlRequestsResponse {
+    /// Number of crawl requests which were deleted
+    pub deleted: u64,
+}
+
+/// Purge Old Crawl Requests
+///
+/// Delete completed, failed, and cancelled crawl requests older than `older_than_days` days. The most recent crawl request of each dataset is always kept. This also runs nightly as part of the crawl cron job. Requires the ADMIN_API_KEY of the instance.
+#[utoipa::path(
+    post,
+    path = "/admin/crawl/purge",
+    context_path = "/api",
+    tag = "Crawl",
+    params(PurgeCrawlRequestsQuery),
+    responses(
+        (status = 200, description = "Number of crawl requests deleted", body = PurgeCrawlRequestsResponse),
+        (status = 401, description = "ADMIN_API_KEY was not provided or is incorrect"),
+        (status = 400, description = "Service error relating to purging crawl requests", body = ErrorResponseBody),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+#[tracing::instrument(skip(pool))]
+pub async fn purge_crawl_requests(
+    req: HttpRequest,
+    query: web::Query<PurgeCrawlRequestsQuery>,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    if !check_x_api_access(&req) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let deleted = purge_old_crawl_requests(query.older_than_days, pool).await?;
+
+    Ok(HttpResponse::Ok().json(PurgeCrawlRequestsResponse { deleted }))
+}
+
+/// Get Crawl Summary
+///
+/// Get the number of crawls of the instance and the median and 95th percentile time the crawl worker took to process them. Requires the ADMIN_API_KEY of the instance.
+#[utoipa::path(
+    get,
+    path = "/admin/crawl/summary",
+    context_path = "/api",
+    tag = "Crawl",
+    responses(
+        (status = 200, description = "Summary of the crawls of the instance", body = CrawlSummary),
+        (status = 401, description = "ADMIN_API_KEY was not provided or is incorrect"),
+        (status = 400, description = "Service error relating to getting the crawl summary", body = ErrorResponseBody),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+#[tracing::instrument(skip(pool))]
+pub async fn get_crawl_summary_handler(
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+) -> Result<HttpResponse, ServiceError> {
+    if !check_x_api_access(&req) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let summary = get_crawl_summary(pool).await?;
+
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+/// Get Crawl Maintenance Mode
+///
+/// Get whether crawl maintenance mode is enabled. While it is enabled, the crawl cron job does not rerun any crawls. Requires the ADMIN_API_KEY of the instance.
+#[utoipa::path(
+    get,
+    path = "/admin/crawl/maintenance",
+    context_path = "/api",
+    tag = "Crawl",
+    responses(
+        (status = 200, description = "Current crawl maintenance mode", body = CrawlMaintenanceMode),
+        (status = 401, description = "ADMIN_API_KEY was not provided or is incorrect"),
+        (status = 400, description = "Service error relating to getting the crawl maintenance mode", body = ErrorResponseBody),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+#[tracing::instrument(skip(redis_pool))]
+pub async fn get_crawl_maintenance_mode_handler(
+    req: HttpRequest,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<HttpResponse, ServiceError> {
+    if !check_x_api_access(&req) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let maintenance_mode = get_crawl_maintenance_mode(redis_pool).await?;
+
+    Ok(HttpResponse::Ok().json(maintenance_mode))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[schema(example = json!({
+    "enabled": true,
+    "reason": "Database migration",
+}))]
+pub struct SetCrawlMaintenanceModeReqPayload {
+    /// Whether the crawl cron job should stop rerunning crawls
+    pub enabled: bool,
+    /// Why crawls are paused, shown when getting the maintenance mode
+    pub reason: Option<String>,
+}
+
+/// Set Crawl Maintenance Mode
+///
+/// Enable or disable crawl maintenance mode. While it is enabled, the crawl cron job does not rerun any crawls, crawls which were already queued are still processed. Requires the ADMIN_API_KEY of the instance.
+#[utoipa::path(
+    post,
+    path = "/admin/crawl/maintenance",
+    context_path = "/api",
+    tag = "Crawl",
+    request_body(content = SetCrawlMaintenanceModeReqPayload, description = "JSON request payload to set the crawl maintenance mode", content_type = "application/json"),
+    responses(
+        (status = 200, description = "The crawl maintenance mode after the change", body = CrawlMaintenanceMode),
+        (status = 401, description = "ADMIN_API_KEY was not provided or is incorrect"),
+        (status = 400, description = "Service error relating to setting the crawl maintenance mode", body = ErrorResponseBody),
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+#[tracing::instrument(skip(redis_pool))]
+pub async fn set_crawl_maintenance_mode_handler(
+    req: HttpRequest,
+    data: web::Json<SetCrawlMaintenanceModeReqPayload>,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<HttpResponse, ServiceError> {
+    if !check_x_api_access(&req) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    set_crawl_maintenance_mode(
+        data.enabled,
+        data.reason.as_deref().unwrap_or_default(),
+        redis_pool.clone(),
+    )
+    .await?;
+    let maintenance_mode = get_crawl_maintenance_mode(redis_pool).await?;
+
+    Ok(HttpResponse::Ok().json(maintenance_mode))
+}
@@ -9,7 +9,8 @@ use crate::{
     middleware::auth_middleware::{verify_admin, verify_owner},
     operators::{
         crawl_operator::{
-            crawl, get_crawl_request_by_dataset_id_query, update_crawl_settings_for_dataset,
+            crawl, crawl_options_etag, get_crawl_request_by_dataset_id_query, list_dataset_urls,
+            update_crawl_settings_for_dataset,
         },
         dataset_operator::{
             clear_dataset_by_dataset_id_query, create_dataset_query, get_dataset_by_id_query,
@@ -22,11 +23,11 @@ use crate::{
         organization_operator::{get_org_dataset_count, get_org_from_id_query},
     },
 };
-use actix_web::{web, FromRequest, HttpMessage, HttpResponse};
+use actix_web::{http::header, web, FromRequest, HttpMessage, HttpRequest, HttpResponse};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::future::{ready, Ready};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
 impl FromRequest for DatasetAndOrgWithSubAndPlan {
     type Error = ServiceError;
@@ -174,6 +175,7 @@ pub async fn create_dataset(
             pool.clone(),
             redis_pool.clone(),
             dataset.id,
+            Some(user.0.id),
         )
         .await?;
     };
@@ -304,6 +306,8 @@ pub async fn update_dataset(
         update_crawl_settings_for_dataset(
             crawl_options.clone(),
             curr_dataset.id,
+            Some(user.0.id),
+            None,
             pool.clone(),
             redis_pool.clone(),
         )
@@ -339,12 +343,14 @@ pub struct GetCrawlOptionsResponse {
     tag = "Dataset",
     responses(
         (status = 200, description = "Crawl options retrieved successfully", body = GetCrawlOptionsResponse),
+        (status = 304, description = "Crawl options did not change since the ETag in the If-None-Match header was read"),
         (status = 400, description = "Service error relating to retrieving the crawl options", body = ErrorResponseBody),
         (status = 404, description = "Dataset not found", body = ErrorResponseBody)
     ),
     params(
         ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
         ("dataset_id" = uuid, Path, description = "The id of the dataset you want to retrieve."),
+        ("If-None-Match" = Option<String>, Header, description = "ETag of previously retrieved crawl options."),
     ),
     security(
         ("ApiKey" = ["admin"]),
@@ -352,6 +358,7 @@ pub struct GetCrawlOptionsResponse {
 )]
 #[tracing::instrument(skip(pool))]
 pub async fn get_dataset_crawl_options(
+    req: HttpRequest,
     pool: web::Data<Pool>,
     dataset_id: web::Path<uuid::Uuid>,
     user: AdminOnly,
@@ -364,11 +371,183 @@ pub async fn get_dataset_crawl_options(
         return Err(ServiceError::Forbidden);
     }
 
-    Ok(HttpResponse::Ok().json(GetCrawlOptionsResponse {
+    let etag = crawl_req
+        .as_ref()
+        .map(|req| crawl_options_etag(&req.crawl_options));
+
+    if let Some(ref etag) = etag {
+        let if_none_match = req
+            .headers()
+            .get(header::IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok());
+
+        if if_none_match == Some(etag.as_str()) {
+            return Ok(HttpResponse::NotModified()
+                .insert_header((header::ETAG, etag.clone()))
+                .finish());
+        }
+    }
+
+    let mut response = HttpResponse::Ok();
+    if let Some(etag) = etag {
+        response.insert_header((header::ETAG, etag));
+    }
+
+    Ok(response.json(GetCrawlOptionsResponse {
         crawl_options: crawl_req.map(|req| req.crawl_options),
     }))
 }
 
+#[derive(Serialize, Deserialize, Debug, ToSchema, IntoParams, Clone)]
+pub struct GetIndexedUrlsQuery {
+    /// Page number to return, 1-indexed. Default is 1.
+    pub page: Option<u32>,
+    /// Number of urls to return per page. Default is 100, at most 1000.
+    pub page_size: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone)]
+#[schema(example = json!({
+    "urls": ["https://example.com", "https://example.com/about"],
+    "total": 2,
+}))]
+pub struct GetIndexedUrlsResponse {
+    /// Urls of the crawled pages which have chunks in the dataset, sorted alphabetically
+    pub urls: Vec<String>,
+    /// Total number of indexed urls in the dataset
+    pub total: u64,
+}
+
+/// Get Dataset Indexed Urls
+///
+/// List the urls of the crawled pages which currently have chunks in the dataset. Auth'ed user or api key must have an admin or owner role for the specified dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/dataset/{dataset_id}/indexed-urls",
+    context_path = "/api",
+    tag = "Dataset",
+    responses(
+        (status = 200, description = "Page of indexed urls retrieved successfully", body = GetIndexedUrlsResponse),
+        (status = 400, description = "Service error relating to listing the indexed urls", body = ErrorResponseBody),
+        (status = 404, description = "Dataset not found", body = ErrorResponseBody)
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("dataset_id" = uuid, Path, description = "The id of the dataset you want to list the indexed urls of."),
+        GetIndexedUrlsQuery,
+    ),
+    security(
+        ("ApiKey" = ["admin"]),
+    )
+)]
+#[tracing::instrument(skip(pool))]
+pub async fn get_dataset_indexed_urls(
+    dataset_id: web::Path<uuid::Uuid>,
+    query: web::Query<GetIndexedUrlsQuery>,
+    pool: web::Data<Pool>,
+    user: AdminOnly,
+) -> Result<HttpResponse, ServiceError> {
+    let d = get_dataset_by_id_query(UnifiedId::TrieveUuid(dataset_id.into_inner()), pool.clone())
+        .await?;
+
+    if !verify_admin(&user, &d.organization_id) {
+        return Err(ServiceError::Forbidden);
+    }
+
+    let page = query.page.unwrap_or(1);
+    if page < 1 {
+        return Err(ServiceError::BadRequest(
+            "Page must be greater than 0".to_string(),
+        ));
+    }
+    let page_size = query.page_size.unwrap_or(100);
+    if !(1..=1000).contains(&page_size) {
+        return Err(ServiceError::BadRequest(
+            "Page size must be between 1 and 1000".to_string(),
+        ));
+    }
+
+    let (urls, total) = list_dataset_urls(d.id, page, page_size, pool).await?;
+
+    Ok(HttpResponse::Ok().json(GetIndexedUrlsResponse { urls, total }))
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone)]
+#[schema(example = json!({
+    "crawl_options": {
+        "site_url": "https://example.com",
+        "interval": "daily",
+        "limit": 1000,
+    }
+}))]
+pub struct UpdateCrawlOptionsReqPayload {
+    /// Crawl options to merge into the dataset's current crawl options. Options which are not specified keep their current value.
+    pub crawl_options: CrawlOptions,
+}
+
+/// Update Dataset Crawl Options
+///
+/// Merge the given crawl options into the dataset's current crawl options and start a new crawl with them. The response contains the new crawl request, including the merged crawl options, and an ETag of the merged options. Send the ETag from a previous read in an If-Match header to only update the crawl options if they have not changed since. Auth'ed user or api key must have an owner role for the specified dataset's organization.
+#[utoipa::path(
+    put,
+    path = "/dataset/crawl_options/{dataset_id}",
+    context_path = "/api",
+    tag = "Dataset",
+    request_body(content = UpdateCrawlOptionsReqPayload, description = "JSON request payload to update the crawl options of a dataset", content_type = "application/json"),
+    responses(
+        (status = 200, description = "Crawl options updated successfully", body = CrawlRequest),
+        (status = 400, description = "Service error relating to updating the crawl options", body = ErrorResponseBody),
+        (status = 404, description = "Dataset not found", body = ErrorResponseBody),
+        (status = 412, description = "The crawl options changed since the ETag in the If-Match header was read", body = ErrorResponseBody),
+    ),
+    params(
+        ("TR-Dataset" = uuid::Uuid, Header, description = "The dataset id or tracking_id to use for the request. We assume you intend to use an id if the value is a valid uuid."),
+        ("dataset_id" = uuid, Path, description = "The id of the dataset you want to update the crawl options of."),
+        ("If-Match" = Option<String>, Header, description = "ETag of the crawl options the update is based on."),
+    ),
+    security(
+        ("ApiKey" = ["owner"]),
+    )
+)]
+#[tracing::instrument(skip(pool, redis_pool))]
+pub async fn update_dataset_crawl_options(
+    req: HttpRequest,
+    data: web::Json<UpdateCrawlOptionsReqPayload>,
+    dataset_id: web::Path<uuid::Uuid>,
+    pool: web::Data<Pool>,
+    redis_pool: web::Data<RedisPool>,
+    user: OwnerOnly,
+) -> Result<HttpResponse, ServiceError> {
+    let d = get_dataset_by_id_query(UnifiedId::TrieveUuid(dataset_id.into_inner()), pool.clone())
+        .await?;
+
+    if !verify_owner(&user, &d.organization_id) {
+        return Err(ServiceError::Forbidden);
+    }
+
+    let if_match = req
+        .headers()
+        .get(header::IF_MATCH)
+        .map(|if_match| if_match.to_str().unwrap_or_default().to_string());
+
+    let crawl_request = update_crawl_settings_for_dataset(
+        data.crawl_options.clone(),
+        d.id,
+        Some(user.0.id),
+        if_match,
+        pool,
+        redis_pool,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            header::ETAG,
+            crawl_options_etag(&crawl_request.crawl_options),
+        ))
+        .json(crawl_request))
+}
+
 /// Delete Dataset
 ///
 /// Auth'ed user must be an owner of the organization to delete a dataset.
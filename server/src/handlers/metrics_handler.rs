@@ -152,7 +152,7 @@ impl Metrics {
     }
 }
 
-fn check_x_api_access(req: &actix_web::HttpRequest) -> bool {
+pub(crate) fn check_x_api_access(req: &actix_web::HttpRequest) -> bool {
     let admin_key = std::env::var("ADMIN_API_KEY");
     let x_api_key = req.headers().get("X-API-KEY");
     let auth_api_key = req.headers().get("Authorization");
@@ -158,6 +158,7 @@ impl Modify for SecurityAddon {
         handlers::auth_handler::login,
         handlers::auth_handler::logout,
         handlers::auth_handler::get_me,
+        handlers::auth_handler::get_csrf_token,
         handlers::auth_handler::callback,
         handlers::auth_handler::health_check,
         handlers::topic_handler::create_topic,
@@ -226,9 +227,24 @@ impl Modify for SecurityAddon {
         handlers::dataset_handler::get_dataset,
         handlers::dataset_handler::get_dataset_by_tracking_id,
         handlers::dataset_handler::get_dataset_crawl_options,
+        handlers::dataset_handler::get_dataset_indexed_urls,
+        handlers::dataset_handler::update_dataset_crawl_options,
         handlers::dataset_handler::get_usage_by_dataset_id,
         handlers::dataset_handler::get_datasets_from_organization,
         handlers::dataset_handler::clear_dataset,
+        handlers::crawl_handler::get_crawl_archive,
+        handlers::crawl_handler::get_crawl_history,
+        handlers::crawl_handler::get_crawl_events_handler,
+        handlers::crawl_handler::get_deduplicated_urls_handler,
+        handlers::crawl_handler::force_crawl_handler,
+        handlers::crawl_handler::reindex_crawl_handler,
+        handlers::crawl_handler::get_crawl_audit_handler,
+        handlers::crawl_handler::delete_crawl_request,
+        handlers::crawl_handler::estimate_crawl_cost_handler,
+        handlers::crawl_handler::purge_crawl_requests,
+        handlers::crawl_handler::get_crawl_summary_handler,
+        handlers::crawl_handler::get_crawl_maintenance_mode_handler,
+        handlers::crawl_handler::set_crawl_maintenance_mode_handler,
         handlers::stripe_handler::direct_to_payment_link,
         handlers::stripe_handler::cancel_subscription,
         handlers::stripe_handler::update_subscription_plan,
@@ -251,6 +267,7 @@ impl Modify for SecurityAddon {
     components(
         schemas(
             handlers::auth_handler::AuthQuery,
+            handlers::auth_handler::CsrfTokenResponse,
             handlers::topic_handler::CreateTopicReqPayload,
             handlers::topic_handler::CloneTopicReqPayload,
             handlers::topic_handler::DeleteTopicData,
@@ -295,6 +312,17 @@ impl Modify for SecurityAddon {
             handlers::dataset_handler::GetAllTagsReqPayload,
             handlers::dataset_handler::GetAllTagsResponse,
             handlers::dataset_handler::GetCrawlOptionsResponse,
+            handlers::dataset_handler::GetIndexedUrlsQuery,
+            handlers::dataset_handler::GetIndexedUrlsResponse,
+            handlers::dataset_handler::UpdateCrawlOptionsReqPayload,
+            handlers::crawl_handler::GetCrawlArchiveResponse,
+            handlers::crawl_handler::PurgeCrawlRequestsQuery,
+            handlers::crawl_handler::PurgeCrawlRequestsResponse,
+            handlers::crawl_handler::SetCrawlMaintenanceModeReqPayload,
+            handlers::crawl_handler::GetDeduplicatedUrlsResponse,
+            handlers::crawl_handler::EstimateCrawlCostReqPayload,
+            handlers::crawl_handler::DeleteCrawlRequestQuery,
+            handlers::crawl_handler::DeleteCrawlRequestResponse,
             handlers::group_handler::RecommendGroupsReqPayload,
             handlers::group_handler::RecommendGroupsResponse,
             handlers::group_handler::SearchWithinGroupReqPayload,
@@ -345,6 +373,20 @@ impl Modify for SecurityAddon {
             handlers::chunk_handler::CrawlInterval,
             data::models::ScrapeOptions,
             data::models::CrawlShopifyOptions,
+            data::models::CrawlGitHubOptions,
+            data::models::CompletionAction,
+            data::models::ChunkStrategy,
+            data::models::TitleExtractionStrategy,
+            data::models::SmartDedupStrategy,
+            data::models::SimilarityMethod,
+            data::models::OverlapStrategy,
+            data::models::OverlapDirection,
+            data::models::AuditCrawlEvent,
+            data::models::JsAction,
+            data::models::OpenAIEnrichConfig,
+            data::models::ActionType,
+            data::models::WebhookConfig,
+            data::models::SlackConfig,
             handlers::analytics_handler::GetTopDatasetsRequestBody,
             handlers::analytics_handler::CTRDataRequestBody,
             data::models::CTRType,
@@ -475,6 +517,14 @@ impl Modify for SecurityAddon {
             data::models::SlimChunkMetadataWithScore,
             data::models::GeoInfo,
             data::models::CrawlOptions,
+            data::models::CrawlOptionsSnapshot,
+            data::models::CrawlRequest,
+            data::models::CrawlStatus,
+            data::models::CrawlCostEstimate,
+            data::models::CrawlSummary,
+            data::models::CrawlMaintenanceMode,
+            data::models::CrawlEvent,
+            data::models::CrawlEventType,
             data::models::GeoInfoWithBias,
             data::models::GeoTypes,
             data::models::ChunkMetadataWithPosition,
@@ -496,6 +546,7 @@ impl Modify for SecurityAddon {
         (name = "User", description = "User endpoint. Enables you to modify user roles and information."),
         (name = "Organization", description = "Organization endpoint. Enables you to modify organization roles and information."),
         (name = "Dataset", description = "Dataset endpoint. Datasets belong to organizations and hold configuration information for both client and server. Datasets contain chunks and chunk groups."),
+        (name = "Crawl", description = "Crawl endpoint. Crawls populate a dataset with the contents of a website and are configured through the crawl_options of the dataset."),
         (name = "Chunk", description = "Chunk endpoint. Think of chunks as individual searchable units of information. The majority of your integration will likely be with the Chunk endpoint."),
         (name = "Chunk Group", description = "Chunk groups endpoint. Think of a chunk_group as a bookmark folder within the dataset."),
         (name = "File", description = "File endpoint. When files are uploaded, they are stored in S3 and broken up into chunks with text extraction from Apache Tika. You can upload files of pretty much any type up to 1GB in size. See chunking algorithm details at `docs.trieve.ai` for more information on how chunking works. Improved default chunking is on our roadmap."),
@@ -771,6 +822,8 @@ pub fn main() -> std::io::Result<()> {
                             web::scope("/dataset")
                                 .service(
                                     web::resource("")
+                                        // Dataset creates and updates carry crawl options
+                                        .wrap(middleware::csrf_middleware::CsrfMiddlewareFactory)
                                         .route(
                                             web::post().to(handlers::dataset_handler::create_dataset),
                                         )
@@ -789,7 +842,9 @@ pub fn main() -> std::io::Result<()> {
                                 )
                                 .service(
                                     web::resource("/crawl_options/{dataset_id}")
-                                        .route(web::get().to(handlers::dataset_handler::get_dataset_crawl_options)),
+                                        .wrap(middleware::csrf_middleware::CsrfMiddlewareFactory)
+                                        .route(web::get().to(handlers::dataset_handler::get_dataset_crawl_options))
+                                        .route(web::put().to(handlers::dataset_handler::update_dataset_crawl_options)),
                                 )
                                 .service(
                                     web::resource("/get_all_tags")
@@ -802,6 +857,10 @@ pub fn main() -> std::io::Result<()> {
                                             web::delete().to(handlers::dataset_handler::delete_dataset),
                                         )
                                 )
+                                .service(
+                                    web::resource("/{dataset_id}/indexed-urls")
+                                        .route(web::get().to(handlers::dataset_handler::get_dataset_indexed_urls)),
+                                )
                                 .service(
                                     web::resource("/clear/{dataset_id}")
                                         .route(web::put().to(handlers::dataset_handler::clear_dataset)),
@@ -824,6 +883,63 @@ pub fn main() -> std::io::Result<()> {
                                     web::get().to(handlers::file_handler::get_dataset_files_handler),
                                 )),
                         )
+                        .service(
+                            web::scope("/crawl")
+                                .wrap(middleware::csrf_middleware::CsrfMiddlewareFactory)
+                                .service(
+                                    web::resource("/estimate")
+                                        .route(web::post().to(handlers::crawl_handler::estimate_crawl_cost_handler)),
+                                )
+                                .service(
+                                    web::resource("/{crawl_id}")
+                                        .route(web::delete().to(handlers::crawl_handler::delete_crawl_request)),
+                                )
+                                .service(
+                                    web::resource("/{crawl_id}/archive")
+                                        .route(web::get().to(handlers::crawl_handler::get_crawl_archive)),
+                                )
+                                .service(
+                                    web::resource("/{crawl_id}/history")
+                                        .route(web::get().to(handlers::crawl_handler::get_crawl_history)),
+                                )
+                                .service(
+                                    web::resource("/{crawl_id}/events")
+                                        .route(web::get().to(handlers::crawl_handler::get_crawl_events_handler)),
+                                )
+                                .service(
+                                    web::resource("/{crawl_id}/deduplicated-urls")
+                                        .route(web::get().to(handlers::crawl_handler::get_deduplicated_urls_handler)),
+                                )
+                                .service(
+                                    web::resource("/{crawl_id}/force")
+                                        .route(web::post().to(handlers::crawl_handler::force_crawl_handler)),
+                                )
+                                .service(
+                                    web::resource("/{crawl_id}/reindex")
+                                        .route(web::post().to(handlers::crawl_handler::reindex_crawl_handler)),
+                                )
+                                .service(
+                                    web::resource("/{crawl_id}/audit")
+                                        .route(web::get().to(handlers::crawl_handler::get_crawl_audit_handler)),
+                                ),
+                        )
+                        .service(
+                            web::scope("/admin/crawl")
+                                .wrap(middleware::csrf_middleware::CsrfMiddlewareFactory)
+                                .service(
+                                    web::resource("/purge")
+                                        .route(web::post().to(handlers::crawl_handler::purge_crawl_requests)),
+                                )
+                                .service(
+                                    web::resource("/summary")
+                                        .route(web::get().to(handlers::crawl_handler::get_crawl_summary_handler)),
+                                )
+                                .service(
+                                    web::resource("/maintenance")
+                                        .route(web::get().to(handlers::crawl_handler::get_crawl_maintenance_mode_handler))
+                                        .route(web::post().to(handlers::crawl_handler::set_crawl_maintenance_mode_handler)),
+                                ),
+                        )
                         .service(
                             web::scope("/auth")
                                 .service(
@@ -835,6 +951,10 @@ pub fn main() -> std::io::Result<()> {
                                     web::resource("/me")
                                         .route(web::get().to(handlers::auth_handler::get_me)),
                                 )
+                                .service(
+                                    web::resource("/csrf")
+                                        .route(web::get().to(handlers::auth_handler::get_csrf_token)),
+                                )
                                 .service(
                                     web::resource("/callback")
                                         .route(web::get().to(handlers::auth_handler::callback)),
@@ -0,0 +1,134 @@
+use crate::errors::ServiceError;
+use actix_identity::IdentityExt;
+use actix_session::SessionExt;
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::Method,
+    Error,
+};
+use futures_util::future::LocalBoxFuture;
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+/// Key of the CSRF token in the session, see `auth_handler::get_csrf_token`
+pub const CSRF_SESSION_KEY: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// Origins browser requests may come from, read from the comma separated `CSRF_ALLOWED_ORIGINS`
+/// and falling back to `ADMIN_DASHBOARD_URL`.
+fn allowed_origins() -> Vec<String> {
+    std::env::var("CSRF_ALLOWED_ORIGINS")
+        .or_else(|_| std::env::var("ADMIN_DASHBOARD_URL"))
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|origin| reqwest::Url::parse(origin.trim()).ok())
+        .map(|origin| origin.origin().ascii_serialization())
+        .collect()
+}
+
+/// Origin of a browser request from its `Origin` header, or its `Referer` header when there is
+/// no `Origin`.
+fn request_origin(req: &ServiceRequest) -> Option<String> {
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| reqwest::Url::parse(value).ok())
+    };
+
+    header("Origin")
+        .or_else(|| header("Referer"))
+        .map(|url| url.origin().ascii_serialization())
+}
+
+fn is_state_mutating(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+fn check_csrf_token(req: &ServiceRequest) -> Result<(), ServiceError> {
+    if !is_state_mutating(req.method()) {
+        return Ok(());
+    }
+
+    // Only requests authenticated by the session cookie can be forged, api key requests are
+    // never sent by the browser on its own
+    if req.get_identity().is_err() {
+        return Ok(());
+    }
+
+    // Browsers may leave out both headers, for example under a strict referrer policy, so a
+    // cookie authenticated request without them can't be told apart from a forged one
+    let origin = request_origin(req).ok_or(ServiceError::Forbidden)?;
+
+    let allowed_origins = allowed_origins();
+    if !allowed_origins.is_empty() && !allowed_origins.contains(&origin) {
+        return Err(ServiceError::Forbidden);
+    }
+
+    let expected_token = req
+        .get_session()
+        .get::<String>(CSRF_SESSION_KEY)
+        .map_err(|_| ServiceError::InternalServerError("Could not get CSRF Session".into()))?;
+    let token = req
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok());
+
+    match (expected_token, token) {
+        (Some(expected_token), Some(token)) if expected_token == token => Ok(()),
+        _ => Err(ServiceError::Forbidden),
+    }
+}
+
+pub struct CsrfMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for CsrfMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // Clone the Rc pointers so we can move them into the async block.
+        let srv = self.service.clone();
+        Box::pin(async move {
+            check_csrf_token(&req)?;
+
+            let res = srv.call(req).await?;
+            Ok(res)
+        })
+    }
+}
+
+/// Requires an allowed `Origin` or `Referer` and a valid `X-CSRF-Token` header on state mutating
+/// requests authenticated with the session cookie. Requests authenticated with an api key are not
+/// checked.
+pub struct CsrfMiddlewareFactory;
+
+impl<S, B> Transform<S, ServiceRequest> for CsrfMiddlewareFactory
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = CsrfMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CsrfMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
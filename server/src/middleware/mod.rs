@@ -1,3 +1,4 @@
 pub mod api_version;
 pub mod auth_middleware;
+pub mod csrf_middleware;
 pub mod json_middleware;
@@ -0,0 +1,128 @@
+use super::http_operator::external_http_client;
+use crate::{
+    data::models::{CompletionAction, SlackConfig, WebhookConfig},
+    errors::ServiceError,
+};
+use serde::{Deserialize, Serialize};
+
+/// Value of `alert` in the completion payload of crawls whose error rate exceeded
+/// `notify_on_error_threshold`
+pub const ERROR_THRESHOLD_EXCEEDED_ALERT: &str = "error_threshold_exceeded";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CrawlCompletionPayload {
+    pub scrape_id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub url: String,
+    pub pages_crawled: usize,
+    pub pages_failed: usize,
+    pub chunks_created: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alert: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_rate: Option<f32>,
+}
+
+/// Share of the crawled pages which failed, if it is above `threshold`
+pub fn error_rate_above_threshold(
+    pages_failed: usize,
+    pages_crawled: usize,
+    threshold: Option<f32>,
+) -> Option<f32> {
+    let threshold = threshold?;
+    if pages_crawled == 0 {
+        return None;
+    }
+
+    let error_rate = pages_failed as f32 / pages_crawled as f32;
+    (error_rate > threshold).then_some(error_rate)
+}
+
+pub async fn dispatch_completion_actions(
+    actions: &[CompletionAction],
+    payload: &CrawlCompletionPayload,
+) {
+    for action in actions {
+        let result = match action {
+            CompletionAction::Webhook(config) => send_completion_webhook(config, payload).await,
+            CompletionAction::SlackNotification(config) => {
+                send_slack_notification(config, payload).await
+            }
+        };
+
+        if let Err(err) = result {
+            log::error!(
+                "Failed to run completion action {:?} for {}: {:?}",
+                action,
+                payload.scrape_id,
+                err
+            );
+        }
+    }
+}
+
+async fn send_completion_webhook(
+    config: &WebhookConfig,
+    payload: &CrawlCompletionPayload,
+) -> Result<(), ServiceError> {
+    let client = external_http_client(&config.url).await?;
+    let mut request = client.post(&config.url).json(payload);
+
+    if let Some(ref headers) = config.headers {
+        for (key, value) in headers {
+            request = request.header(key, value);
+        }
+    }
+
+    let response = request.send().await.map_err(|e| {
+        log::error!("Error sending completion webhook: {:?}", e);
+        ServiceError::InternalServerError("Error sending completion webhook".to_string())
+    })?;
+
+    if !response.status().is_success() {
+        return Err(ServiceError::InternalServerError(format!(
+            "Completion webhook returned status {}",
+            response.status()
+        )));
+    }
+
+    Ok(())
+}
+
+async fn send_slack_notification(
+    config: &SlackConfig,
+    payload: &CrawlCompletionPayload,
+) -> Result<(), ServiceError> {
+    let mut text = format!(
+        "Crawl of {} finished: {} pages crawled, {} chunks created (dataset {})",
+        payload.url, payload.pages_crawled, payload.chunks_created, payload.dataset_id
+    );
+    if let Some(error_rate) = payload.error_rate {
+        text.push_str(&format!(
+            "\n:warning: {} of {} pages failed ({:.0}% error rate)",
+            payload.pages_failed,
+            payload.pages_crawled,
+            error_rate * 100.0
+        ));
+    }
+
+    let client = external_http_client(&config.webhook_url).await?;
+    let response = client
+        .post(&config.webhook_url)
+        .json(&serde_json::json!({ "text": text }))
+        .send()
+        .await
+        .map_err(|e| {
+            log::error!("Error sending slack notification: {:?}", e);
+            ServiceError::InternalServerError("Error sending slack notification".to_string())
+        })?;
+
+    if !response.status().is_success() {
+        return Err(ServiceError::InternalServerError(format!(
+            "Slack webhook returned status {}",
+            response.status()
+        )));
+    }
+
+    Ok(())
+}
@@ -1,8 +1,32 @@
+use crate::data::models::ActionType;
+use crate::data::models::AuditCrawlEvent;
+use crate::data::models::ChunkStrategy;
+use crate::data::models::CrawlAuditAction;
+use crate::data::models::CrawlCostEstimate;
+use crate::data::models::CrawlDiff;
+use crate::data::models::CrawlDiffPG;
+use crate::data::models::CrawlError;
+use crate::data::models::CrawlEvent;
+use crate::data::models::CrawlIndexedPage;
+use crate::data::models::CrawlMaintenanceMode;
 use crate::data::models::CrawlOptions;
+use crate::data::models::CrawlOptionsMigrator;
+use crate::data::models::CrawlOptionsSnapshot;
 use crate::data::models::CrawlStatus;
+use crate::data::models::CrawlSummary;
+use crate::data::models::CrawledUrlIndex;
+use crate::data::models::DatasetConfiguration;
 use crate::data::models::FirecrawlCrawlRequest;
+use crate::data::models::LinkedDocumentMessage;
+use crate::data::models::MigrationResult;
+use crate::data::models::OpenAIEnrichConfig;
+use crate::data::models::OverlapDirection;
+use crate::data::models::OverlapStrategy;
 use crate::data::models::RedisPool;
-use crate::handlers::chunk_handler::CrawlInterval;
+use crate::data::models::TitleExtractionStrategy;
+use crate::data::models::UnifiedId;
+use crate::data::models::CRAWL_OPTIONS_VERSION;
+use crate::handlers::chunk_handler::{ChunkReqPayload, CrawlInterval};
 use crate::{
     data::models::{CrawlRequest, CrawlRequestPG, Pool, ScrapeOptions},
     errors::ServiceError,
@@ -10,14 +34,36 @@ use crate::{
 use actix_web::web;
 use diesel::prelude::*;
 use diesel::QueryDsl;
-use diesel_async::RunQueryDsl;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use flate2::{
+    write::{GzDecoder, GzEncoder},
+    Compression,
+};
+use once_cell::sync::Lazy;
+use openai_dive::v1::api::Client;
+use openai_dive::v1::resources::chat::{ChatCompletionParameters, ChatMessage, ChatMessageContent};
 use regex::Regex;
 use reqwest::Url;
+use s3::Bucket;
 use scraper::Html;
 use scraper::Selector;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::time::{Duration, Instant};
+use tokio::sync::{RwLock, Semaphore};
+use unicode_segmentation::UnicodeSegmentation;
 
+use super::dataset_operator::get_dataset_by_id_query;
+use super::file_operator::get_aws_bucket;
+use super::http_operator::{external_http_client, read_external_response_body};
 use super::parse_operator::convert_html_to_text;
+use super::parse_operator::escape_html;
+use super::pdf_operator::convert_pdf_to_document;
+use super::qdrant_operator::{
+    delete_points_from_qdrant, get_qdrant_collection_from_dataset_config,
+};
+use super::user_operator::hash_function;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct IngestResult {
@@ -30,21 +76,42 @@ pub struct IngestResult {
     pub expires_at: String,
     pub next: Option<String>,
     pub data: Option<Vec<Option<Document>>>,
+    /// Urls of the crawled pages which did not return a 200 status. Not sent by Firecrawl, filled
+    /// in once all the pages of a crawl are collected.
+    #[serde(rename = "failedUrls", default)]
+    pub failed_urls: Option<Vec<String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 #[serde(rename_all = "lowercase")]
 pub enum Status {
     Scraping,
+    /// Every page of the crawl was crawled successfully
     Completed,
+    /// The crawl finished but some of its pages failed, they are listed in `failed_urls`
+    Partial,
     Failed,
     Cancelled,
 }
 
+/// Urls of the documents which did not return a 200 status
+pub fn get_failed_urls(documents: &[Option<Document>]) -> Vec<String> {
+    documents
+        .iter()
+        .flatten()
+        .filter(|document| document.metadata.status_code != Some(200))
+        .filter_map(|document| document.metadata.source_url.clone())
+        .collect()
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Document {
+    /// Mime type of the crawled resource, used to pick how the document is processed. Taken
+    /// from the page metadata for Firecrawl documents.
+    #[serde(rename = "contentType")]
+    pub content_type: Option<String>,
     pub markdown: Option<String>,
-    pub extract: Option<String>,
+    pub extract: Option<serde_json::Value>,
     pub html: Option<String>,
     #[serde(rename = "rawHtml")]
     pub raw_html: Option<String>,
@@ -53,7 +120,64 @@ pub struct Document {
     pub metadata: Metadata,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// How a crawled [`Document`] is processed before it is chunked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentKind {
+    Html,
+    Pdf,
+    Markdown,
+    Json,
+}
+
+impl DocumentKind {
+    pub fn from_content_type(content_type: &str) -> Option<DocumentKind> {
+        let mime_type = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase();
+        match mime_type.as_str() {
+            "text/html" | "application/xhtml+xml" => Some(DocumentKind::Html),
+            "application/pdf" => Some(DocumentKind::Pdf),
+            "text/markdown" | "text/x-markdown" => Some(DocumentKind::Markdown),
+            "application/json" => Some(DocumentKind::Json),
+            mime_type if mime_type.ends_with("+json") => Some(DocumentKind::Json),
+            _ => None,
+        }
+    }
+
+    pub fn from_url(url: &str) -> Option<DocumentKind> {
+        let path = Url::parse(url).ok()?.path().to_lowercase();
+        let extension = path.rsplit_once('.')?.1.to_string();
+        match extension.as_str() {
+            "html" | "htm" => Some(DocumentKind::Html),
+            "pdf" => Some(DocumentKind::Pdf),
+            "md" | "markdown" => Some(DocumentKind::Markdown),
+            "json" => Some(DocumentKind::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Picks how a document is processed from its content type, falling back to the extension of
+/// its url and then to html.
+pub fn get_document_kind(document: &Document) -> DocumentKind {
+    document
+        .content_type
+        .as_deref()
+        .and_then(DocumentKind::from_content_type)
+        .or_else(|| {
+            document
+                .metadata
+                .source_url
+                .as_deref()
+                .and_then(DocumentKind::from_url)
+        })
+        .unwrap_or(DocumentKind::Html)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Metadata {
     pub title: Option<String>,
     pub description: Option<String>,
@@ -112,8 +236,25 @@ pub struct Metadata {
     pub source_url: Option<String>,
     #[serde(rename = "statusCode")]
     pub status_code: Option<u32>,
+    #[serde(rename = "contentType")]
+    pub content_type: Option<String>,
     pub error: Option<String>,
     pub site_map: Option<Sitemap>,
+    /// Set by the crawl worker from the page html, see `parse_operator::detect_paywall`
+    pub is_paywalled: Option<bool>,
+    /// Set by the crawl worker from the `<link rel="icon">` elements of the page
+    pub favicon_url: Option<String>,
+    /// Number of image descriptions extracted from each page of a pdf, set by
+    /// `pdf_operator::convert_pdf_to_document` when `include_pdf_images` is enabled
+    pub pdf_image_counts: Option<Vec<usize>>,
+    /// Set by the crawl worker from the page html, see `extract_breadcrumbs`
+    pub breadcrumbs: Option<Vec<BreadcrumbItem>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BreadcrumbItem {
+    pub name: String,
+    pub url: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -121,21 +262,316 @@ pub struct Sitemap {
     pub changefreq: String,
 }
 
+pub fn validate_crawl_options(crawl_options: &CrawlOptions) -> Result<(), ServiceError> {
+    if let Some(ref skip_urls_matching) = crawl_options.skip_urls_matching {
+        compile_url_patterns(skip_urls_matching)?;
+    }
+
+    if let Some(ref exclude_url_patterns) = crawl_options.exclude_url_patterns {
+        compile_url_patterns(exclude_url_patterns)?;
+    }
+
+    if let Some(ref allowed_schemes) = crawl_options.allowed_schemes {
+        if allowed_schemes.is_empty() {
+            return Err(ServiceError::BadRequest(
+                "allowed_schemes cannot be empty".to_string(),
+            ));
+        }
+
+        if let Some(scheme) = allowed_schemes
+            .iter()
+            .find(|scheme| !DEFAULT_ALLOWED_SCHEMES.contains(&scheme.to_lowercase().as_str()))
+        {
+            return Err(ServiceError::BadRequest(format!(
+                "Unsupported scheme {} in allowed_schemes, only https and http can be crawled",
+                scheme
+            )));
+        }
+
+        let site_url = crawl_options.site_url.as_deref().unwrap_or_default();
+        if Url::parse(site_url).is_ok() && !url_has_allowed_scheme(site_url, Some(allowed_schemes))
+        {
+            return Err(ServiceError::BadRequest(
+                "The scheme of site_url is not in allowed_schemes".to_string(),
+            ));
+        }
+    }
+
+    if let Some(ref seed_urls) = crawl_options.seed_urls {
+        if let Some(seed_url) = seed_urls.iter().find(|seed_url| {
+            !url_has_allowed_scheme(seed_url, crawl_options.allowed_schemes.as_deref())
+        }) {
+            return Err(ServiceError::BadRequest(format!(
+                "Invalid seed url {}, seed urls must be urls with an allowed scheme",
+                seed_url
+            )));
+        }
+    }
+
+    if let Some(ScrapeOptions::GitHub(ref github_options)) = crawl_options.scrape_options {
+        let is_valid_repo = github_options
+            .repo
+            .split_once('/')
+            .is_some_and(|(owner, name)| {
+                !owner.is_empty() && !name.is_empty() && !name.contains('/')
+            });
+        if !is_valid_repo {
+            return Err(ServiceError::BadRequest(
+                "GitHub repo must be in the form owner/name".to_string(),
+            ));
+        }
+
+        if github_options.branch.trim().is_empty() {
+            return Err(ServiceError::BadRequest(
+                "GitHub branch cannot be empty".to_string(),
+            ));
+        }
+    }
+
+    if let Some(ref structured_output_schema) = crawl_options.structured_output_schema {
+        if !structured_output_schema.is_object() {
+            return Err(ServiceError::BadRequest(
+                "structured_output_schema must be a JSON schema object".to_string(),
+            ));
+        }
+    }
+
+    if let Some(ref enrich_config) = crawl_options.enrich_with_openai {
+        if enrich_config.model.trim().is_empty() {
+            return Err(ServiceError::BadRequest(
+                "enrich_with_openai model cannot be empty".to_string(),
+            ));
+        }
+
+        if enrich_config.max_tokens == 0 {
+            return Err(ServiceError::BadRequest(
+                "enrich_with_openai max_tokens must be greater than 0".to_string(),
+            ));
+        }
+    }
+
+    if let Some(threshold) = crawl_options.notify_on_error_threshold {
+        if !(0.0..=1.0).contains(&threshold) {
+            return Err(ServiceError::BadRequest(
+                "notify_on_error_threshold must be between 0.0 and 1.0".to_string(),
+            ));
+        }
+    }
+
+    if let Some(ref linked_document_types) = crawl_options.linked_document_types {
+        for document_type in linked_document_types {
+            if document_type.split_once('/').is_none() {
+                return Err(ServiceError::BadRequest(format!(
+                    "Invalid linked document type {}, linked document types must be mime types",
+                    document_type
+                )));
+            }
+        }
+    }
+
+    if let Some(ref smart_dedup_strategy) = crawl_options.smart_dedup_strategy {
+        if !(0.0..=1.0).contains(&smart_dedup_strategy.similarity_threshold) {
+            return Err(ServiceError::BadRequest(
+                "smart_dedup_strategy similarity_threshold must be between 0.0 and 1.0".to_string(),
+            ));
+        }
+    }
+
+    if let Some(ref chunk_overlap_strategy) = crawl_options.chunk_overlap_strategy {
+        if chunk_overlap_strategy.words == 0 {
+            return Err(ServiceError::BadRequest(
+                "chunk_overlap_strategy words must be greater than 0".to_string(),
+            ));
+        }
+    }
+
+    if let Some(TitleExtractionStrategy::Custom(ref selector)) =
+        crawl_options.title_extraction_strategy
+    {
+        Selector::parse(selector).map_err(|err| {
+            ServiceError::BadRequest(format!(
+                "Invalid title_extraction_strategy selector {}: {:?}",
+                selector, err
+            ))
+        })?;
+    }
+
+    if let Some(ref tag_prefix) = crawl_options.tag_prefix {
+        if tag_prefix.contains(char::is_whitespace) {
+            return Err(ServiceError::BadRequest(
+                "tag_prefix cannot contain spaces".to_string(),
+            ));
+        }
+
+        if tag_prefix.chars().count() > 32 {
+            return Err(ServiceError::BadRequest(
+                "tag_prefix must be at most 32 characters".to_string(),
+            ));
+        }
+    }
+
+    match crawl_options.chunk_strategy {
+        Some(ChunkStrategy::FixedSize { words: 0 }) => {
+            return Err(ServiceError::BadRequest(
+                "chunk_strategy words must be greater than 0".to_string(),
+            ));
+        }
+        Some(ChunkStrategy::SentenceBased { max_sentences: 0 }) => {
+            return Err(ServiceError::BadRequest(
+                "chunk_strategy max_sentences must be greater than 0".to_string(),
+            ));
+        }
+        _ => {}
+    }
+
+    if let Some(ref chunk_by_element_id) = crawl_options.chunk_by_element_id {
+        Selector::parse(chunk_by_element_id).map_err(|err| {
+            ServiceError::BadRequest(format!(
+                "Invalid chunk_by_element_id selector {}: {:?}",
+                chunk_by_element_id, err
+            ))
+        })?;
+    }
+
+    if let Some(ref page_js_actions) = crawl_options.page_js_actions {
+        for js_action in page_js_actions {
+            match (&js_action.type_, &js_action.selector) {
+                (ActionType::Click | ActionType::WaitForSelector, None) => {
+                    return Err(ServiceError::BadRequest(
+                        "click and wait_for_selector page_js_actions require a selector"
+                            .to_string(),
+                    ));
+                }
+                (_, Some(selector)) => {
+                    Selector::parse(selector).map_err(|err| {
+                        ServiceError::BadRequest(format!(
+                            "Invalid selector {} in page_js_actions: {:?}",
+                            selector, err
+                        ))
+                    })?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if let Some(ref metadata_fields_to_index) = crawl_options.metadata_fields_to_index {
+        for field in metadata_fields_to_index {
+            if !KNOWN_METADATA_FIELDS.contains(&field.as_str()) {
+                log::warn!(
+                    "Unknown field {} in metadata_fields_to_index, it will be ignored",
+                    field
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Keys of the serialized page [`Metadata`] which can be selected with `metadata_fields_to_index`.
+/// Every field added to [`Metadata`] must be listed here, which the tests check.
+pub const KNOWN_METADATA_FIELDS: &[&str] = &[
+    "title",
+    "description",
+    "language",
+    "keywords",
+    "robots",
+    "ogTitle",
+    "ogDescription",
+    "ogUrl",
+    "ogImage",
+    "ogAudio",
+    "ogDeterminer",
+    "ogLocale",
+    "ogLocaleAlternate",
+    "ogSiteName",
+    "ogVideo",
+    "dcTermsCreated",
+    "dcDateCreated",
+    "dcDate",
+    "dcTermsType",
+    "dcType",
+    "dcTermsAudience",
+    "dcTermsSubject",
+    "dcSubject",
+    "dcDescription",
+    "dcTermsKeywords",
+    "modifiedTime",
+    "publishedTime",
+    "articleTag",
+    "articleSection",
+    "sourceURL",
+    "statusCode",
+    "contentType",
+    "error",
+    "site_map",
+    "is_paywalled",
+    "favicon_url",
+    "pdf_image_counts",
+    "breadcrumbs",
+];
+
+/// Returns the non-null fields of the serialized page metadata whose keys are in `fields`.
+pub fn filter_page_metadata(
+    metadata: &Metadata,
+    fields: &[String],
+) -> serde_json::Map<String, serde_json::Value> {
+    match serde_json::to_value(metadata) {
+        Ok(serde_json::Value::Object(metadata)) => metadata
+            .into_iter()
+            .filter(|(key, value)| !value.is_null() && fields.contains(key))
+            .collect(),
+        _ => serde_json::Map::new(),
+    }
+}
+
+pub fn compile_url_patterns(patterns: &[String]) -> Result<Vec<Regex>, ServiceError> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Regex::new(pattern).map_err(|err| {
+                ServiceError::BadRequest(format!("Invalid URL pattern {}: {}", pattern, err))
+            })
+        })
+        .collect()
+}
+
+pub fn url_matches_any_pattern(url: &str, patterns: &[Regex]) -> bool {
+    patterns.iter().any(|pattern| pattern.is_match(url))
+}
+
+/// Starts crawling the site of `crawl_options` and returns the scrape id of the crawl
+async fn start_scrape(crawl_options: &CrawlOptions) -> Result<uuid::Uuid, ServiceError> {
+    // GitHub repositories are read by the crawl worker itself, so they get their own scrape id
+    match crawl_options.scrape_options {
+        Some(ScrapeOptions::Shopify(_)) => Ok(uuid::Uuid::nil()),
+        Some(ScrapeOptions::GitHub(_)) => Ok(uuid::Uuid::new_v4()),
+        _ => crawl_site(crawl_options.clone())
+            .await
+            .map_err(|err| ServiceError::BadRequest(format!("Could not crawl site: {}", err))),
+    }
+}
+
 pub async fn crawl(
     crawl_options: CrawlOptions,
     pool: web::Data<Pool>,
     redis_pool: web::Data<RedisPool>,
     dataset_id: uuid::Uuid,
+    created_by: Option<uuid::Uuid>,
 ) -> Result<uuid::Uuid, ServiceError> {
-    let scrape_id = if let Some(ScrapeOptions::Shopify(_)) = crawl_options.scrape_options {
-        uuid::Uuid::nil()
-    } else {
-        crawl_site(crawl_options.clone())
-            .await
-            .map_err(|err| ServiceError::BadRequest(format!("Could not crawl site: {}", err)))?
-    };
+    validate_crawl_options(&crawl_options)?;
+    let scrape_id = start_scrape(&crawl_options).await?;
 
-    create_crawl_request(crawl_options, dataset_id, scrape_id, pool, redis_pool).await?;
+    create_crawl_request(
+        crawl_options,
+        dataset_id,
+        scrape_id,
+        created_by,
+        pool,
+        redis_pool,
+    )
+    .await?;
 
     Ok(scrape_id)
 }
@@ -160,13 +596,58 @@ pub async fn get_crawl_request(
             scrape_id,
             dataset_id,
             created_at,
+            crawl_duration_ms,
+            github_token,
         ))
         .filter(scrape_id.eq(crawl_id))
         .first::<CrawlRequestPG>(&mut conn)
         .await
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => {
+                ServiceError::NotFound(format!("Crawl request {} not found", crawl_id))
+            }
+            e => ServiceError::InternalServerError(e.to_string()),
+        })?;
+
+    request.try_into()
+}
+
+/// Looks up a crawl request by its row id. Unlike the scrape id, which is nil until Firecrawl has
+/// accepted the crawl, the row id is known as soon as the request is created.
+pub async fn get_crawl_request_by_id(
+    crawl_request_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<CrawlRequest, ServiceError> {
+    use crate::data::schema::crawl_requests::dsl as crawl_requests_table;
+    let mut conn = pool
+        .get()
+        .await
         .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+    let request = crawl_requests_table::crawl_requests
+        .select((
+            crawl_requests_table::id,
+            crawl_requests_table::url,
+            crawl_requests_table::status,
+            crawl_requests_table::next_crawl_at,
+            crawl_requests_table::interval,
+            crawl_requests_table::crawl_options,
+            crawl_requests_table::scrape_id,
+            crawl_requests_table::dataset_id,
+            crawl_requests_table::created_at,
+            crawl_requests_table::crawl_duration_ms,
+            crawl_requests_table::github_token,
+        ))
+        .filter(crawl_requests_table::id.eq(crawl_request_id))
+        .first::<CrawlRequestPG>(&mut conn)
+        .await
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => {
+                ServiceError::NotFound(format!("Crawl request {} not found", crawl_request_id))
+            }
+            e => ServiceError::InternalServerError(e.to_string()),
+        })?;
 
-    Ok(request.into())
+    request.try_into()
 }
 
 pub async fn get_crawl_request_by_dataset_id_query(
@@ -180,6 +661,7 @@ pub async fn get_crawl_request_by_dataset_id_query(
         .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
     let request: Option<CrawlRequestPG> = crawl_requests_table::crawl_requests
         .filter(crawl_requests_table::dataset_id.eq(dataset_id))
+        .order_by(crawl_requests_table::created_at.desc())
         .select((
             crawl_requests_table::id,
             crawl_requests_table::url,
@@ -190,349 +672,2650 @@ pub async fn get_crawl_request_by_dataset_id_query(
             crawl_requests_table::scrape_id,
             crawl_requests_table::dataset_id,
             crawl_requests_table::created_at,
+            crawl_requests_table::crawl_duration_ms,
+            crawl_requests_table::github_token,
         ))
         .first(&mut conn)
         .await
         .optional()
         .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
 
-    Ok(request.map(|req| req.into()))
+    request.map(CrawlRequest::try_from).transpose()
 }
 
-pub async fn get_crawl_requests_to_rerun(
-    pool: web::Data<Pool>,
-) -> Result<Vec<CrawlRequest>, ServiceError> {
-    use crate::data::schema::crawl_requests::dsl::*;
-    let mut conn = pool
+const CRAWL_MAINTENANCE_KEY: &str = "crawl:maintenance";
+
+/// Pauses or resumes the scheduling of crawls for the whole instance, e.g. during database
+/// migrations or Firecrawl outages. Crawls which are already queued are still processed.
+pub async fn set_crawl_maintenance_mode(
+    enabled: bool,
+    reason: &str,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<(), ServiceError> {
+    let mut redis_conn = redis_pool
         .get()
         .await
         .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
-    let requests = crawl_requests
-        .select((
-            id,
-            url,
-            status,
-            next_crawl_at,
-            interval,
-            crawl_options,
-            scrape_id,
-            dataset_id,
-            created_at,
-        ))
-        .filter(next_crawl_at.le(chrono::Utc::now().naive_utc()))
-        .load::<CrawlRequestPG>(&mut conn)
+
+    if enabled {
+        let maintenance_mode = CrawlMaintenanceMode {
+            enabled,
+            reason: Some(reason.to_string()).filter(|reason| !reason.is_empty()),
+            enabled_at: Some(chrono::Utc::now().naive_utc()),
+        };
+        redis::cmd("SET")
+            .arg(CRAWL_MAINTENANCE_KEY)
+            .arg(serde_json::to_string(&maintenance_mode).unwrap())
+            .query_async::<redis::aio::MultiplexedConnection, ()>(&mut *redis_conn)
+            .await
+            .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+    } else {
+        redis::cmd("DEL")
+            .arg(CRAWL_MAINTENANCE_KEY)
+            .query_async::<redis::aio::MultiplexedConnection, usize>(&mut *redis_conn)
+            .await
+            .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+    }
+
+    Ok(())
+}
+
+pub async fn get_crawl_maintenance_mode(
+    redis_pool: web::Data<RedisPool>,
+) -> Result<CrawlMaintenanceMode, ServiceError> {
+    let mut redis_conn = redis_pool
+        .get()
         .await
         .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
-    Ok(requests.into_iter().map(|r| r.into()).collect())
+
+    let maintenance_mode = redis::cmd("GET")
+        .arg(CRAWL_MAINTENANCE_KEY)
+        .query_async::<redis::aio::MultiplexedConnection, Option<String>>(&mut *redis_conn)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    Ok(maintenance_mode
+        .and_then(|maintenance_mode| serde_json::from_str(&maintenance_mode).ok())
+        .unwrap_or(CrawlMaintenanceMode {
+            enabled: false,
+            reason: None,
+            enabled_at: None,
+        }))
 }
 
-pub async fn create_crawl_request(
-    crawl_options: CrawlOptions,
-    dataset_id: uuid::Uuid,
-    scrape_id: uuid::Uuid,
+/// Crawl requests which are due to be rerun. Crawls of datasets whose organization is suspended are
+/// skipped, and none are returned while crawl maintenance mode is enabled.
+pub async fn get_crawl_requests_to_rerun(
     pool: web::Data<Pool>,
     redis_pool: web::Data<RedisPool>,
-) -> Result<uuid::Uuid, ServiceError> {
+) -> Result<Vec<CrawlRequest>, ServiceError> {
     use crate::data::schema::crawl_requests::dsl as crawl_requests_table;
+    use crate::data::schema::datasets::dsl as datasets_table;
+    use crate::data::schema::organizations::dsl as organizations_table;
 
-    let interval = match crawl_options.interval {
-        Some(CrawlInterval::Daily) => std::time::Duration::from_secs(60 * 60 * 24),
-        Some(CrawlInterval::Weekly) => std::time::Duration::from_secs(60 * 60 * 24 * 7),
-        Some(CrawlInterval::Monthly) => std::time::Duration::from_secs(60 * 60 * 24 * 30),
-        None => std::time::Duration::from_secs(60 * 60 * 24),
-    };
-
-    let new_crawl_request: CrawlRequestPG = CrawlRequest {
-        id: uuid::Uuid::new_v4(),
-        url: crawl_options.site_url.clone().unwrap_or_default(),
-        status: CrawlStatus::Pending,
-        interval,
-        next_crawl_at: chrono::Utc::now().naive_utc(),
-        crawl_options,
-        scrape_id,
-        dataset_id,
-        created_at: chrono::Utc::now().naive_utc(),
-        attempt_number: 0,
+    let maintenance_mode = get_crawl_maintenance_mode(redis_pool).await?;
+    if maintenance_mode.enabled {
+        log::info!(
+            "Crawl maintenance mode is enabled, not rerunning crawls: {}",
+            maintenance_mode.reason.unwrap_or_default()
+        );
+        return Ok(vec![]);
     }
-    .into();
 
     let mut conn = pool
         .get()
         .await
         .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
-
-    diesel::insert_into(crawl_requests_table::crawl_requests)
-        .values(&new_crawl_request)
-        .execute(&mut conn)
+    let requests = crawl_requests_table::crawl_requests
+        .inner_join(datasets_table::datasets.inner_join(organizations_table::organizations))
+        .select((
+            crawl_requests_table::id,
+            crawl_requests_table::url,
+            crawl_requests_table::status,
+            crawl_requests_table::next_crawl_at,
+            crawl_requests_table::interval,
+            crawl_requests_table::crawl_options,
+            crawl_requests_table::scrape_id,
+            crawl_requests_table::dataset_id,
+            crawl_requests_table::created_at,
+            crawl_requests_table::crawl_duration_ms,
+            crawl_requests_table::github_token,
+        ))
+        .filter(crawl_requests_table::next_crawl_at.le(chrono::Utc::now().naive_utc()))
+        .filter(organizations_table::status.ne("suspended"))
+        .load::<CrawlRequestPG>(&mut conn)
         .await
         .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+    // A crawl request whose options can't be read is skipped so it doesn't hold up the others
+    Ok(requests
+        .into_iter()
+        .filter_map(|request| {
+            let crawl_request_id = request.id;
+            CrawlRequest::try_from(request)
+                .map_err(|e| {
+                    log::error!(
+                        "Error reading crawl request {} to rerun: {:?}",
+                        crawl_request_id,
+                        e
+                    );
+                })
+                .ok()
+        })
+        .collect())
+}
 
-    let serialized_message =
-        serde_json::to_string(&CrawlRequest::from(new_crawl_request.clone())).unwrap();
-    let mut redis_conn = redis_pool
+/// Deletes the chunks of the pages indexed by the crawl with a single `DELETE`, along with their
+/// points in Qdrant. Crawled chunks are matched by their link to the urls recorded in
+/// `crawl_indexed_pages` for the crawl. Returns the number of deleted chunks.
+pub async fn delete_chunks_for_crawl(
+    scrape_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<u64, ServiceError> {
+    let deleted = delete_crawl_page_chunks(scrape_id, dataset_id, vec![], pool.clone()).await?;
+
+    let mut conn = pool
         .get()
         .await
         .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+    delete_crawled_urls_of_crawl(scrape_id, &mut conn).await?;
 
-    redis::cmd("lpush")
-        .arg("scrape_queue")
-        .arg(&serialized_message)
-        .query_async::<redis::aio::MultiplexedConnection, usize>(&mut *redis_conn)
-        .await
-        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+    Ok(deleted)
+}
 
-    Ok(new_crawl_request.scrape_id)
+/// Deletes the chunks of the pages indexed by the crawl which a reindex did not produce again.
+/// The chunks with one of `kept_tracking_ids` were upserted by the reindex and are kept. Returns
+/// the number of deleted chunks.
+pub async fn delete_stale_chunks_for_crawl(
+    scrape_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    kept_tracking_ids: Vec<String>,
+    pool: web::Data<Pool>,
+) -> Result<u64, ServiceError> {
+    delete_crawl_page_chunks(scrape_id, dataset_id, kept_tracking_ids, pool).await
 }
 
-pub async fn update_crawl_status(
-    crawl_id: uuid::Uuid,
-    status: CrawlStatus,
+async fn delete_crawl_page_chunks(
+    scrape_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    kept_tracking_ids: Vec<String>,
     pool: web::Data<Pool>,
-) -> Result<(), ServiceError> {
-    use crate::data::schema::crawl_requests::dsl as crawl_requests_table;
+) -> Result<u64, ServiceError> {
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+    use crate::data::schema::crawl_indexed_pages::dsl as crawl_indexed_pages_table;
+
+    let dataset = get_dataset_by_id_query(UnifiedId::TrieveUuid(dataset_id), pool.clone()).await?;
+    let dataset_config = DatasetConfiguration::from_json(dataset.server_configuration);
 
     let mut conn = pool
         .get()
         .await
         .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
 
-    diesel::update(
-        crawl_requests_table::crawl_requests.filter(crawl_requests_table::scrape_id.eq(crawl_id)),
+    let crawled_urls = crawl_indexed_pages_table::crawl_indexed_pages
+        .select(crawl_indexed_pages_table::url.nullable())
+        .filter(crawl_indexed_pages_table::crawl_id.eq(scrape_id))
+        .filter(crawl_indexed_pages_table::dataset_id.eq(dataset_id));
+
+    let deleted_points = diesel::delete(
+        chunk_metadata_columns::chunk_metadata
+            .filter(chunk_metadata_columns::dataset_id.eq(dataset_id))
+            .filter(chunk_metadata_columns::link.eq_any(crawled_urls))
+            .filter(
+                chunk_metadata_columns::tracking_id
+                    .is_null()
+                    .or(chunk_metadata_columns::tracking_id.ne_all(kept_tracking_ids)),
+            ),
     )
-    .set(crawl_requests_table::status.eq(status.to_string()))
-    .execute(&mut conn)
+    .returning(chunk_metadata_columns::qdrant_point_id)
+    .get_results::<uuid::Uuid>(&mut conn)
     .await
-    .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+    .map_err(|e| {
+        log::error!("Error deleting chunks of crawl {}: {:?}", scrape_id, e);
+        ServiceError::InternalServerError("Error deleting chunks of crawl".to_string())
+    })?;
+
+    let deleted = deleted_points.len() as u64;
+    delete_points_from_qdrant(
+        deleted_points,
+        get_qdrant_collection_from_dataset_config(&dataset_config),
+    )
+    .await?;
+
+    Ok(deleted)
+}
+
+/// Removes the urls indexed by a crawl from the crawled url index of its organization, so that
+/// the other datasets of the organization no longer skip them. Urls which another crawl of the
+/// same dataset also indexed are kept.
+async fn delete_crawled_urls_of_crawl(
+    scrape_id: uuid::Uuid,
+    conn: &mut diesel_async::AsyncPgConnection,
+) -> Result<(), ServiceError> {
+    diesel::sql_query(
+        "DELETE FROM crawled_url_index
+        WHERE (dataset_id, rtrim(url, '/')) IN (
+            SELECT pages.dataset_id, rtrim(pages.url, '/')
+            FROM crawl_indexed_pages pages
+            WHERE pages.crawl_id = $1
+            AND NOT EXISTS (
+                SELECT 1 FROM crawl_indexed_pages other_pages
+                WHERE other_pages.dataset_id = pages.dataset_id
+                AND other_pages.url = pages.url
+                AND other_pages.crawl_id <> $1
+            )
+        )",
+    )
+    .bind::<diesel::sql_types::Uuid, _>(scrape_id)
+    .execute(conn)
+    .await
+    .map_err(|e| {
+        log::error!(
+            "Error deleting crawled urls of crawl {}: {:?}",
+            scrape_id,
+            e
+        );
+        ServiceError::InternalServerError("Error deleting crawled urls of crawl".to_string())
+    })?;
 
     Ok(())
 }
 
-pub async fn update_next_crawl_at(
-    crawl_id: uuid::Uuid,
-    next_crawl_at: chrono::NaiveDateTime,
+/// Deletes the crawl requests of a crawl, which stops it from being rerun
+pub async fn delete_crawl_request_query(
+    scrape_id: uuid::Uuid,
+    deleted_by: Option<uuid::Uuid>,
     pool: web::Data<Pool>,
 ) -> Result<(), ServiceError> {
     use crate::data::schema::crawl_requests::dsl as crawl_requests_table;
+
     let mut conn = pool
         .get()
         .await
         .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
-    diesel::update(
-        crawl_requests_table::crawl_requests.filter(crawl_requests_table::scrape_id.eq(crawl_id)),
+
+    conn.transaction::<_, ServiceError, _>(|conn| {
+        async move {
+            let deleted_crawl_requests = diesel::delete(
+                crawl_requests_table::crawl_requests
+                    .filter(crawl_requests_table::scrape_id.eq(scrape_id)),
+            )
+            .returning((
+                crawl_requests_table::id,
+                crawl_requests_table::dataset_id,
+                crawl_requests_table::crawl_options,
+            ))
+            .get_results::<(uuid::Uuid, uuid::Uuid, serde_json::Value)>(conn)
+            .await
+            .map_err(|e| {
+                log::error!("Error deleting crawl request {}: {:?}", scrape_id, e);
+                ServiceError::InternalServerError("Error deleting crawl request".to_string())
+            })?;
+
+            delete_crawled_urls_of_crawl(scrape_id, conn).await?;
+
+            record_audit_crawl_events(
+                deleted_crawl_requests
+                    .into_iter()
+                    .map(|(crawl_request_id, dataset_id, crawl_options)| {
+                        AuditCrawlEvent::from_details(
+                            crawl_request_id,
+                            dataset_id,
+                            scrape_id,
+                            deleted_by,
+                            CrawlAuditAction::Deleted,
+                            Some(crawl_options),
+                            None,
+                        )
+                    })
+                    .collect(),
+                conn,
+            )
+            .await
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+#[derive(QueryableByName)]
+struct PurgedCrawlRequestRow {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    dataset_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    scrape_id: uuid::Uuid,
+    #[diesel(sql_type = diesel::sql_types::Jsonb)]
+    crawl_options: serde_json::Value,
+}
+
+/// Deletes finished crawl requests created more than `older_than_days` days ago. The most recent
+/// crawl request of each dataset is always kept since it holds the dataset's active crawl settings.
+pub async fn purge_old_crawl_requests(
+    older_than_days: u32,
+    pool: web::Data<Pool>,
+) -> Result<u64, ServiceError> {
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let cutoff = chrono::Utc::now().naive_utc() - chrono::Duration::days(older_than_days.into());
+
+    conn.transaction::<_, ServiceError, _>(|conn| {
+        async move {
+            // Statuses are stored through CrawlStatus's Display impl, compare case-insensitively
+            let purged = diesel::sql_query(
+                "DELETE FROM crawl_requests
+                WHERE lower(status) IN ('completed', 'partial', 'failed', 'cancelled')
+                AND created_at < $1
+                AND id NOT IN (
+                    SELECT DISTINCT ON (dataset_id) id FROM crawl_requests
+                    ORDER BY dataset_id, created_at DESC
+                )
+                RETURNING id, dataset_id, scrape_id, crawl_options",
+            )
+            .bind::<diesel::sql_types::Timestamp, _>(cutoff)
+            .load::<PurgedCrawlRequestRow>(conn)
+            .await
+            .map_err(|e| {
+                log::error!("Error purging old crawl requests: {:?}", e);
+                ServiceError::InternalServerError("Error purging old crawl requests".to_string())
+            })?;
+
+            let deleted = purged.len() as u64;
+            record_audit_crawl_events(
+                purged
+                    .into_iter()
+                    .map(|row| {
+                        AuditCrawlEvent::from_details(
+                            row.id,
+                            row.dataset_id,
+                            row.scrape_id,
+                            None,
+                            CrawlAuditAction::Deleted,
+                            Some(row.crawl_options),
+                            None,
+                        )
+                    })
+                    .collect(),
+                conn,
+            )
+            .await?;
+
+            Ok(deleted)
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+fn new_crawl_request(
+    crawl_options: CrawlOptions,
+    dataset_id: uuid::Uuid,
+    scrape_id: uuid::Uuid,
+) -> CrawlRequestPG {
+    let interval = match crawl_options.interval {
+        Some(CrawlInterval::Daily) => std::time::Duration::from_secs(60 * 60 * 24),
+        Some(CrawlInterval::Weekly) => std::time::Duration::from_secs(60 * 60 * 24 * 7),
+        Some(CrawlInterval::Monthly) => std::time::Duration::from_secs(60 * 60 * 24 * 30),
+        None => std::time::Duration::from_secs(60 * 60 * 24),
+    };
+
+    CrawlRequest {
+        id: uuid::Uuid::new_v4(),
+        url: crawl_options.site_url.clone().unwrap_or_default(),
+        status: CrawlStatus::Pending,
+        interval,
+        next_crawl_at: chrono::Utc::now().naive_utc(),
+        // The version sent by the client is not trusted, the options are stored in the current
+        // format whatever it says
+        crawl_options: CrawlOptions {
+            version: CRAWL_OPTIONS_VERSION,
+            ..crawl_options
+        },
+        scrape_id,
+        dataset_id,
+        created_at: chrono::Utc::now().naive_utc(),
+        attempt_number: 0,
+        crawl_duration_ms: None,
+    }
+    .into()
+}
+
+/// Announces a newly inserted crawl request and pushes it onto the scrape queue
+async fn enqueue_crawl_request(
+    new_crawl_request: &CrawlRequestPG,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<(), ServiceError> {
+    if let Err(e) = publish_crawl_event(
+        CrawlEvent::created(new_crawl_request.scrape_id, &CrawlStatus::Pending),
+        redis_pool.clone(),
     )
-    .set(crawl_requests_table::next_crawl_at.eq(next_crawl_at))
-    .execute(&mut conn)
     .await
-    .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+    {
+        log::error!("Error publishing crawl created event: {:?}", e);
+    }
+
+    let serialized_message =
+        serde_json::to_string(&CrawlRequest::try_from(new_crawl_request.clone())?).unwrap();
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    redis::cmd("lpush")
+        .arg("scrape_queue")
+        .arg(&serialized_message)
+        .query_async::<redis::aio::MultiplexedConnection, usize>(&mut *redis_conn)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
     Ok(())
 }
 
-pub async fn update_crawl_settings_for_dataset(
+pub async fn create_crawl_request(
     crawl_options: CrawlOptions,
     dataset_id: uuid::Uuid,
+    scrape_id: uuid::Uuid,
+    created_by: Option<uuid::Uuid>,
     pool: web::Data<Pool>,
     redis_pool: web::Data<RedisPool>,
-) -> Result<(), ServiceError> {
+) -> Result<uuid::Uuid, ServiceError> {
     use crate::data::schema::crawl_requests::dsl as crawl_requests_table;
+
+    let new_crawl_request = new_crawl_request(crawl_options, dataset_id, scrape_id);
+
     let mut conn = pool
         .get()
         .await
         .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
 
-    let prev_crawl_req = crawl_requests_table::crawl_requests
-        .select((
-            crawl_requests_table::id,
-            crawl_requests_table::url,
-            crawl_requests_table::status,
-            crawl_requests_table::next_crawl_at,
-            crawl_requests_table::interval,
-            crawl_requests_table::crawl_options,
-            crawl_requests_table::scrape_id,
-            crawl_requests_table::dataset_id,
-            crawl_requests_table::created_at,
-        ))
-        .filter(crawl_requests_table::dataset_id.eq(dataset_id))
-        .first::<CrawlRequestPG>(&mut conn)
-        .await
-        .optional()?;
+    conn.transaction::<_, ServiceError, _>(|conn| {
+        let new_crawl_request = new_crawl_request.clone();
+        async move {
+            diesel::insert_into(crawl_requests_table::crawl_requests)
+                .values(&new_crawl_request)
+                .execute(conn)
+                .await
+                .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
 
-    if let Some(ref url) = crawl_options.site_url {
-        diesel::update(
-            crawl_requests_table::crawl_requests
-                .filter(crawl_requests_table::dataset_id.eq(dataset_id)),
-        )
-        .set(crawl_requests_table::url.eq(url))
-        .execute(&mut conn)
-        .await
-        .map_err(|e| {
-            log::error!("Error updating url on crawl_requests: {:?}", e);
-            ServiceError::InternalServerError("Error updating url on crawl_requests".to_string())
-        })?;
-    }
+            record_audit_crawl_events(
+                vec![AuditCrawlEvent::from_details(
+                    new_crawl_request.id,
+                    new_crawl_request.dataset_id,
+                    new_crawl_request.scrape_id,
+                    created_by,
+                    CrawlAuditAction::Created,
+                    None,
+                    Some(new_crawl_request.crawl_options.clone()),
+                )],
+                conn,
+            )
+            .await
+        }
+        .scope_boxed()
+    })
+    .await?;
 
-    if let Some(interval) = crawl_options.interval.clone() {
-        let interval = match interval {
-            CrawlInterval::Daily => std::time::Duration::from_secs(60 * 60 * 24),
-            CrawlInterval::Weekly => std::time::Duration::from_secs(60 * 60 * 24 * 7),
-            CrawlInterval::Monthly => std::time::Duration::from_secs(60 * 60 * 24 * 30),
-        };
-        diesel::update(
-            crawl_requests_table::crawl_requests
-                .filter(crawl_requests_table::dataset_id.eq(dataset_id)),
-        )
-        .set(crawl_requests_table::interval.eq(interval.as_secs() as i32))
-        .execute(&mut conn)
+    enqueue_crawl_request(&new_crawl_request, redis_pool).await?;
+
+    Ok(new_crawl_request.scrape_id)
+}
+
+pub async fn update_crawl_status(
+    crawl_id: uuid::Uuid,
+    status: CrawlStatus,
+    pool: web::Data<Pool>,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::crawl_requests::dsl as crawl_requests_table;
+
+    let mut conn = pool
+        .get()
         .await
-        .map_err(|e| {
-            log::error!("Error updating interval on crawl_requests: {:?}", e);
-            ServiceError::InternalServerError(
-                "Error updating interval on crawl_requests".to_string(),
-            )
-        })?;
-    }
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
 
-    let merged_options = if let Some(prev_crawl_req) = prev_crawl_req {
-        let previous_crawl_options: CrawlOptions =
-            serde_json::from_value(prev_crawl_req.crawl_options)
+    let previous_status = conn
+        .transaction::<_, ServiceError, _>(|conn| {
+            let status = status.clone();
+            async move {
+                let previous_statuses = crawl_requests_table::crawl_requests
+                    .select((
+                        crawl_requests_table::id,
+                        crawl_requests_table::dataset_id,
+                        crawl_requests_table::status,
+                    ))
+                    .filter(crawl_requests_table::scrape_id.eq(crawl_id))
+                    .load::<(uuid::Uuid, uuid::Uuid, String)>(conn)
+                    .await
+                    .map_err(|e| ServiceError::InternalServerError(e.to_string()))?
+                    .into_iter()
+                    .map(|(crawl_request_id, dataset_id, previous_status)| {
+                        (
+                            crawl_request_id,
+                            dataset_id,
+                            CrawlStatus::from(previous_status),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                let previous_status = previous_statuses
+                    .first()
+                    .map(|(_, _, previous_status)| previous_status.clone());
+
+                diesel::update(
+                    crawl_requests_table::crawl_requests
+                        .filter(crawl_requests_table::scrape_id.eq(crawl_id)),
+                )
+                .set(crawl_requests_table::status.eq(status.to_string()))
+                .execute(conn)
+                .await
                 .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
-        crawl_options.merge(previous_crawl_options)
-    } else {
-        crawl_options
-    };
 
-    diesel::update(
-        crawl_requests_table::crawl_requests
-            .filter(crawl_requests_table::dataset_id.eq(dataset_id)),
-    )
-    .set(crawl_requests_table::crawl_options.eq(
-        serde_json::to_value(merged_options.clone()).map_err(|e| {
-            log::error!("Failed to serialize crawl options: {:?}", e);
-            ServiceError::BadRequest("Failed to serialize crawl options".to_string())
-        })?,
-    ))
-    .execute(&mut conn)
-    .await
-    .map_err(|e| {
-        log::error!("Error updating crawl options on crawl_requests: {:?}", e);
-        ServiceError::InternalServerError(
-            "Error updating crawl options on crawl_requests".to_string(),
-        )
-    })?;
+                record_audit_crawl_events(
+                    previous_statuses
+                        .into_iter()
+                        .map(|(crawl_request_id, dataset_id, previous_status)| {
+                            AuditCrawlEvent::from_details(
+                                crawl_request_id,
+                                dataset_id,
+                                crawl_id,
+                                None,
+                                CrawlAuditAction::StatusChanged,
+                                Some(serde_json::json!(previous_status.as_str())),
+                                Some(serde_json::json!(status.as_str())),
+                            )
+                        })
+                        .collect(),
+                    conn,
+                )
+                .await?;
 
-    crawl(
-        merged_options.clone(),
-        pool.clone(),
-        redis_pool.clone(),
-        dataset_id,
+                Ok(previous_status)
+            }
+            .scope_boxed()
+        })
+        .await?;
+
+    if let Err(e) = publish_crawl_event(
+        CrawlEvent::status_changed(crawl_id, previous_status.as_ref(), &status),
+        redis_pool,
     )
-    .await?;
+    .await
+    {
+        log::error!("Error publishing crawl status change: {:?}", e);
+    }
 
     Ok(())
 }
 
-pub async fn update_scrape_id(
-    scrape_id: uuid::Uuid,
-    new_scrape_id: uuid::Uuid,
+pub async fn update_crawl_duration(
+    crawl_request_id: uuid::Uuid,
+    crawl_duration_ms: i64,
     pool: web::Data<Pool>,
-) -> Result<CrawlRequest, ServiceError> {
+) -> Result<(), ServiceError> {
     use crate::data::schema::crawl_requests::dsl as crawl_requests_table;
+
     let mut conn = pool
         .get()
         .await
         .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
-    let updated_request = diesel::update(
-        crawl_requests_table::crawl_requests.filter(crawl_requests_table::scrape_id.eq(scrape_id)),
+
+    diesel::update(
+        crawl_requests_table::crawl_requests.filter(crawl_requests_table::id.eq(crawl_request_id)),
     )
-    .set(crawl_requests_table::scrape_id.eq(new_scrape_id))
-    .returning(CrawlRequestPG::as_returning())
-    .get_result(&mut conn)
+    .set(crawl_requests_table::crawl_duration_ms.eq(crawl_duration_ms))
+    .execute(&mut conn)
     .await
     .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
 
-    Ok(updated_request.into())
+    Ok(())
 }
 
-pub async fn get_crawl_from_firecrawl(scrape_id: uuid::Uuid) -> Result<IngestResult, ServiceError> {
-    log::info!("Getting crawl from firecrawl");
+#[derive(QueryableByName)]
+struct CrawlSummaryRow {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    total_crawls: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    completed_crawls: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    failed_crawls: i64,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Double>)]
+    p50_duration_ms: Option<f64>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Double>)]
+    p95_duration_ms: Option<f64>,
+}
 
-    let firecrawl_url =
-        std::env::var("FIRECRAWL_URL").unwrap_or_else(|_| "https://api.firecrawl.dev".to_string());
-    let firecrawl_api_key = std::env::var("FIRECRAWL_API_KEY").unwrap_or_else(|_| "".to_string());
-    let mut firecrawl_url = format!("{}/v1/crawl/{}", firecrawl_url, scrape_id);
+pub async fn get_crawl_summary(pool: web::Data<Pool>) -> Result<CrawlSummary, ServiceError> {
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
 
-    let mut collected_docs: Vec<Option<Document>> = vec![];
-    let mut resp = None;
+    // Statuses are stored through CrawlStatus's Display impl, compare case-insensitively
+    let summary = diesel::sql_query(
+        "SELECT
+            COUNT(*) AS total_crawls,
+            COUNT(*) FILTER (WHERE lower(status) IN ('completed', 'partial')) AS completed_crawls,
+            COUNT(*) FILTER (WHERE lower(status) = 'failed') AS failed_crawls,
+            percentile_cont(0.5) WITHIN GROUP (ORDER BY crawl_duration_ms) AS p50_duration_ms,
+            percentile_cont(0.95) WITHIN GROUP (ORDER BY crawl_duration_ms) AS p95_duration_ms
+        FROM crawl_requests",
+    )
+    .get_result::<CrawlSummaryRow>(&mut conn)
+    .await
+    .map_err(|e| {
+        log::error!("Error getting crawl summary: {:?}", e);
+        ServiceError::InternalServerError("Error getting crawl summary".to_string())
+    })?;
 
-    let client = reqwest::Client::new();
+    Ok(CrawlSummary {
+        total_crawls: summary.total_crawls,
+        completed_crawls: summary.completed_crawls,
+        failed_crawls: summary.failed_crawls,
+        p50_duration_ms: summary
+            .p50_duration_ms
+            .map(|duration| duration.round() as i64),
+        p95_duration_ms: summary
+            .p95_duration_ms
+            .map(|duration| duration.round() as i64),
+    })
+}
 
-    while resp.is_none() {
-        let response = client
-            .get(&firecrawl_url)
-            .header("Authorization", format!("Bearer {}", firecrawl_api_key))
-            .send()
-            .await
-            .map_err(|e| {
-                log::error!("Error sending request to firecrawl: {:?}", e);
-                ServiceError::InternalServerError("Error sending request to firecrawl".to_string())
-            })?;
+#[derive(QueryableByName)]
+struct DatasetUrlRow {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    url: String,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    total_urls: i64,
+}
 
-        if !response.status().is_success() {
-            log::error!(
-                "Error getting response from firecrawl: {:?}",
-                response.text().await
-            );
-            return Err(ServiceError::InternalServerError(
-                "Error getting response from firecrawl".to_string(),
-            ));
-        };
+/// Distinct urls of the crawled pages which have chunks in the dataset, along with the total
+/// number of distinct urls. Crawled chunks store the url of their page under the `url` key of
+/// their metadata. `page` is 1-indexed.
+pub async fn list_dataset_urls(
+    dataset_id: uuid::Uuid,
+    page: u32,
+    page_size: u32,
+    pool: web::Data<Pool>,
+) -> Result<(Vec<String>, u64), ServiceError> {
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
 
-        let ingest_result = response.json::<IngestResult>().await.map_err(|e| {
-            log::error!("Error parsing response from firecrawl: {:?}", e);
-            ServiceError::InternalServerError("Error parsing response from firecrawl".to_string())
-        })?;
+    let rows = diesel::sql_query(
+        "SELECT url, COUNT(*) OVER () AS total_urls
+        FROM (
+            SELECT DISTINCT metadata->>'url' AS url
+            FROM chunk_metadata
+            WHERE dataset_id = $1 AND metadata->>'url' IS NOT NULL
+        ) urls
+        ORDER BY url
+        LIMIT $2 OFFSET $3",
+    )
+    .bind::<diesel::sql_types::Uuid, _>(dataset_id)
+    .bind::<diesel::sql_types::BigInt, _>(page_size as i64)
+    .bind::<diesel::sql_types::BigInt, _>((page.max(1) as i64 - 1) * page_size as i64)
+    .load::<DatasetUrlRow>(&mut conn)
+    .await
+    .map_err(|e| {
+        log::error!("Error listing dataset urls: {:?}", e);
+        ServiceError::InternalServerError("Error listing dataset urls".to_string())
+    })?;
 
-        if ingest_result.status != Status::Completed {
-            log::info!("Crawl status: {:?}", ingest_result.status);
-            return Ok(ingest_result);
+    let total_urls = match rows.first() {
+        Some(row) => row.total_urls as u64,
+        // Pages past the last url have no rows to read the total from
+        None => {
+            diesel::sql_query(
+                "SELECT '' AS url, COUNT(DISTINCT metadata->>'url') AS total_urls
+            FROM chunk_metadata
+            WHERE dataset_id = $1",
+            )
+            .bind::<diesel::sql_types::Uuid, _>(dataset_id)
+            .get_result::<DatasetUrlRow>(&mut conn)
+            .await
+            .map_err(|e| {
+                log::error!("Error counting dataset urls: {:?}", e);
+                ServiceError::InternalServerError("Error counting dataset urls".to_string())
+            })?
+            .total_urls as u64
         }
+    };
 
-        let cur_docs = ingest_result.clone().data.unwrap_or_default();
-        collected_docs.extend(cur_docs);
+    Ok((rows.into_iter().map(|row| row.url).collect(), total_urls))
+}
 
-        if let Some(ref next_ingest_result) = ingest_result.next {
-            let next_ingest_result = next_ingest_result.replace("https://", "http://");
+fn crawl_events_key(scrape_id: uuid::Uuid) -> String {
+    format!("crawl_events:{}", scrape_id)
+}
 
-            log::info!(
-                "Next ingest url: {} | prev {}",
+const CRAWL_EVENTS_MAX_LEN: usize = 1000;
+const CRAWL_EVENTS_TTL_SECS: u64 = 60 * 60 * 24 * 30;
+
+/// Appends the event to the Redis stream of its crawl.
+pub async fn publish_crawl_event(
+    event: CrawlEvent,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<(), ServiceError> {
+    let serialized_event = serde_json::to_string(&event)
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    redis::pipe()
+        .cmd("XADD")
+        .arg(crawl_events_key(event.scrape_id))
+        .arg("MAXLEN")
+        .arg("~")
+        .arg(CRAWL_EVENTS_MAX_LEN)
+        .arg("*")
+        .arg("event")
+        .arg(serialized_event)
+        .ignore()
+        .cmd("EXPIRE")
+        .arg(crawl_events_key(event.scrape_id))
+        .arg(CRAWL_EVENTS_TTL_SECS)
+        .ignore()
+        .query_async::<redis::aio::MultiplexedConnection, ()>(&mut *redis_conn)
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Returns the recorded events of a crawl, oldest first.
+pub async fn get_crawl_events(
+    scrape_id: uuid::Uuid,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<Vec<CrawlEvent>, ServiceError> {
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let entries: Vec<(String, HashMap<String, String>)> = redis::cmd("XRANGE")
+        .arg(crawl_events_key(scrape_id))
+        .arg("-")
+        .arg("+")
+        .query_async::<redis::aio::MultiplexedConnection, _>(&mut *redis_conn)
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    Ok(entries
+        .into_iter()
+        .filter_map(|(_, fields)| {
+            fields
+                .get("event")
+                .and_then(|event| serde_json::from_str(event).ok())
+        })
+        .collect())
+}
+
+pub async fn update_next_crawl_at(
+    crawl_id: uuid::Uuid,
+    next_crawl_at: chrono::NaiveDateTime,
+    pool: web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::crawl_requests::dsl as crawl_requests_table;
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+    diesel::update(
+        crawl_requests_table::crawl_requests.filter(crawl_requests_table::scrape_id.eq(crawl_id)),
+    )
+    .set(crawl_requests_table::next_crawl_at.eq(next_crawl_at))
+    .execute(&mut conn)
+    .await
+    .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+    Ok(())
+}
+
+const MAX_CRAWL_SETTINGS_HISTORY: usize = 10;
+
+/// Merges `crawl_options` into the dataset's crawl options and starts a new crawl with them.
+/// With `if_match`, the update only goes through if it is `*` or the ETag of the dataset's
+/// current crawl options, otherwise it fails with `PreconditionFailed`.
+pub async fn update_crawl_settings_for_dataset(
+    crawl_options: CrawlOptions,
+    dataset_id: uuid::Uuid,
+    changed_by: Option<uuid::Uuid>,
+    if_match: Option<String>,
+    pool: web::Data<Pool>,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<CrawlRequest, ServiceError> {
+    use crate::data::schema::crawl_requests::dsl as crawl_requests_table;
+    validate_crawl_options(&crawl_options)?;
+
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let prev_crawl_req = crawl_requests_table::crawl_requests
+        .select((
+            crawl_requests_table::id,
+            crawl_requests_table::url,
+            crawl_requests_table::status,
+            crawl_requests_table::next_crawl_at,
+            crawl_requests_table::interval,
+            crawl_requests_table::crawl_options,
+            crawl_requests_table::scrape_id,
+            crawl_requests_table::dataset_id,
+            crawl_requests_table::created_at,
+            crawl_requests_table::crawl_duration_ms,
+            crawl_requests_table::github_token,
+        ))
+        .filter(crawl_requests_table::dataset_id.eq(dataset_id))
+        .order_by(crawl_requests_table::created_at.desc())
+        .first::<CrawlRequestPG>(&mut conn)
+        .await
+        .optional()?;
+
+    let url = crawl_options.site_url.clone();
+    let interval = crawl_options
+        .interval
+        .clone()
+        .map(|interval| match interval {
+            CrawlInterval::Daily => std::time::Duration::from_secs(60 * 60 * 24),
+            CrawlInterval::Weekly => std::time::Duration::from_secs(60 * 60 * 24 * 7),
+            CrawlInterval::Monthly => std::time::Duration::from_secs(60 * 60 * 24 * 30),
+        });
+
+    let if_match = if_match.filter(|if_match| if_match != "*");
+    let mut expected_crawl_options = None;
+    let mut settings_history = vec![];
+    let mut audit_events = vec![];
+    let merged_options = if let Some(prev_crawl_req) = prev_crawl_req {
+        let previous_crawl_options =
+            CrawlOptionsMigrator::migrate(prev_crawl_req.crawl_options.clone())?;
+
+        if let Some(ref if_match) = if_match {
+            if *if_match != crawl_options_etag(&previous_crawl_options) {
+                return Err(ServiceError::PreconditionFailed(
+                    "The crawl options changed since the ETag in the If-Match header was read"
+                        .to_string(),
+                ));
+            }
+            expected_crawl_options =
+                Some((prev_crawl_req.id, prev_crawl_req.crawl_options.clone()));
+        }
+
+        settings_history.push(CrawlOptionsSnapshot {
+            changed_at: chrono::Utc::now().naive_utc(),
+            changed_by,
+            previous_options: previous_crawl_options.clone(),
+        });
+        settings_history
+            .extend(get_crawl_settings_history(prev_crawl_req.scrape_id, pool.clone()).await?);
+        settings_history.truncate(MAX_CRAWL_SETTINGS_HISTORY);
+
+        let merged_options = crawl_options.merge(previous_crawl_options);
+        audit_events.push(AuditCrawlEvent::from_details(
+            prev_crawl_req.id,
+            prev_crawl_req.dataset_id,
+            prev_crawl_req.scrape_id,
+            changed_by,
+            CrawlAuditAction::SettingsUpdated,
+            Some(prev_crawl_req.crawl_options),
+            serde_json::to_value(&merged_options).ok(),
+        ));
+        merged_options
+    } else if if_match.is_some() {
+        return Err(ServiceError::PreconditionFailed(
+            "The dataset has no crawl options to match the If-Match header against".to_string(),
+        ));
+    } else {
+        CrawlOptions {
+            version: CRAWL_OPTIONS_VERSION,
+            ..crawl_options
+        }
+    };
+    validate_crawl_options(&merged_options)?;
+
+    let merged_options_value = serde_json::to_value(merged_options.clone()).map_err(|e| {
+        log::error!("Failed to serialize crawl options: {:?}", e);
+        ServiceError::BadRequest("Failed to serialize crawl options".to_string())
+    })?;
+    let settings_history_value = serde_json::to_value(settings_history).map_err(|e| {
+        log::error!("Failed to serialize crawl settings history: {:?}", e);
+        ServiceError::BadRequest("Failed to serialize crawl settings history".to_string())
+    })?;
+
+    // The site is crawled before anything is written, so a crawl which can't be started leaves
+    // the dataset's crawl settings as they were
+    let new_scrape_id = start_scrape(&merged_options).await?;
+    let new_crawl_request = new_crawl_request(merged_options, dataset_id, new_scrape_id);
+    audit_events.push(AuditCrawlEvent::from_details(
+        new_crawl_request.id,
+        new_crawl_request.dataset_id,
+        new_crawl_request.scrape_id,
+        changed_by,
+        CrawlAuditAction::Created,
+        None,
+        Some(new_crawl_request.crawl_options.clone()),
+    ));
+
+    conn.transaction::<_, ServiceError, _>(|conn| {
+        let new_crawl_request = new_crawl_request.clone();
+        async move {
+            // Compares and updates in one statement, so of two updates based on the same ETag
+            // only the first goes through
+            if let Some((prev_crawl_req_id, prev_crawl_options)) = expected_crawl_options {
+                let updated = diesel::update(
+                    crawl_requests_table::crawl_requests
+                        .filter(crawl_requests_table::id.eq(prev_crawl_req_id))
+                        .filter(crawl_requests_table::crawl_options.eq(prev_crawl_options)),
+                )
+                .set(crawl_requests_table::crawl_options.eq(merged_options_value.clone()))
+                .execute(conn)
+                .await
+                .map_err(|e| {
+                    log::error!("Error updating crawl options on crawl_requests: {:?}", e);
+                    ServiceError::InternalServerError(
+                        "Error updating crawl options on crawl_requests".to_string(),
+                    )
+                })?;
+
+                if updated == 0 {
+                    return Err(ServiceError::PreconditionFailed(
+                        "The crawl options changed since the ETag in the If-Match header was read"
+                            .to_string(),
+                    ));
+                }
+            }
+
+            if let Some(url) = url {
+                diesel::update(
+                    crawl_requests_table::crawl_requests
+                        .filter(crawl_requests_table::dataset_id.eq(dataset_id)),
+                )
+                .set(crawl_requests_table::url.eq(url))
+                .execute(conn)
+                .await
+                .map_err(|e| {
+                    log::error!("Error updating url on crawl_requests: {:?}", e);
+                    ServiceError::InternalServerError(
+                        "Error updating url on crawl_requests".to_string(),
+                    )
+                })?;
+            }
+
+            if let Some(interval) = interval {
+                diesel::update(
+                    crawl_requests_table::crawl_requests
+                        .filter(crawl_requests_table::dataset_id.eq(dataset_id)),
+                )
+                .set(crawl_requests_table::interval.eq(interval.as_secs() as i32))
+                .execute(conn)
+                .await
+                .map_err(|e| {
+                    log::error!("Error updating interval on crawl_requests: {:?}", e);
+                    ServiceError::InternalServerError(
+                        "Error updating interval on crawl_requests".to_string(),
+                    )
+                })?;
+            }
+
+            diesel::update(
+                crawl_requests_table::crawl_requests
+                    .filter(crawl_requests_table::dataset_id.eq(dataset_id)),
+            )
+            .set((
+                crawl_requests_table::crawl_options.eq(merged_options_value),
+                crawl_requests_table::github_token.eq(new_crawl_request.github_token.clone()),
+            ))
+            .execute(conn)
+            .await
+            .map_err(|e| {
+                log::error!("Error updating crawl options on crawl_requests: {:?}", e);
+                ServiceError::InternalServerError(
+                    "Error updating crawl options on crawl_requests".to_string(),
+                )
+            })?;
+
+            diesel::insert_into(crawl_requests_table::crawl_requests)
+                .values(&new_crawl_request)
+                .execute(conn)
+                .await
+                .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+            // Set after the insert so the new crawl request carries the history as well
+            diesel::update(
+                crawl_requests_table::crawl_requests
+                    .filter(crawl_requests_table::dataset_id.eq(dataset_id)),
+            )
+            .set(crawl_requests_table::settings_history.eq(settings_history_value))
+            .execute(conn)
+            .await
+            .map_err(|e| {
+                log::error!("Error updating settings_history on crawl_requests: {:?}", e);
+                ServiceError::InternalServerError(
+                    "Error updating settings_history on crawl_requests".to_string(),
+                )
+            })?;
+
+            record_audit_crawl_events(audit_events, conn).await
+        }
+        .scope_boxed()
+    })
+    .await?;
+
+    enqueue_crawl_request(&new_crawl_request, redis_pool).await?;
+
+    new_crawl_request.try_into()
+}
+
+/// Copy of `value` whose objects have their keys in sorted order, so that equal values always
+/// serialize to the same string whatever order their maps were built in.
+fn sort_json_keys(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(object) => {
+            let sorted: std::collections::BTreeMap<String, serde_json::Value> = object
+                .into_iter()
+                .map(|(key, value)| (key, sort_json_keys(value)))
+                .collect();
+            serde_json::Value::Object(sorted.into_iter().collect())
+        }
+        serde_json::Value::Array(values) => {
+            serde_json::Value::Array(values.into_iter().map(sort_json_keys).collect())
+        }
+        value => value,
+    }
+}
+
+/// ETag for a set of crawl options, changes whenever any of the options change.
+pub fn crawl_options_etag(crawl_options: &CrawlOptions) -> String {
+    let serialized_options = serde_json::to_value(crawl_options)
+        .map(|value| sort_json_keys(value).to_string())
+        .unwrap_or_default();
+    format!("\"{}\"", hash_function(&serialized_options))
+}
+
+/// Returns the previous crawl options of a crawl, most recent change first.
+pub async fn get_crawl_settings_history(
+    scrape_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<Vec<CrawlOptionsSnapshot>, ServiceError> {
+    use crate::data::schema::crawl_requests::dsl as crawl_requests_table;
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let settings_history = crawl_requests_table::crawl_requests
+        .select(crawl_requests_table::settings_history)
+        .filter(crawl_requests_table::scrape_id.eq(scrape_id))
+        .first::<serde_json::Value>(&mut conn)
+        .await
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => {
+                ServiceError::NotFound(format!("Crawl request {} not found", scrape_id))
+            }
+            e => ServiceError::InternalServerError(e.to_string()),
+        })?;
+
+    serde_json::from_value(settings_history).map_err(|e| {
+        log::error!("Failed to deserialize crawl settings history: {:?}", e);
+        ServiceError::InternalServerError(
+            "Failed to deserialize crawl settings history".to_string(),
+        )
+    })
+}
+
+pub async fn record_crawl_indexed_pages(
+    crawl_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    pages: Vec<CrawlIndexedPage>,
+    pool: web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::crawl_indexed_pages::dsl as crawl_indexed_pages_table;
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    // A retried crawl reuses its crawl_id, so replace any pages recorded by a previous attempt
+    diesel::delete(
+        crawl_indexed_pages_table::crawl_indexed_pages
+            .filter(crawl_indexed_pages_table::crawl_id.eq(crawl_id))
+            .filter(crawl_indexed_pages_table::dataset_id.eq(dataset_id)),
+    )
+    .execute(&mut conn)
+    .await
+    .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    for pages_chunk in pages.chunks(1000) {
+        diesel::insert_into(crawl_indexed_pages_table::crawl_indexed_pages)
+            .values(pages_chunk)
+            .execute(&mut conn)
+            .await
+            .map_err(|e| {
+                log::error!("Error inserting crawl indexed pages: {:?}", e);
+                ServiceError::InternalServerError("Error inserting crawl indexed pages".to_string())
+            })?;
+    }
+
+    Ok(())
+}
+
+pub const DEDUPLICATED_CRAWL_ERROR: &str = "deduplicated";
+
+pub fn crawled_url_hash(url: &str) -> String {
+    hash_function(url.trim_end_matches('/'))
+}
+
+pub async fn get_urls_indexed_in_other_datasets(
+    organization_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    url_hashes: Vec<String>,
+    pool: web::Data<Pool>,
+) -> Result<HashSet<String>, ServiceError> {
+    use crate::data::schema::crawled_url_index::dsl as crawled_url_index_table;
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let mut indexed_hashes = HashSet::new();
+    for hashes_chunk in url_hashes.chunks(1000) {
+        let found_hashes: Vec<String> = crawled_url_index_table::crawled_url_index
+            .select(crawled_url_index_table::url_hash)
+            .filter(crawled_url_index_table::organization_id.eq(organization_id))
+            .filter(crawled_url_index_table::dataset_id.ne(dataset_id))
+            .filter(crawled_url_index_table::url_hash.eq_any(hashes_chunk))
+            .load::<String>(&mut conn)
+            .await
+            .map_err(|e| {
+                log::error!("Error getting crawled url index: {:?}", e);
+                ServiceError::InternalServerError("Error getting crawled url index".to_string())
+            })?;
+        indexed_hashes.extend(found_hashes);
+    }
+
+    Ok(indexed_hashes)
+}
+
+pub async fn record_crawled_urls(
+    organization_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    urls: Vec<String>,
+    pool: web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::crawled_url_index::dsl as crawled_url_index_table;
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let entries: Vec<CrawledUrlIndex> = urls
+        .into_iter()
+        .map(|url| {
+            CrawledUrlIndex::from_details(organization_id, dataset_id, crawled_url_hash(&url), url)
+        })
+        .collect();
+
+    for entries_chunk in entries.chunks(1000) {
+        diesel::insert_into(crawled_url_index_table::crawled_url_index)
+            .values(entries_chunk)
+            .on_conflict_do_nothing()
+            .execute(&mut conn)
+            .await
+            .map_err(|e| {
+                log::error!("Error inserting crawled url index: {:?}", e);
+                ServiceError::InternalServerError("Error inserting crawled url index".to_string())
+            })?;
+    }
+
+    Ok(())
+}
+
+pub async fn record_crawl_errors(
+    errors: Vec<CrawlError>,
+    pool: web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::crawl_errors::dsl as crawl_errors_table;
+    if errors.is_empty() {
+        return Ok(());
+    }
+
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    for errors_chunk in errors.chunks(1000) {
+        diesel::insert_into(crawl_errors_table::crawl_errors)
+            .values(errors_chunk)
+            .execute(&mut conn)
+            .await
+            .map_err(|e| {
+                log::error!("Error inserting crawl errors: {:?}", e);
+                ServiceError::InternalServerError("Error inserting crawl errors".to_string())
+            })?;
+    }
+
+    Ok(())
+}
+
+/// Inserts audit log rows, on the connection of the transaction making the change they record
+pub async fn record_audit_crawl_events(
+    events: Vec<AuditCrawlEvent>,
+    conn: &mut diesel_async::AsyncPgConnection,
+) -> Result<(), ServiceError> {
+    use crate::data::schema::audit_crawl_events::dsl as audit_crawl_events_table;
+    if events.is_empty() {
+        return Ok(());
+    }
+
+    diesel::insert_into(audit_crawl_events_table::audit_crawl_events)
+        .values(&events)
+        .execute(conn)
+        .await
+        .map_err(|e| {
+            log::error!("Error inserting audit crawl events: {:?}", e);
+            ServiceError::InternalServerError("Error inserting audit crawl events".to_string())
+        })?;
+
+    Ok(())
+}
+
+/// Audit log of the crawl requests of the dataset which had the scrape id, oldest change first.
+/// The log is found through the dataset and scrape id stored on its rows, so it is still
+/// returned once the crawl request is deleted.
+pub async fn get_audit_crawl_events(
+    scrape_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<Vec<AuditCrawlEvent>, ServiceError> {
+    use crate::data::schema::audit_crawl_events::dsl as audit_crawl_events_table;
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let crawl_request_ids = audit_crawl_events_table::audit_crawl_events
+        .select(audit_crawl_events_table::crawl_request_id)
+        .filter(audit_crawl_events_table::dataset_id.eq(dataset_id))
+        .filter(audit_crawl_events_table::scrape_id.eq(scrape_id));
+
+    let events = audit_crawl_events_table::audit_crawl_events
+        .filter(audit_crawl_events_table::dataset_id.eq(dataset_id))
+        .filter(audit_crawl_events_table::crawl_request_id.eq_any(crawl_request_ids))
+        .order(audit_crawl_events_table::occurred_at.asc())
+        .select(AuditCrawlEvent::as_select())
+        .load::<AuditCrawlEvent>(&mut conn)
+        .await
+        .map_err(|e| {
+            log::error!("Error getting audit crawl events: {:?}", e);
+            ServiceError::InternalServerError("Error getting audit crawl events".to_string())
+        })?;
+
+    Ok(events)
+}
+
+pub async fn get_deduplicated_urls(
+    crawl_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<Vec<String>, ServiceError> {
+    use crate::data::schema::crawl_errors::dsl as crawl_errors_table;
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let urls = crawl_errors_table::crawl_errors
+        .select(crawl_errors_table::url)
+        .filter(crawl_errors_table::crawl_id.eq(crawl_id))
+        .filter(crawl_errors_table::error_type.eq(DEDUPLICATED_CRAWL_ERROR))
+        .order(crawl_errors_table::created_at.asc())
+        .load::<String>(&mut conn)
+        .await
+        .map_err(|e| {
+            log::error!("Error getting deduplicated urls: {:?}", e);
+            ServiceError::InternalServerError("Error getting deduplicated urls".to_string())
+        })?;
+
+    Ok(urls)
+}
+
+pub async fn get_previous_crawl_id(
+    crawl_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<Option<uuid::Uuid>, ServiceError> {
+    use crate::data::schema::crawl_indexed_pages::dsl as crawl_indexed_pages_table;
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    crawl_indexed_pages_table::crawl_indexed_pages
+        .select(crawl_indexed_pages_table::crawl_id)
+        .filter(crawl_indexed_pages_table::dataset_id.eq(dataset_id))
+        .filter(crawl_indexed_pages_table::crawl_id.ne(crawl_id))
+        .order_by(crawl_indexed_pages_table::created_at.desc())
+        .first::<uuid::Uuid>(&mut conn)
+        .await
+        .optional()
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))
+}
+
+pub async fn compute_crawl_diff(
+    old_crawl_id: uuid::Uuid,
+    new_crawl_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<CrawlDiff, ServiceError> {
+    use crate::data::schema::crawl_diffs::dsl as crawl_diffs_table;
+    use crate::data::schema::crawl_indexed_pages::dsl as crawl_indexed_pages_table;
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let pages = crawl_indexed_pages_table::crawl_indexed_pages
+        .filter(crawl_indexed_pages_table::crawl_id.eq_any(vec![old_crawl_id, new_crawl_id]))
+        .select(CrawlIndexedPage::as_select())
+        .load::<CrawlIndexedPage>(&mut conn)
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let dataset_id = pages
+        .iter()
+        .find(|page| page.crawl_id == new_crawl_id)
+        .or(pages.first())
+        .map(|page| page.dataset_id)
+        .ok_or(ServiceError::NotFound(
+            "No indexed pages found for either crawl".to_string(),
+        ))?;
+
+    let (old_pages, new_pages): (Vec<CrawlIndexedPage>, Vec<CrawlIndexedPage>) = pages
+        .into_iter()
+        .partition(|page| page.crawl_id == old_crawl_id);
+
+    let old_hashes: HashMap<String, String> = old_pages
+        .into_iter()
+        .map(|page| (page.url, page.content_hash))
+        .collect();
+    let new_hashes: HashMap<String, String> = new_pages
+        .into_iter()
+        .map(|page| (page.url, page.content_hash))
+        .collect();
+
+    let mut diff = CrawlDiff::default();
+    for (url, content_hash) in new_hashes.iter() {
+        match old_hashes.get(url) {
+            None => diff.added.push(url.clone()),
+            Some(old_hash) if old_hash != content_hash => diff.modified.push(url.clone()),
+            Some(_) => {}
+        }
+    }
+    diff.removed = old_hashes
+        .keys()
+        .filter(|url| !new_hashes.contains_key(*url))
+        .cloned()
+        .collect();
+
+    diff.added.sort();
+    diff.removed.sort();
+    diff.modified.sort();
+
+    let crawl_diff = CrawlDiffPG {
+        id: uuid::Uuid::new_v4(),
+        dataset_id,
+        old_crawl_id,
+        new_crawl_id,
+        diff: serde_json::to_value(&diff)
+            .map_err(|e| ServiceError::InternalServerError(e.to_string()))?,
+        created_at: chrono::Utc::now().naive_utc(),
+    };
+
+    diesel::insert_into(crawl_diffs_table::crawl_diffs)
+        .values(&crawl_diff)
+        .execute(&mut conn)
+        .await
+        .map_err(|e| {
+            log::error!("Error inserting crawl diff: {:?}", e);
+            ServiceError::InternalServerError("Error inserting crawl diff".to_string())
+        })?;
+
+    Ok(diff)
+}
+
+pub async fn update_scrape_id(
+    scrape_id: uuid::Uuid,
+    new_scrape_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<CrawlRequest, ServiceError> {
+    use crate::data::schema::crawl_requests::dsl as crawl_requests_table;
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let updated_request = conn
+        .transaction::<_, ServiceError, _>(|conn| {
+            async move {
+                let updated_request = diesel::update(
+                    crawl_requests_table::crawl_requests
+                        .filter(crawl_requests_table::scrape_id.eq(scrape_id)),
+                )
+                .set(crawl_requests_table::scrape_id.eq(new_scrape_id))
+                .returning(CrawlRequestPG::as_returning())
+                .get_result(conn)
+                .await
+                .map_err(|e| match e {
+                    diesel::result::Error::NotFound => {
+                        ServiceError::NotFound(format!("Crawl request {} not found", scrape_id))
+                    }
+                    e => ServiceError::InternalServerError(e.to_string()),
+                })?;
+
+                record_audit_crawl_events(
+                    vec![AuditCrawlEvent::from_details(
+                        updated_request.id,
+                        updated_request.dataset_id,
+                        new_scrape_id,
+                        None,
+                        CrawlAuditAction::ScrapeIdChanged,
+                        Some(serde_json::json!(scrape_id)),
+                        Some(serde_json::json!(new_scrape_id)),
+                    )],
+                    conn,
+                )
+                .await?;
+
+                Ok(updated_request)
+            }
+            .scope_boxed()
+        })
+        .await?;
+
+    updated_request.try_into()
+}
+
+const FORCE_CRAWL_COOLDOWN_SECS: u64 = 60 * 15;
+
+fn force_crawl_lock_key(dataset_id: uuid::Uuid) -> String {
+    format!("force_crawl_lock:{}", dataset_id)
+}
+
+/// Starts a new crawl for an existing crawl request without waiting for its `next_crawl_at`. Each
+/// dataset can only force a crawl once every 15 minutes.
+pub async fn force_crawl_now(
+    scrape_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<(), ServiceError> {
+    let crawl_request = get_crawl_request(scrape_id, pool.clone()).await?;
+
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let lock_key = force_crawl_lock_key(crawl_request.dataset_id);
+    let acquired_lock = redis::cmd("SET")
+        .arg(&lock_key)
+        .arg(chrono::Utc::now().timestamp())
+        .arg("NX")
+        .arg("EX")
+        .arg(FORCE_CRAWL_COOLDOWN_SECS)
+        .query_async::<redis::aio::MultiplexedConnection, Option<String>>(&mut *redis_conn)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?
+        .is_some();
+
+    if !acquired_lock {
+        return Err(ServiceError::BadRequest(
+            "A crawl was already forced for this dataset in the last 15 minutes".to_string(),
+        ));
+    }
+
+    let new_scrape_id = match crawl_request.crawl_options.scrape_options {
+        Some(ScrapeOptions::Shopify(_)) => uuid::Uuid::nil(),
+        Some(ScrapeOptions::GitHub(_)) => uuid::Uuid::new_v4(),
+        _ => match crawl_site(crawl_request.crawl_options.clone()).await {
+            Ok(new_scrape_id) => new_scrape_id,
+            Err(err) => {
+                // Release the lock so a failed attempt does not block retrying
+                let _ = redis::cmd("DEL")
+                    .arg(&lock_key)
+                    .query_async::<redis::aio::MultiplexedConnection, usize>(&mut *redis_conn)
+                    .await;
+                return Err(ServiceError::BadRequest(format!(
+                    "Could not crawl site: {}",
+                    err
+                )));
+            }
+        },
+    };
+
+    update_scrape_id(crawl_request.scrape_id, new_scrape_id, pool.clone()).await?;
+    update_crawl_status(
+        new_scrape_id,
+        CrawlStatus::Pending,
+        pool.clone(),
+        redis_pool.clone(),
+    )
+    .await?;
+
+    let updated_request = get_crawl_request_by_id(crawl_request.id, pool).await?;
+    let serialized_message = serde_json::to_string(&updated_request).unwrap();
+
+    redis::cmd("lpush")
+        .arg("scrape_queue")
+        .arg(&serialized_message)
+        .query_async::<redis::aio::MultiplexedConnection, usize>(&mut *redis_conn)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    Ok(())
+}
+
+/// `url` with its `old_base_url` prefix replaced by `new_base_url`, `None` if it does not start
+/// with `old_base_url`. The prefix only matches at a path boundary, so `https://example.com`
+/// does not match `https://example.com.evil.org`.
+pub fn replace_base_url(url: &str, old_base_url: &str, new_base_url: &str) -> Option<String> {
+    let path = url.strip_prefix(old_base_url)?;
+    if !(path.is_empty() || path.starts_with('/') || old_base_url.ends_with('/')) {
+        return None;
+    }
+
+    Some(format!("{}{}", new_base_url, path))
+}
+
+/// Moves a crawl to the new base url of its site. The url and crawl options of the crawl request
+/// and the urls of the dataset's chunks under `old_base_url` are rewritten, then the site is
+/// crawled again at its new url.
+pub async fn migrate_crawl_to_new_url(
+    scrape_id: uuid::Uuid,
+    old_base_url: &str,
+    new_base_url: &str,
+    pool: web::Data<Pool>,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<MigrationResult, ServiceError> {
+    use crate::data::schema::crawl_requests::dsl as crawl_requests_table;
+
+    if reqwest::Url::parse(old_base_url).is_err() {
+        return Err(ServiceError::BadRequest(format!(
+            "Invalid old base url {}",
+            old_base_url
+        )));
+    }
+    if reqwest::Url::parse(new_base_url).is_err() {
+        return Err(ServiceError::BadRequest(format!(
+            "Invalid new base url {}",
+            new_base_url
+        )));
+    }
+
+    let crawl_request = get_crawl_request(scrape_id, pool.clone()).await?;
+    let url =
+        replace_base_url(&crawl_request.url, old_base_url, new_base_url).ok_or_else(|| {
+            ServiceError::BadRequest(format!(
+                "Crawl url {} is not under the old base url {}",
+                crawl_request.url, old_base_url
+            ))
+        })?;
+
+    let mut crawl_options = crawl_request.crawl_options.clone();
+    crawl_options.site_url = crawl_options
+        .site_url
+        .map(|url| replace_base_url(&url, old_base_url, new_base_url).unwrap_or(url));
+    crawl_options.seed_urls = crawl_options.seed_urls.map(|seed_urls| {
+        seed_urls
+            .into_iter()
+            .map(|url| replace_base_url(&url, old_base_url, new_base_url).unwrap_or(url))
+            .collect()
+    });
+    validate_crawl_options(&crawl_options)?;
+    let serialized_crawl_options = serde_json::to_value(&crawl_options).map_err(|e| {
+        log::error!("Failed to serialize crawl options: {:?}", e);
+        ServiceError::BadRequest("Failed to serialize crawl options".to_string())
+    })?;
+    let old_url_prefix = if old_base_url.ends_with('/') {
+        old_base_url.to_string()
+    } else {
+        format!("{}/", old_base_url)
+    };
+
+    let new_scrape_id = match crawl_options.scrape_options {
+        Some(ScrapeOptions::Shopify(_)) => uuid::Uuid::nil(),
+        Some(ScrapeOptions::GitHub(_)) => uuid::Uuid::new_v4(),
+        _ => crawl_site(crawl_options)
+            .await
+            .map_err(|err| ServiceError::BadRequest(format!("Could not crawl site: {}", err)))?,
+    };
+
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let dataset_id = crawl_request.dataset_id;
+    let old_url = crawl_request.url.clone();
+    let (updated_request, updated_chunks) = conn
+        .transaction::<_, ServiceError, _>(|conn| {
+            async move {
+                let updated_request = diesel::update(
+                    crawl_requests_table::crawl_requests
+                        .filter(crawl_requests_table::scrape_id.eq(scrape_id)),
+                )
+                .set((
+                    crawl_requests_table::url.eq(url),
+                    crawl_requests_table::crawl_options.eq(serialized_crawl_options),
+                    crawl_requests_table::scrape_id.eq(new_scrape_id),
+                    crawl_requests_table::status.eq(CrawlStatus::Pending.to_string()),
+                ))
+                .returning(CrawlRequestPG::as_returning())
+                .get_result(conn)
+                .await
+                .map_err(|e| {
+                    log::error!("Error updating url on crawl_requests: {:?}", e);
+                    ServiceError::InternalServerError(
+                        "Error updating url on crawl_requests".to_string(),
+                    )
+                })?;
+
+                // Crawled chunks store the url of their page as their link and under the `url`
+                // key of their metadata. Only the leading `old_base_url` is replaced, matching
+                // `replace_base_url`.
+                let updated_chunks = diesel::sql_query(
+                    "UPDATE chunk_metadata
+                    SET link = CASE WHEN link = $2 OR starts_with(link, $4)
+                            THEN $3 || substr(link, length($2) + 1) ELSE link END,
+                        metadata = CASE WHEN metadata->>'url' = $2 OR starts_with(metadata->>'url', $4)
+                            THEN jsonb_set(metadata, '{url}', to_jsonb($3 || substr(metadata->>'url', length($2) + 1)))
+                            ELSE metadata END
+                    WHERE dataset_id = $1
+                    AND (link = $2 OR starts_with(link, $4)
+                        OR metadata->>'url' = $2 OR starts_with(metadata->>'url', $4))",
+                )
+                .bind::<diesel::sql_types::Uuid, _>(dataset_id)
+                .bind::<diesel::sql_types::Text, _>(old_base_url)
+                .bind::<diesel::sql_types::Text, _>(new_base_url)
+                .bind::<diesel::sql_types::Text, _>(&old_url_prefix)
+                .execute(conn)
+                .await
+                .map_err(|e| {
+                    log::error!("Error updating urls of chunks under {}: {:?}", old_base_url, e);
+                    ServiceError::InternalServerError("Error updating urls of chunks".to_string())
+                })? as u64;
+
+                record_audit_crawl_events(
+                    vec![AuditCrawlEvent::from_details(
+                        updated_request.id,
+                        dataset_id,
+                        new_scrape_id,
+                        None,
+                        CrawlAuditAction::UrlMigrated,
+                        Some(serde_json::json!({ "url": old_url, "scrape_id": scrape_id })),
+                        Some(serde_json::json!({
+                            "url": updated_request.url,
+                            "scrape_id": new_scrape_id,
+                        })),
+                    )],
+                    conn,
+                )
+                .await?;
+
+                Ok((updated_request, updated_chunks))
+            }
+            .scope_boxed()
+        })
+        .await?;
+
+    if let Err(e) = publish_crawl_event(
+        CrawlEvent::status_changed(
+            new_scrape_id,
+            Some(&crawl_request.status),
+            &CrawlStatus::Pending,
+        ),
+        redis_pool.clone(),
+    )
+    .await
+    {
+        log::error!("Error publishing crawl status change: {:?}", e);
+    }
+
+    let updated_request: CrawlRequest = updated_request.try_into()?;
+    let serialized_message = serde_json::to_string(&updated_request).unwrap();
+
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+    redis::cmd("lpush")
+        .arg("scrape_queue")
+        .arg(&serialized_message)
+        .query_async::<redis::aio::MultiplexedConnection, usize>(&mut *redis_conn)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    Ok(MigrationResult {
+        updated_chunks,
+        new_scrape_id,
+    })
+}
+
+const CIRCUIT_BREAKER_FAILURE_THRESHOLD: u32 = 5;
+const CIRCUIT_BREAKER_FAILURE_WINDOW: Duration = Duration::from_secs(60);
+const CIRCUIT_BREAKER_OPEN_DURATION: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitBreakerState {
+    Closed {
+        consecutive_failures: u32,
+        window_started_at: Option<Instant>,
+    },
+    Open {
+        opened_at: Instant,
+    },
+    /// A single trial request is let through to find out whether the upstream recovered
+    HalfOpen {
+        trial_started_at: Instant,
+    },
+}
+
+/// Stops requests to a failing upstream for a while after it fails repeatedly. Workers build one
+/// per upstream and share it through `web::Data`.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    name: &'static str,
+    state: RwLock<CircuitBreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            state: RwLock::new(CircuitBreakerState::Closed {
+                consecutive_failures: 0,
+                window_started_at: None,
+            }),
+        }
+    }
+
+    pub async fn state(&self) -> CircuitBreakerState {
+        *self.state.read().await
+    }
+
+    pub async fn check(&self) -> Result<(), ServiceError> {
+        if let CircuitBreakerState::Closed { .. } = *self.state.read().await {
+            return Ok(());
+        }
+
+        let mut state = self.state.write().await;
+        match *state {
+            CircuitBreakerState::Closed { .. } => Ok(()),
+            CircuitBreakerState::Open { opened_at }
+                if opened_at.elapsed() >= CIRCUIT_BREAKER_OPEN_DURATION =>
+            {
+                log::info!("Circuit breaker for {} is half open", self.name);
+                *state = CircuitBreakerState::HalfOpen {
+                    trial_started_at: Instant::now(),
+                };
+                Ok(())
+            }
+            // A trial which never reported back is given up on and replaced by a new one
+            CircuitBreakerState::HalfOpen { trial_started_at }
+                if trial_started_at.elapsed() >= CIRCUIT_BREAKER_OPEN_DURATION =>
+            {
+                *state = CircuitBreakerState::HalfOpen {
+                    trial_started_at: Instant::now(),
+                };
+                Ok(())
+            }
+            CircuitBreakerState::Open { .. } | CircuitBreakerState::HalfOpen { .. } => {
+                Err(ServiceError::ServiceUnavailable(format!(
+                    "{} is unavailable, retry later",
+                    self.name
+                )))
+            }
+        }
+    }
+
+    pub async fn record_success(&self) {
+        let mut state = self.state.write().await;
+        *state = CircuitBreakerState::Closed {
+            consecutive_failures: 0,
+            window_started_at: None,
+        };
+    }
+
+    pub async fn record_failure(&self) {
+        let mut state = self.state.write().await;
+        *state = match *state {
+            CircuitBreakerState::Closed {
+                consecutive_failures,
+                window_started_at: Some(window_started_at),
+            } if window_started_at.elapsed() < CIRCUIT_BREAKER_FAILURE_WINDOW => {
+                if consecutive_failures + 1 >= CIRCUIT_BREAKER_FAILURE_THRESHOLD {
+                    log::error!("Circuit breaker for {} is open", self.name);
+                    CircuitBreakerState::Open {
+                        opened_at: Instant::now(),
+                    }
+                } else {
+                    CircuitBreakerState::Closed {
+                        consecutive_failures: consecutive_failures + 1,
+                        window_started_at: Some(window_started_at),
+                    }
+                }
+            }
+            CircuitBreakerState::Closed { .. } => CircuitBreakerState::Closed {
+                consecutive_failures: 1,
+                window_started_at: Some(Instant::now()),
+            },
+            CircuitBreakerState::HalfOpen { .. } | CircuitBreakerState::Open { .. } => {
+                log::error!("Circuit breaker for {} is open", self.name);
+                CircuitBreakerState::Open {
+                    opened_at: Instant::now(),
+                }
+            }
+        };
+    }
+}
+
+fn firecrawl_next_page_key(scrape_id: uuid::Uuid) -> String {
+    format!("firecrawl_next_page:{}", scrape_id)
+}
+
+fn firecrawl_docs_key(scrape_id: uuid::Uuid) -> String {
+    format!("firecrawl_docs:{}", scrape_id)
+}
+
+fn firecrawl_exclude_url_patterns_key(scrape_id: uuid::Uuid) -> String {
+    format!("firecrawl_exclude_url_patterns:{}", scrape_id)
+}
+
+const FIRECRAWL_PROGRESS_TTL_SECS: u64 = 60 * 60 * 24;
+
+/// Stores the `exclude_url_patterns` of the crawl options of a Firecrawl crawl for
+/// `resume_interrupted_crawl`. They are left to expire instead of being cleared with the pagination
+/// progress, since pagination starts over while the crawl is still being polled.
+pub async fn save_firecrawl_exclude_url_patterns(
+    scrape_id: uuid::Uuid,
+    crawl_options: &CrawlOptions,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<(), ServiceError> {
+    let serialized_patterns = serde_json::to_string(
+        &crawl_options
+            .exclude_url_patterns
+            .clone()
+            .unwrap_or_default(),
+    )
+    .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    redis::cmd("SET")
+        .arg(firecrawl_exclude_url_patterns_key(scrape_id))
+        .arg(serialized_patterns)
+        .arg("EX")
+        .arg(FIRECRAWL_PROGRESS_TTL_SECS)
+        .query_async::<redis::aio::MultiplexedConnection, ()>(&mut *redis_conn)
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    Ok(())
+}
+
+async fn get_firecrawl_exclude_url_patterns(
+    scrape_id: uuid::Uuid,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<Vec<Regex>, ServiceError> {
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let serialized_patterns: Option<String> = redis::cmd("GET")
+        .arg(firecrawl_exclude_url_patterns_key(scrape_id))
+        .query_async(&mut *redis_conn)
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let patterns: Vec<String> = match serialized_patterns {
+        Some(serialized_patterns) => serde_json::from_str(&serialized_patterns).map_err(|e| {
+            log::error!("Error deserializing exclude url patterns: {:?}", e);
+            ServiceError::InternalServerError(
+                "Error deserializing exclude url patterns".to_string(),
+            )
+        })?,
+        None => vec![],
+    };
+
+    compile_url_patterns(&patterns)
+}
+
+pub async fn get_last_processed_firecrawl_page(
+    scrape_id: uuid::Uuid,
+    redis_pool: web::Data<RedisPool>,
+) -> Option<String> {
+    let mut redis_conn = redis_pool.get().await.ok()?;
+
+    redis::cmd("GET")
+        .arg(firecrawl_next_page_key(scrape_id))
+        .query_async::<redis::aio::MultiplexedConnection, Option<String>>(&mut *redis_conn)
+        .await
+        .map_err(|e| {
+            log::error!("Error getting last processed firecrawl page: {:?}", e);
+        })
+        .ok()
+        .flatten()
+}
+
+pub async fn get_cached_firecrawl_docs(
+    scrape_id: uuid::Uuid,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<Vec<Option<Document>>, ServiceError> {
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let cached_pages: Vec<String> = redis::cmd("LRANGE")
+        .arg(firecrawl_docs_key(scrape_id))
+        .arg(0)
+        .arg(-1)
+        .query_async(&mut *redis_conn)
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let mut docs = vec![];
+    for cached_page in cached_pages {
+        let page_docs: Vec<Option<Document>> = serde_json::from_str(&cached_page).map_err(|e| {
+            log::error!("Error deserializing cached firecrawl docs: {:?}", e);
+            ServiceError::InternalServerError(
+                "Error deserializing cached firecrawl docs".to_string(),
+            )
+        })?;
+        docs.extend(page_docs);
+    }
+
+    Ok(docs)
+}
+
+async fn save_firecrawl_progress(
+    scrape_id: uuid::Uuid,
+    page_docs: &[Option<Document>],
+    next_page: Option<&str>,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<(), ServiceError> {
+    let serialized_docs = serde_json::to_string(page_docs)
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let mut pipe = redis::pipe();
+    pipe.atomic()
+        .cmd("RPUSH")
+        .arg(firecrawl_docs_key(scrape_id))
+        .arg(serialized_docs)
+        .ignore()
+        .cmd("EXPIRE")
+        .arg(firecrawl_docs_key(scrape_id))
+        .arg(FIRECRAWL_PROGRESS_TTL_SECS)
+        .ignore();
+
+    match next_page {
+        Some(next_page) => pipe
+            .cmd("SET")
+            .arg(firecrawl_next_page_key(scrape_id))
+            .arg(next_page)
+            .arg("EX")
+            .arg(FIRECRAWL_PROGRESS_TTL_SECS)
+            .ignore(),
+        None => pipe
+            .cmd("DEL")
+            .arg(firecrawl_next_page_key(scrape_id))
+            .ignore(),
+    };
+
+    pipe.query_async::<redis::aio::MultiplexedConnection, ()>(&mut *redis_conn)
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    Ok(())
+}
+
+pub async fn clear_firecrawl_progress(
+    scrape_id: uuid::Uuid,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<(), ServiceError> {
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    redis::cmd("DEL")
+        .arg(firecrawl_next_page_key(scrape_id))
+        .arg(firecrawl_docs_key(scrape_id))
+        .arg(crawl_reindex_key(scrape_id))
+        .query_async::<redis::aio::MultiplexedConnection, ()>(&mut *redis_conn)
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    Ok(())
+}
+
+pub async fn archive_completed_crawl_data(
+    scrape_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+    redis_pool: web::Data<RedisPool>,
+    s3_client: Bucket,
+) -> Result<String, ServiceError> {
+    use crate::data::schema::crawl_requests::dsl as crawl_requests_table;
+
+    let docs = get_cached_firecrawl_docs(scrape_id, redis_pool.clone()).await?;
+
+    let serialized_docs = serde_json::to_vec(&docs).map_err(|e| {
+        log::error!("Failed to serialize crawl documents for archive: {:?}", e);
+        ServiceError::InternalServerError(
+            "Failed to serialize crawl documents for archive".to_string(),
+        )
+    })?;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&serialized_docs).map_err(|_| {
+        ServiceError::InternalServerError("Failed to compress crawl archive".to_string())
+    })?;
+    let compressed_docs = encoder.finish().map_err(|_| {
+        ServiceError::InternalServerError("Failed to finish compressing crawl archive".to_string())
+    })?;
+
+    let archive_key = format!("crawl-archives/{}.json.gz", scrape_id);
+
+    s3_client
+        .put_object_with_content_type(&archive_key, &compressed_docs, "application/gzip")
+        .await
+        .map_err(|e| {
+            log::error!("Could not upload crawl archive to S3 {:?}", e);
+            ServiceError::BadRequest("Could not upload crawl archive to S3".to_string())
+        })?;
+
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    diesel::update(
+        crawl_requests_table::crawl_requests.filter(crawl_requests_table::scrape_id.eq(scrape_id)),
+    )
+    .set(crawl_requests_table::archive_key.eq(&archive_key))
+    .execute(&mut conn)
+    .await
+    .map_err(|e| {
+        log::error!("Error updating archive_key on crawl_requests: {:?}", e);
+        ServiceError::InternalServerError(
+            "Error updating archive_key on crawl_requests".to_string(),
+        )
+    })?;
+
+    clear_firecrawl_progress(scrape_id, redis_pool).await?;
+
+    Ok(archive_key)
+}
+
+pub async fn get_crawl_archive_key(
+    scrape_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<Option<String>, ServiceError> {
+    use crate::data::schema::crawl_requests::dsl as crawl_requests_table;
+    let mut conn = pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    crawl_requests_table::crawl_requests
+        .select(crawl_requests_table::archive_key)
+        .filter(crawl_requests_table::scrape_id.eq(scrape_id))
+        .first::<Option<String>>(&mut conn)
+        .await
+        .map_err(|e| match e {
+            diesel::result::Error::NotFound => {
+                ServiceError::NotFound(format!("Crawl request {} not found", scrape_id))
+            }
+            e => ServiceError::InternalServerError(e.to_string()),
+        })
+}
+
+/// Downloads and decompresses the documents archived by `archive_completed_crawl_data`
+pub async fn download_crawl_archive(
+    archive_key: &str,
+    s3_client: Bucket,
+) -> Result<Vec<Option<Document>>, ServiceError> {
+    let compressed_docs = s3_client
+        .get_object(archive_key)
+        .await
+        .map_err(|e| {
+            log::error!("Could not get crawl archive from S3 {:?}", e);
+            ServiceError::BadRequest("Crawl archive is not present in s3".to_string())
+        })?
+        .as_slice()
+        .to_vec();
+
+    let mut decoder = GzDecoder::new(Vec::new());
+    decoder.write_all(&compressed_docs).map_err(|_| {
+        ServiceError::InternalServerError("Failed to decompress crawl archive".to_string())
+    })?;
+    let serialized_docs = decoder.finish().map_err(|_| {
+        ServiceError::InternalServerError(
+            "Failed to finish decompressing crawl archive".to_string(),
+        )
+    })?;
+
+    serde_json::from_slice(&serialized_docs).map_err(|e| {
+        log::error!("Error deserializing crawl archive: {:?}", e);
+        ServiceError::InternalServerError("Error deserializing crawl archive".to_string())
+    })
+}
+
+fn crawl_reindex_key(scrape_id: uuid::Uuid) -> String {
+    format!("crawl_reindex:{}", scrape_id)
+}
+
+/// Caches `docs` as the crawled documents of the crawl and flags it so the crawl worker chunks
+/// them instead of getting the documents from Firecrawl
+pub async fn restore_crawl_documents(
+    scrape_id: uuid::Uuid,
+    docs: &[Option<Document>],
+    redis_pool: web::Data<RedisPool>,
+) -> Result<(), ServiceError> {
+    clear_firecrawl_progress(scrape_id, redis_pool.clone()).await?;
+    save_firecrawl_progress(scrape_id, docs, None, redis_pool.clone()).await?;
+
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    redis::cmd("SET")
+        .arg(crawl_reindex_key(scrape_id))
+        .arg(chrono::Utc::now().timestamp())
+        .arg("EX")
+        .arg(FIRECRAWL_PROGRESS_TTL_SECS)
+        .query_async::<redis::aio::MultiplexedConnection, ()>(&mut *redis_conn)
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Whether the crawl was queued by `reindex_crawl`. The flag is cleared along with the cached
+/// documents by `clear_firecrawl_progress` once the crawl is archived again, so retries of a
+/// failed reindex still use the restored documents.
+pub async fn is_crawl_reindex(
+    scrape_id: uuid::Uuid,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<bool, ServiceError> {
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let reindex_flag = redis::cmd("GET")
+        .arg(crawl_reindex_key(scrape_id))
+        .query_async::<redis::aio::MultiplexedConnection, Option<String>>(&mut *redis_conn)
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    Ok(reindex_flag.is_some())
+}
+
+/// Chunks the pages of a finished crawl again with its current crawl options, without crawling
+/// the site. The documents are restored from the crawl archive and the crawl is queued for the
+/// crawl worker, which indexes the restored documents. Chunks keep their tracking ids, so the
+/// worker upserts them and only deletes the chunks the reindex no longer produces.
+pub async fn reindex_crawl(
+    scrape_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<(), ServiceError> {
+    let crawl_request = get_crawl_request(scrape_id, pool.clone()).await?;
+    if !matches!(
+        crawl_request.status,
+        CrawlStatus::Completed | CrawlStatus::Partial | CrawlStatus::Failed
+    ) {
+        return Err(ServiceError::BadRequest(format!(
+            "Crawl {} is still running, only finished crawls can be reindexed",
+            scrape_id
+        )));
+    }
+
+    let archive_key = get_crawl_archive_key(scrape_id, pool.clone())
+        .await?
+        .ok_or_else(|| {
+            ServiceError::BadRequest("Crawl has no archive to reindex from".to_string())
+        })?;
+
+    let docs = download_crawl_archive(&archive_key, get_aws_bucket()?).await?;
+    restore_crawl_documents(scrape_id, &docs, redis_pool.clone()).await?;
+    log::info!(
+        "Reindexing {} archived documents of crawl {}",
+        docs.len(),
+        scrape_id
+    );
+
+    update_crawl_status(
+        scrape_id,
+        CrawlStatus::Pending,
+        pool.clone(),
+        redis_pool.clone(),
+    )
+    .await?;
+
+    let serialized_message = serde_json::to_string(&crawl_request).unwrap();
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    redis::cmd("lpush")
+        .arg("scrape_queue")
+        .arg(&serialized_message)
+        .query_async::<redis::aio::MultiplexedConnection, usize>(&mut *redis_conn)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    Ok(())
+}
+
+/// Gets the Firecrawl crawl starting from the last page it was paginated to, dropping the
+/// documents matching the exclude url patterns stored by `save_firecrawl_exclude_url_patterns`
+pub async fn resume_interrupted_crawl(
+    scrape_id: uuid::Uuid,
+    firecrawl_circuit_breaker: web::Data<CircuitBreaker>,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<IngestResult, ServiceError> {
+    let exclude_url_patterns =
+        get_firecrawl_exclude_url_patterns(scrape_id, redis_pool.clone()).await?;
+    let last_page = get_last_processed_firecrawl_page(scrape_id, redis_pool.clone()).await;
+
+    if let Some(ref last_page) = last_page {
+        log::info!(
+            "Resuming firecrawl pagination for scrape_id {} from {}",
+            scrape_id,
+            last_page
+        );
+    }
+
+    get_crawl_from_firecrawl(
+        scrape_id,
+        last_page,
+        &exclude_url_patterns,
+        firecrawl_circuit_breaker,
+        redis_pool,
+    )
+    .await
+}
+
+/// Firecrawl builds its pagination urls from its public address, which can use a different
+/// scheme than the one it is reached at, e.g. behind a TLS terminating proxy. Use the scheme
+/// of the configured Firecrawl url instead.
+fn with_firecrawl_scheme(next_url: &str, firecrawl_base_url: &str) -> String {
+    let (Ok(mut next), Ok(base)) = (Url::parse(next_url), Url::parse(firecrawl_base_url)) else {
+        return next_url.to_string();
+    };
+
+    if next.set_scheme(base.scheme()).is_err() {
+        return next_url.to_string();
+    }
+
+    next.to_string()
+}
+
+pub const DEFAULT_ALLOWED_SCHEMES: &[&str] = &["https", "http"];
+
+/// Whether the scheme of `url` is one of `allowed_schemes`, or http(s) when no allowlist is set.
+pub fn url_has_allowed_scheme(url: &str, allowed_schemes: Option<&[String]>) -> bool {
+    let Ok(url) = Url::parse(url) else {
+        return false;
+    };
+
+    match allowed_schemes {
+        Some(allowed_schemes) => allowed_schemes
+            .iter()
+            .any(|scheme| scheme.eq_ignore_ascii_case(url.scheme())),
+        None => DEFAULT_ALLOWED_SCHEMES.contains(&url.scheme()),
+    }
+}
+
+pub async fn get_crawl_from_firecrawl(
+    scrape_id: uuid::Uuid,
+    start_url: Option<String>,
+    exclude_url_patterns: &[Regex],
+    firecrawl_circuit_breaker: web::Data<CircuitBreaker>,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<IngestResult, ServiceError> {
+    log::info!("Getting crawl from firecrawl");
+
+    let firecrawl_base_url =
+        std::env::var("FIRECRAWL_URL").unwrap_or_else(|_| "https://api.firecrawl.dev".to_string());
+    let firecrawl_api_key = std::env::var("FIRECRAWL_API_KEY").unwrap_or_else(|_| "".to_string());
+
+    let (mut firecrawl_url, mut collected_docs) = match start_url {
+        Some(start_url) => (
+            start_url,
+            get_cached_firecrawl_docs(scrape_id, redis_pool.clone()).await?,
+        ),
+        None => {
+            clear_firecrawl_progress(scrape_id, redis_pool.clone()).await?;
+            (
+                format!("{}/v1/crawl/{}", firecrawl_base_url, scrape_id),
+                vec![],
+            )
+        }
+    };
+
+    let mut resp = None;
+
+    let client = reqwest::Client::new();
+
+    while resp.is_none() {
+        firecrawl_circuit_breaker.check().await?;
+
+        let response = match client
+            .get(&firecrawl_url)
+            .header("Authorization", format!("Bearer {}", firecrawl_api_key))
+            .send()
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => {
+                log::error!("Error sending request to firecrawl: {:?}", e);
+                firecrawl_circuit_breaker.record_failure().await;
+                return Err(ServiceError::InternalServerError(
+                    "Error sending request to firecrawl".to_string(),
+                ));
+            }
+        };
+
+        if response.status().is_server_error() {
+            firecrawl_circuit_breaker.record_failure().await;
+        } else {
+            firecrawl_circuit_breaker.record_success().await;
+        }
+
+        if !response.status().is_success() {
+            log::error!(
+                "Error getting response from firecrawl: {:?}",
+                response.text().await
+            );
+            return Err(ServiceError::InternalServerError(
+                "Error getting response from firecrawl".to_string(),
+            ));
+        };
+
+        let ingest_result = response.json::<IngestResult>().await.map_err(|e| {
+            log::error!("Error parsing response from firecrawl: {:?}", e);
+            ServiceError::InternalServerError("Error parsing response from firecrawl".to_string())
+        })?;
+
+        if ingest_result.status != Status::Completed {
+            log::info!("Crawl status: {:?}", ingest_result.status);
+            return Ok(ingest_result);
+        }
+
+        // Firecrawl's own url filtering is best effort, drop anything it let through
+        let cur_docs: Vec<Option<Document>> = ingest_result
+            .clone()
+            .data
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|doc| {
+                !doc.as_ref()
+                    .and_then(|doc| doc.metadata.source_url.as_ref())
+                    .is_some_and(|url| url_matches_any_pattern(url, exclude_url_patterns))
+            })
+            .map(|doc| {
+                doc.map(|mut doc| {
+                    if doc.content_type.is_none() {
+                        doc.content_type = doc.metadata.content_type.clone();
+                    }
+                    doc
+                })
+            })
+            .collect();
+
+        if let Some(ref next_ingest_result) = ingest_result.next {
+            let next_ingest_result = with_firecrawl_scheme(next_ingest_result, &firecrawl_base_url);
+
+            log::info!(
+                "Next ingest url: {} | prev {}",
                 next_ingest_result,
                 firecrawl_url
             );
             if next_ingest_result == firecrawl_url {
                 log::info!("Breaking loop");
+                save_firecrawl_progress(scrape_id, &cur_docs, None, redis_pool.clone()).await?;
+                collected_docs.extend(cur_docs);
                 resp = Some(ingest_result.clone());
                 break;
             }
 
-            firecrawl_url = next_ingest_result;
-        } else {
-            resp = Some(ingest_result.clone());
+            save_firecrawl_progress(
+                scrape_id,
+                &cur_docs,
+                Some(&next_ingest_result),
+                redis_pool.clone(),
+            )
+            .await?;
+            collected_docs.extend(cur_docs);
+
+            firecrawl_url = next_ingest_result;
+        } else {
+            save_firecrawl_progress(scrape_id, &cur_docs, None, redis_pool.clone()).await?;
+            collected_docs.extend(cur_docs);
+            resp = Some(ingest_result.clone());
+        }
+    }
+
+    match resp {
+        Some(resp) => {
+            let failed_urls = get_failed_urls(&collected_docs);
+            let status = if resp.status == Status::Completed
+                && !failed_urls.is_empty()
+                && collected_docs.iter().flatten().count() > failed_urls.len()
+            {
+                Status::Partial
+            } else {
+                resp.status
+            };
+
+            Ok(IngestResult {
+                status,
+                completed: resp.completed,
+                total: resp.total,
+                credits_used: resp.credits_used,
+                expires_at: resp.expires_at,
+                next: None,
+                data: Some(collected_docs),
+                failed_urls: Some(failed_urls),
+            })
+        }
+        None => Err(ServiceError::InternalServerError(
+            "Error getting response from firecrawl".to_string(),
+        )),
+    }
+}
+
+/// Connection and pricing settings for the Firecrawl API.
+#[derive(Debug, Clone)]
+pub struct FirecrawlConfig {
+    pub url: String,
+    pub api_key: String,
+    /// Credits charged per crawled page
+    pub credits_per_page: u32,
+    /// Pages the crawler is expected to get through per minute
+    pub pages_per_minute: u32,
+}
+
+impl FirecrawlConfig {
+    pub fn from_env() -> Self {
+        Self {
+            url: std::env::var("FIRECRAWL_URL")
+                .unwrap_or_else(|_| "https://api.firecrawl.dev".to_string()),
+            api_key: std::env::var("FIRECRAWL_API_KEY").unwrap_or_else(|_| "".to_string()),
+            credits_per_page: std::env::var("FIRECRAWL_CREDITS_PER_PAGE")
+                .ok()
+                .and_then(|credits| credits.parse().ok())
+                .unwrap_or(1),
+            pages_per_minute: std::env::var("FIRECRAWL_PAGES_PER_MINUTE")
+                .ok()
+                .and_then(|pages| pages.parse().ok())
+                .filter(|pages| *pages > 0)
+                .unwrap_or(20),
         }
     }
+}
 
-    match resp {
-        Some(resp) => Ok(IngestResult {
-            status: resp.status,
-            completed: resp.completed,
-            total: resp.total,
-            credits_used: resp.credits_used,
-            expires_at: resp.expires_at,
-            next: None,
-            data: Some(collected_docs),
-        }),
-        None => Err(ServiceError::InternalServerError(
-            "Error getting response from firecrawl".to_string(),
-        )),
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct FirecrawlMapRequest {
+    url: String,
+    ignore_sitemap: Option<bool>,
+    include_subdomains: bool,
+    limit: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct FirecrawlMapResponse {
+    success: bool,
+    links: Option<Vec<String>>,
+}
+
+fn matches_crawl_path_patterns(url: &str, patterns: &[String]) -> bool {
+    let path = Url::parse(url)
+        .map(|url| url.path().to_string())
+        .unwrap_or_default();
+
+    patterns.iter().any(|pattern| match Regex::new(pattern) {
+        Ok(re) => re.is_match(url) || re.is_match(&path),
+        Err(_) => false,
+    })
+}
+
+fn url_path_depth(url: &str) -> usize {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| {
+            url.path_segments()
+                .map(|segments| segments.filter(|segment| !segment.is_empty()).count())
+        })
+        .unwrap_or(0)
+}
+
+/// Estimates the pages, credits and duration of a crawl without starting it. The urls of the
+/// site are listed with Firecrawl's map endpoint, which is much cheaper than crawling, and
+/// filtered with the path, depth and url pattern options the crawl would apply.
+pub async fn estimate_crawl_cost(
+    crawl_options: &CrawlOptions,
+    config: &FirecrawlConfig,
+) -> Result<CrawlCostEstimate, ServiceError> {
+    let site_url = crawl_options
+        .site_url
+        .clone()
+        .ok_or(ServiceError::BadRequest("site_url is required".to_string()))?;
+    let limit = crawl_options.limit.unwrap_or(1000).max(0);
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("{}/v1/map", config.url))
+        .json(&FirecrawlMapRequest {
+            url: site_url.clone(),
+            ignore_sitemap: crawl_options.ignore_sitemap,
+            include_subdomains: false,
+            // Filtering happens after mapping, so map more urls than the limit
+            limit: limit.saturating_mul(2).min(5000),
+        })
+        .header("Authorization", format!("Bearer {}", config.api_key))
+        .send()
+        .await
+        .map_err(|e| {
+            log::error!("Error sending map request to firecrawl: {:?}", e);
+            ServiceError::InternalServerError("Error sending map request to firecrawl".to_string())
+        })?;
+
+    if !response.status().is_success() {
+        log::error!(
+            "Error getting map response from firecrawl: {:?}",
+            response.text().await
+        );
+        return Err(ServiceError::InternalServerError(
+            "Error getting map response from firecrawl".to_string(),
+        ));
     }
+
+    let map_response = response.json::<FirecrawlMapResponse>().await.map_err(|e| {
+        log::error!("Error parsing map response from firecrawl: {:?}", e);
+        ServiceError::InternalServerError("Error parsing map response from firecrawl".to_string())
+    })?;
+
+    if !map_response.success {
+        return Err(ServiceError::InternalServerError(
+            "Firecrawl failed to map the site".to_string(),
+        ));
+    }
+
+    let skip_url_patterns = compile_url_patterns(
+        &[
+            crawl_options.skip_urls_matching.clone().unwrap_or_default(),
+            crawl_options
+                .exclude_url_patterns
+                .clone()
+                .unwrap_or_default(),
+        ]
+        .concat(),
+    )?;
+    // Depth is counted from the path of the site url, like the crawler does
+    let max_depth =
+        crawl_options.max_depth.unwrap_or(10).max(0) as usize + url_path_depth(&site_url);
+
+    let page_count = map_response
+        .links
+        .unwrap_or_default()
+        .iter()
+        .filter(|link| {
+            crawl_options
+                .include_paths
+                .as_ref()
+                .map(|include_paths| {
+                    include_paths.is_empty() || matches_crawl_path_patterns(link, include_paths)
+                })
+                .unwrap_or(true)
+        })
+        .filter(|link| {
+            !crawl_options
+                .exclude_paths
+                .as_ref()
+                .map(|exclude_paths| matches_crawl_path_patterns(link, exclude_paths))
+                .unwrap_or(false)
+        })
+        .filter(|link| !url_matches_any_pattern(link, &skip_url_patterns))
+        .filter(|link| url_path_depth(link) <= max_depth)
+        .count();
+
+    let estimated_pages = page_count.min(limit as usize) as u32;
+
+    Ok(CrawlCostEstimate {
+        estimated_pages,
+        estimated_credits: estimated_pages.saturating_mul(config.credits_per_page),
+        estimated_duration_minutes: estimated_pages.div_ceil(config.pages_per_minute.max(1)),
+    })
 }
 
 pub async fn crawl_site(crawl_options: CrawlOptions) -> Result<uuid::Uuid, ServiceError> {
@@ -570,14 +3353,14 @@ pub async fn crawl_site(crawl_options: CrawlOptions) -> Result<uuid::Uuid, Servi
     }
 }
 
-pub fn get_tags(url: String) -> Vec<String> {
+pub fn get_tags(url: String, tag_prefix: Option<&str>) -> Vec<String> {
     if let Ok(parsed_url) = Url::parse(&url) {
         let path_parts: Vec<&str> = parsed_url.path().split('/').collect();
         return path_parts
             .iter()
             .filter_map(|part| {
                 if !part.is_empty() {
-                    Some(part.to_string())
+                    Some(format!("{}{}", tag_prefix.unwrap_or_default(), part))
                 } else {
                     None
                 }
@@ -587,12 +3370,857 @@ pub fn get_tags(url: String) -> Vec<String> {
     Vec::new()
 }
 
-pub fn chunk_html(html: &str) -> Vec<(String, String)> {
-    let re = Regex::new(r"(?i)<h[1-6].*?>").unwrap();
+const MAX_EXTERNAL_LINKS: usize = 50;
+
+/// Returns the deduplicated http(s) links in `links` which point to a different host than
+/// `page_url`, capped at 50.
+pub fn get_external_links(page_url: &str, links: &[String]) -> Vec<String> {
+    let page_host = Url::parse(page_url).ok().and_then(|url| {
+        url.host_str()
+            .map(|host| host.trim_start_matches("www.").to_string())
+    });
+
+    let mut external_links: Vec<String> = vec![];
+    for link in links {
+        let Ok(url) = Url::parse(link) else {
+            continue;
+        };
+
+        if !matches!(url.scheme(), "http" | "https") {
+            continue;
+        }
+
+        let host = url.host_str().map(|host| host.trim_start_matches("www."));
+        if host.is_none() || host == page_host.as_deref() {
+            continue;
+        }
+
+        if !external_links.contains(link) {
+            external_links.push(link.clone());
+        }
+
+        if external_links.len() >= MAX_EXTERNAL_LINKS {
+            break;
+        }
+    }
+
+    external_links
+}
+
+/// Mime types of the linked documents indexed when `index_linked_documents` is set and
+/// `linked_document_types` is not
+pub const DEFAULT_LINKED_DOCUMENT_TYPES: &[&str] = &["application/pdf", "text/markdown"];
+/// Maximum number of linked documents indexed per crawl
+pub const MAX_LINKED_DOCUMENTS: usize = 100;
+
+fn mime_type(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase()
+}
+
+/// Guesses the mime type of the resource at the url from its extension
+pub fn guess_mime_type(url: &str) -> Option<&'static str> {
+    let path = Url::parse(url).ok()?.path().to_lowercase();
+    let extension = path.rsplit_once('.')?.1.to_string();
+    match extension.as_str() {
+        "html" | "htm" => Some("text/html"),
+        "pdf" => Some("application/pdf"),
+        "md" | "markdown" => Some("text/markdown"),
+        "json" => Some("application/json"),
+        "txt" => Some("text/plain"),
+        _ => None,
+    }
+}
+
+/// Whether a linked document with the given content type, or the mime type guessed from its url
+/// when it has none, is one of `document_types`
+pub fn is_linked_document_type(
+    url: &str,
+    content_type: Option<&str>,
+    document_types: &[String],
+) -> bool {
+    let mime_type = match content_type {
+        Some(content_type) => mime_type(content_type),
+        None => match guess_mime_type(url) {
+            Some(mime_type) => mime_type.to_string(),
+            None => return false,
+        },
+    };
+
+    document_types
+        .iter()
+        .any(|document_type| document_type.eq_ignore_ascii_case(&mime_type))
+}
+
+/// Mime types of the linked documents indexed for the crawl options, see
+/// `CrawlOptions::linked_document_types`
+pub fn linked_document_types(crawl_options: &CrawlOptions) -> Vec<String> {
+    crawl_options
+        .linked_document_types
+        .clone()
+        .unwrap_or_else(|| {
+            DEFAULT_LINKED_DOCUMENT_TYPES
+                .iter()
+                .map(|document_type| document_type.to_string())
+                .collect()
+        })
+}
+
+/// Deduplicated external links of the pages which may be documents of one of `document_types`,
+/// capped at [`MAX_LINKED_DOCUMENTS`]. Links without an extension are kept as well, since only
+/// their content type tells what they are.
+pub fn get_linked_document_urls(
+    pages: &[Option<Document>],
+    document_types: &[String],
+) -> Vec<String> {
+    let mut linked_document_urls: Vec<String> = vec![];
+    for page in pages.iter().flatten() {
+        let (Some(page_url), Some(links)) = (page.metadata.source_url.as_deref(), &page.links)
+        else {
+            continue;
+        };
+
+        for link in get_external_links(page_url, links) {
+            if linked_document_urls.len() >= MAX_LINKED_DOCUMENTS {
+                return linked_document_urls;
+            }
+
+            let has_extension = Url::parse(&link).is_ok_and(|url| {
+                url.path_segments()
+                    .and_then(|mut segments| segments.next_back())
+                    .is_some_and(|segment| segment.contains('.'))
+            });
+            let may_be_document = if has_extension {
+                is_linked_document_type(&link, None, document_types)
+            } else {
+                true
+            };
+            if may_be_document && !linked_document_urls.contains(&link) {
+                linked_document_urls.push(link);
+            }
+        }
+    }
+
+    linked_document_urls
+}
+
+/// Queues a single page scrape job in the dataset of the crawl for each linked document, which
+/// the crawl worker picks up from the `linked_document_queue`
+pub async fn enqueue_linked_documents(
+    urls: Vec<String>,
+    crawl_request: &CrawlRequest,
+    redis_pool: web::Data<RedisPool>,
+) -> Result<(), ServiceError> {
+    if urls.is_empty() {
+        return Ok(());
+    }
+
+    let serialized_messages = urls
+        .into_iter()
+        .map(|url| {
+            serde_json::to_string(&LinkedDocumentMessage {
+                url,
+                crawl_request: crawl_request.clone(),
+            })
+            .map_err(|e| ServiceError::InternalServerError(e.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut redis_conn = redis_pool
+        .get()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    redis::cmd("lpush")
+        .arg("linked_document_queue")
+        .arg(&serialized_messages)
+        .query_async::<redis::aio::MultiplexedConnection, usize>(&mut *redis_conn)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+
+    Ok(())
+}
+
+/// Fetches a single page outside of a Firecrawl crawl, such as a document linked from a crawled
+/// page. `None` is returned without reading the body when the content type of the response is
+/// not one of `document_types`. The body is kept as the markdown of markdown documents and as
+/// the html of other text documents, pdfs are converted with pdfium from the downloaded bytes.
+pub async fn scrape_single_page(
+    url: &str,
+    document_types: &[String],
+    include_pdf_images: bool,
+) -> Result<Option<Document>, ServiceError> {
+    let response = external_http_client(url)
+        .await?
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| {
+            log::error!("Error fetching page {}: {:?}", url, e);
+            ServiceError::BadRequest(format!("Error fetching page {}", url))
+        })?;
+
+    let status_code = response.status().as_u16() as u32;
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .map(|content_type| content_type.to_string());
+
+    if !is_linked_document_type(url, content_type.as_deref(), document_types) {
+        return Ok(None);
+    }
+
+    let kind = content_type
+        .as_deref()
+        .and_then(DocumentKind::from_content_type)
+        .or_else(|| DocumentKind::from_url(url));
+    if kind == Some(DocumentKind::Pdf) {
+        let pdf_bytes = read_external_response_body(response, url).await?;
+        let url = url.to_string();
+        let document = tokio::task::spawn_blocking(move || {
+            convert_pdf_to_document(&pdf_bytes, &url, include_pdf_images)
+        })
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))??;
+
+        return Ok(Some(document));
+    }
+
+    let mut document = Document {
+        content_type: content_type.clone(),
+        markdown: None,
+        extract: None,
+        html: None,
+        raw_html: None,
+        links: None,
+        screenshot: None,
+        metadata: Metadata {
+            source_url: Some(url.to_string()),
+            status_code: Some(status_code),
+            content_type: content_type.clone(),
+            ..Default::default()
+        },
+    };
+
+    let body =
+        String::from_utf8_lossy(&read_external_response_body(response, url).await?).into_owned();
+    match kind {
+        Some(DocumentKind::Markdown) => document.markdown = Some(body),
+        Some(DocumentKind::Json) => document.raw_html = Some(body),
+        Some(DocumentKind::Html) => {
+            document.html = Some(body.clone());
+            document.raw_html = Some(body);
+        }
+        _ => document.html = Some(format!("<pre>{}</pre>", escape_html(&body))),
+    }
+
+    Ok(Some(document))
+}
+
+/// Returns the absolute url of the favicon declared by the page's `<link rel="icon">` elements.
+/// Icons sized 32x32, then 64x64, are preferred over other sizes.
+pub fn extract_favicon_url(html: &str, page_url: &str) -> Option<String> {
+    let document = Html::parse_document(html);
+    let link_selector = Selector::parse("link[rel][href]").unwrap();
+    let base_url = Url::parse(page_url).ok()?;
+
+    document
+        .select(&link_selector)
+        .filter(|link| {
+            link.value()
+                .attr("rel")
+                .unwrap_or_default()
+                .split_whitespace()
+                .any(|rel| rel.eq_ignore_ascii_case("icon"))
+        })
+        .filter_map(|link| {
+            let href = link.value().attr("href")?.trim();
+            let favicon_url = base_url.join(href).ok()?;
+            let sizes = link
+                .value()
+                .attr("sizes")
+                .unwrap_or_default()
+                .to_lowercase();
+            let rank = if sizes.split_whitespace().any(|size| size == "32x32") {
+                0
+            } else if sizes.split_whitespace().any(|size| size == "64x64") {
+                1
+            } else {
+                2
+            };
+            Some((rank, favicon_url.to_string()))
+        })
+        .min_by_key(|(rank, _)| *rank)
+        .map(|(_, favicon_url)| favicon_url)
+}
+
+fn json_ld_breadcrumb_lists(value: &serde_json::Value) -> Vec<&serde_json::Value> {
+    match value {
+        serde_json::Value::Array(values) => {
+            values.iter().flat_map(json_ld_breadcrumb_lists).collect()
+        }
+        serde_json::Value::Object(object) => {
+            let is_breadcrumb_list = match object.get("@type") {
+                Some(serde_json::Value::String(type_)) => type_ == "BreadcrumbList",
+                Some(serde_json::Value::Array(types)) => types
+                    .iter()
+                    .any(|type_| type_.as_str() == Some("BreadcrumbList")),
+                _ => false,
+            };
+            if is_breadcrumb_list {
+                vec![value]
+            } else {
+                object.values().flat_map(json_ld_breadcrumb_lists).collect()
+            }
+        }
+        _ => vec![],
+    }
+}
+
+fn json_ld_breadcrumbs(document: &Html, base_url: Option<&Url>) -> Vec<BreadcrumbItem> {
+    let script_selector = Selector::parse(r#"script[type="application/ld+json"]"#).unwrap();
+
+    for script in document.select(&script_selector) {
+        let Ok(json_ld) = serde_json::from_str::<serde_json::Value>(&script.inner_html()) else {
+            continue;
+        };
+
+        for breadcrumb_list in json_ld_breadcrumb_lists(&json_ld) {
+            let mut elements: Vec<&serde_json::Value> = breadcrumb_list
+                .get("itemListElement")
+                .and_then(|elements| elements.as_array())
+                .map(|elements| elements.iter().collect())
+                .unwrap_or_default();
+            elements.sort_by_key(|element| {
+                element
+                    .get("position")
+                    .and_then(|position| position.as_u64())
+                    .unwrap_or(u64::MAX)
+            });
+
+            let items: Vec<BreadcrumbItem> = elements
+                .into_iter()
+                .filter_map(|element| {
+                    let item = element.get("item");
+                    let name = element
+                        .get("name")
+                        .or_else(|| item.and_then(|item| item.get("name")))
+                        .and_then(|name| name.as_str())?
+                        .trim()
+                        .to_string();
+                    let url = item
+                        .and_then(|item| {
+                            item.as_str()
+                                .or_else(|| item.get("@id").and_then(|id| id.as_str()))
+                                .or_else(|| item.get("url").and_then(|url| url.as_str()))
+                        })
+                        .map(|url| resolve_breadcrumb_url(url, base_url));
+                    Some(BreadcrumbItem { name, url })
+                })
+                .filter(|item| !item.name.is_empty())
+                .collect();
+            if !items.is_empty() {
+                return items;
+            }
+        }
+    }
+
+    vec![]
+}
+
+fn resolve_breadcrumb_url(url: &str, base_url: Option<&Url>) -> String {
+    base_url
+        .and_then(|base_url| base_url.join(url.trim()).ok())
+        .map(|url| url.to_string())
+        .unwrap_or_else(|| url.trim().to_string())
+}
+
+fn nav_breadcrumbs(document: &Html, base_url: Option<&Url>) -> Vec<BreadcrumbItem> {
+    let nav_selector = Selector::parse("nav[aria-label]").unwrap();
+    let item_selector = Selector::parse("li").unwrap();
+    let link_selector = Selector::parse("a[href]").unwrap();
+    let element_text = |element: scraper::ElementRef| {
+        element
+            .text()
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ")
+    };
+
+    let Some(nav) = document.select(&nav_selector).find(|nav| {
+        nav.value()
+            .attr("aria-label")
+            .unwrap_or_default()
+            .to_lowercase()
+            .starts_with("breadcrumb")
+    }) else {
+        return vec![];
+    };
+
+    let list_items: Vec<_> = nav.select(&item_selector).collect();
+    let items: Vec<BreadcrumbItem> = if list_items.is_empty() {
+        nav.select(&link_selector)
+            .map(|link| BreadcrumbItem {
+                name: element_text(link),
+                url: link
+                    .value()
+                    .attr("href")
+                    .map(|href| resolve_breadcrumb_url(href, base_url)),
+            })
+            .collect()
+    } else {
+        list_items
+            .into_iter()
+            .map(|item| BreadcrumbItem {
+                name: element_text(item),
+                url: item
+                    .select(&link_selector)
+                    .next()
+                    .and_then(|link| link.value().attr("href"))
+                    .map(|href| resolve_breadcrumb_url(href, base_url)),
+            })
+            .collect()
+    };
+
+    items
+        .into_iter()
+        .filter(|item| !item.name.is_empty())
+        .collect()
+}
+
+/// Breadcrumbs of the page, from its JSON-LD `BreadcrumbList`, then from its
+/// `<nav aria-label="breadcrumb">` element, falling back to the segments of the page's url path.
+pub fn extract_breadcrumbs(html: &str, page_url: &str) -> Vec<BreadcrumbItem> {
+    let document = Html::parse_document(html);
+    let base_url = Url::parse(page_url).ok();
+
+    let breadcrumbs = json_ld_breadcrumbs(&document, base_url.as_ref());
+    if !breadcrumbs.is_empty() {
+        return breadcrumbs;
+    }
+
+    let breadcrumbs = nav_breadcrumbs(&document, base_url.as_ref());
+    if !breadcrumbs.is_empty() {
+        return breadcrumbs;
+    }
+
+    let mut segment_url = base_url.map(|url| url.origin().ascii_serialization());
+    get_tags(page_url.to_string(), None)
+        .into_iter()
+        .map(|segment| {
+            let url = segment_url.as_mut().map(|url| {
+                url.push('/');
+                url.push_str(&segment);
+                url.clone()
+            });
+            BreadcrumbItem { name: segment, url }
+        })
+        .collect()
+}
+
+/// Heading for a chunk split off by a horizontal rule, which often has no heading of its own.
+/// Falls back to the last heading seen, then to the start of the chunk's first sentence.
+fn horizontal_rule_section_heading(
+    headings_text: String,
+    chunk_text: &str,
+    last_heading: &mut String,
+) -> String {
+    if !headings_text.trim().is_empty() {
+        *last_heading = headings_text.clone();
+        return headings_text;
+    }
+
+    if !last_heading.is_empty() {
+        return last_heading.clone();
+    }
+
+    first_sentence_heading(chunk_text)
+}
+
+/// The start of the first sentence of the text, truncated to 12 words.
+fn first_sentence_heading(text: &str) -> String {
+    let first_sentence = text
+        .split_inclusive(['.', '!', '?'])
+        .next()
+        .unwrap_or_default();
+    let words: Vec<&str> = first_sentence.split_whitespace().collect();
+    if words.len() > 12 {
+        format!("{}...", words[..12].join(" "))
+    } else {
+        words.join(" ")
+    }
+}
+
+fn text_chunk_to_html(text: &str) -> String {
+    format!(
+        "<p>{}</p>",
+        text.trim()
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    )
+}
+
+/// Splits the text of the html into consecutive chunks of `words` words.
+pub fn chunk_html_by_fixed_size(html: &str, words: usize) -> Vec<(String, String, String)> {
+    let text = convert_html_to_text(html);
+    let text_words: Vec<&str> = text.split_whitespace().collect();
+
+    text_words
+        .chunks(words.max(1))
+        .map(|chunk_words| {
+            let chunk_text = chunk_words.join(" ");
+            (
+                first_sentence_heading(&chunk_text),
+                text_chunk_to_html(&chunk_text),
+                chunk_text,
+            )
+        })
+        .collect()
+}
+
+/// Splits the text of the html into chunks of at most `max_sentences` sentences, using unicode
+/// sentence boundaries.
+pub fn chunk_html_by_sentences(html: &str, max_sentences: usize) -> Vec<(String, String, String)> {
+    let text = convert_html_to_text(html);
+    let sentences: Vec<&str> = text
+        .split_sentence_bounds()
+        .map(|sentence| sentence.trim())
+        .filter(|sentence| !sentence.is_empty())
+        .collect();
+
+    sentences
+        .chunks(max_sentences.max(1))
+        .map(|chunk_sentences| {
+            let chunk_text = chunk_sentences
+                .iter()
+                .flat_map(|sentence| sentence.split_whitespace())
+                .collect::<Vec<&str>>()
+                .join(" ");
+            (
+                first_sentence_heading(&chunk_text),
+                text_chunk_to_html(&chunk_text),
+                chunk_text,
+            )
+        })
+        .collect()
+}
+
+/// Splits off one chunk per element matching `selector`, headed by the element's id. Elements
+/// nested in another match are part of the outer chunk. Returns the chunks and the html left
+/// once the matched elements are removed.
+pub fn chunk_html_by_element_id(
+    html: &str,
+    selector: &Selector,
+) -> (Vec<(String, String, String)>, String) {
+    let mut fragment = Html::parse_fragment(html);
+
+    let matched_elements: Vec<_> = fragment
+        .select(selector)
+        .filter(|element| {
+            !element
+                .ancestors()
+                .filter_map(scraper::ElementRef::wrap)
+                .any(|ancestor| selector.matches(&ancestor))
+        })
+        .map(|element| {
+            let heading = element
+                .value()
+                .id()
+                .map(|id| id.to_string())
+                .unwrap_or_else(|| extract_all_headings(&element.inner_html()));
+            (element.id(), heading, element.inner_html())
+        })
+        .collect();
+
+    let mut chunks = vec![];
+    for (node_id, heading, inner_html) in matched_elements {
+        if let Some(mut node) = fragment.tree.get_mut(node_id) {
+            node.detach();
+        }
+
+        let chunk_text = convert_html_to_text(&inner_html);
+        if !chunk_text.trim().is_empty() {
+            chunks.push((heading, inner_html.trim().to_string(), chunk_text));
+        }
+    }
+
+    (chunks, fragment.root_element().inner_html())
+}
+
+/// Removes every `<noscript>` element, whose fallback content is usually a "Please enable
+/// JavaScript" message rather than part of the page.
+pub fn remove_noscript_elements(html: &str) -> String {
+    if !html.to_lowercase().contains("<noscript") {
+        return html.to_string();
+    }
+
+    let mut fragment = Html::parse_fragment(html);
+    let selector = Selector::parse("noscript").unwrap();
+    let noscript_ids: Vec<_> = fragment
+        .select(&selector)
+        .map(|element| element.id())
+        .collect();
+    for node_id in noscript_ids {
+        if let Some(mut node) = fragment.tree.get_mut(node_id) {
+            node.detach();
+        }
+    }
+
+    fragment.root_element().inner_html()
+}
+
+/// Most html stored in the metadata of a chunk when `preserve_html_in_chunk` is set
+pub const MAX_PRESERVED_CHUNK_HTML_BYTES: usize = 16 * 1024;
+
+/// Cuts the html down to at most `max_bytes`, on a character boundary and before any tag which
+/// would be cut in half.
+pub fn truncate_html(html: &str, max_bytes: usize) -> &str {
+    if html.len() <= max_bytes {
+        return html;
+    }
+
+    let mut end = max_bytes;
+    while !html.is_char_boundary(end) {
+        end -= 1;
+    }
+    let truncated = &html[..end];
+    match (truncated.rfind('<'), truncated.rfind('>')) {
+        (Some(tag_start), Some(tag_end)) if tag_start > tag_end => &truncated[..tag_start],
+        (Some(tag_start), None) => &truncated[..tag_start],
+        _ => truncated,
+    }
+}
+
+static DOM_ID_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)<[a-z][a-z0-9-]*\b[^>]*?\sid\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))[^>]*>"#)
+        .unwrap()
+});
+
+/// Splits the chunks which contain more than one element with an `id` attribute at the start of
+/// each such element, heading every part by the element's id. Html before the first element keeps
+/// the heading of the chunk. Chunks with at most one id are left as they are.
+fn split_chunks_by_dom_id(chunks: Vec<(String, String, String)>) -> Vec<(String, String, String)> {
+    let mut split_chunks = vec![];
+    for (heading, chunk_html, chunk_text) in chunks {
+        let id_elements: Vec<(usize, String)> = DOM_ID_RE
+            .captures_iter(&chunk_html)
+            .filter_map(|captures| {
+                let id = captures
+                    .get(1)
+                    .or(captures.get(2))
+                    .or(captures.get(3))?
+                    .as_str()
+                    .trim();
+                (!id.is_empty()).then(|| (captures.get(0).unwrap().start(), id.to_string()))
+            })
+            .collect();
+
+        if id_elements.len() < 2 {
+            split_chunks.push((heading, chunk_html, chunk_text));
+            continue;
+        }
+
+        let mut parts = vec![(heading, 0)];
+        parts.extend(id_elements.into_iter().map(|(start, id)| (id, start)));
+        for (i, (part_heading, start)) in parts.iter().enumerate() {
+            let end = parts
+                .get(i + 1)
+                .map(|(_, end)| *end)
+                .unwrap_or(chunk_html.len());
+            let part_html = chunk_html[*start..end].trim();
+            let part_text = convert_html_to_text(part_html);
+            if !part_text.trim().is_empty() {
+                split_chunks.push((part_heading.clone(), part_html.to_string(), part_text));
+            }
+        }
+    }
+    split_chunks
+}
+
+fn overlap_html(words: &[&str], direction: &str) -> String {
+    format!(
+        "<p class=\"trieve-overlap-{}\">{}</p>",
+        direction,
+        escape_html(&words.join(" "))
+    )
+}
+
+/// Adds the words of the neighbouring chunks to each chunk by the overlap strategy. Overlap taken
+/// from the previous chunk is prepended in a `<p class="trieve-overlap-forward">` and overlap
+/// taken from the next chunk is appended in a `<p class="trieve-overlap-backward">`, so clients
+/// can tell it apart from the chunk's own content.
+fn apply_chunk_overlap(
+    chunks: Vec<(String, String, String)>,
+    overlap_strategy: &OverlapStrategy,
+) -> Vec<(String, String, String)> {
+    let words = overlap_strategy.words;
+    let (forward, backward) = match overlap_strategy.direction {
+        OverlapDirection::Forward => (true, false),
+        OverlapDirection::Backward => (false, true),
+        OverlapDirection::Bidirectional => (true, true),
+    };
+    let chunk_words: Vec<Vec<&str>> = chunks
+        .iter()
+        .map(|(_, _, chunk_text)| chunk_text.split_whitespace().collect())
+        .collect();
+
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(i, (heading, chunk_html, chunk_text))| {
+            let mut chunk_html = chunk_html.clone();
+            let mut chunk_text = chunk_text.clone();
+
+            if let Some(previous_words) = i.checked_sub(1).map(|i| &chunk_words[i]) {
+                if forward && !previous_words.is_empty() {
+                    let tail = &previous_words[previous_words.len().saturating_sub(words)..];
+                    chunk_html = format!("{}{}", overlap_html(tail, "forward"), chunk_html);
+                    chunk_text = format!("{}\n\n{}", tail.join(" "), chunk_text);
+                }
+            }
+            if let Some(next_words) = chunk_words.get(i + 1) {
+                if backward && !next_words.is_empty() {
+                    let head = &next_words[..words.min(next_words.len())];
+                    chunk_html = format!("{}{}", chunk_html, overlap_html(head, "backward"));
+                    chunk_text = format!("{}\n\n{}", chunk_text, head.join(" "));
+                }
+            }
+
+            (heading.clone(), chunk_html, chunk_text)
+        })
+        .collect()
+}
+
+/// Chunks the html of a page with the chunking options of the crawl. `<noscript>` elements are
+/// removed unless `include_noscript_content` is set. Elements matching `chunk_by_element_id`
+/// become their own chunks, the rest of the page is chunked with the chunk strategy, defaulting
+/// to heading based, and split further by element id when `chunk_by_dom_id` is set. The overlap
+/// of `chunk_overlap_strategy` is added between these chunks, in page order.
+pub fn chunk_page_html(html: &str, crawl_options: &CrawlOptions) -> Vec<(String, String, String)> {
+    let html = if crawl_options.include_noscript_content.unwrap_or(false) {
+        html.to_string()
+    } else {
+        remove_noscript_elements(html)
+    };
+
+    let (mut chunks, remaining_html) = match crawl_options
+        .chunk_by_element_id
+        .as_deref()
+        .and_then(|selector| Selector::parse(selector).ok())
+    {
+        Some(selector) => chunk_html_by_element_id(&html, &selector),
+        None => (vec![], html),
+    };
+
+    let remaining_chunks = match crawl_options.chunk_strategy {
+        None | Some(ChunkStrategy::HeadingBased) => chunk_html(
+            &remaining_html,
+            crawl_options.split_on_horizontal_rule.unwrap_or(false),
+        ),
+        Some(ChunkStrategy::FixedSize { words }) => {
+            chunk_html_by_fixed_size(&remaining_html, words)
+        }
+        Some(ChunkStrategy::SentenceBased { max_sentences }) => {
+            chunk_html_by_sentences(&remaining_html, max_sentences)
+        }
+    };
+    let remaining_chunks = if crawl_options.chunk_by_dom_id.unwrap_or(false) {
+        split_chunks_by_dom_id(remaining_chunks)
+    } else {
+        remaining_chunks
+    };
+    let remaining_chunks = match crawl_options.chunk_overlap_strategy {
+        Some(ref overlap_strategy) => apply_chunk_overlap(remaining_chunks, overlap_strategy),
+        None => remaining_chunks,
+    };
+
+    chunks.extend(remaining_chunks);
+    chunks
+}
+
+fn url_path_heading(page_url: &str) -> String {
+    match reqwest::Url::parse(page_url) {
+        Ok(url) => match url.path().trim_matches('/') {
+            "" => url.host_str().unwrap_or_default().to_string(),
+            path => path.to_string(),
+        },
+        Err(_) => String::new(),
+    }
+}
+
+fn selector_text(html: &Html, selector: &Selector) -> Option<String> {
+    html.select(selector)
+        .map(|element| element.text().collect::<Vec<_>>().join(" "))
+        .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+        .find(|text| !text.is_empty())
+}
+
+/// Fills in the heading of the chunks which have none with the title extraction strategy of the
+/// crawl. Headings found in the chunk always take precedence.
+pub fn apply_title_extraction_strategy(
+    chunks: &mut [(String, String, String)],
+    crawl_options: &CrawlOptions,
+    page_html: &str,
+    page_title: &str,
+    page_url: &str,
+) {
+    let strategy = match crawl_options.title_extraction_strategy {
+        None | Some(TitleExtractionStrategy::FirstHeading) => return,
+        Some(ref strategy) => strategy,
+    };
+
+    let custom_selector = match strategy {
+        TitleExtractionStrategy::Custom(selector) => Selector::parse(selector).ok(),
+        _ => None,
+    };
+    let mut page_selector_text: Option<Option<String>> = None;
+
+    for (heading, chunk_html, chunk_text) in chunks.iter_mut() {
+        if !heading.trim().is_empty() {
+            continue;
+        }
+
+        *heading = match strategy {
+            TitleExtractionStrategy::FirstHeading => continue,
+            TitleExtractionStrategy::MetadataTitle => page_title.to_string(),
+            TitleExtractionStrategy::UrlPath => url_path_heading(page_url),
+            TitleExtractionStrategy::FirstSentence => first_sentence_heading(chunk_text),
+            TitleExtractionStrategy::Custom(_) => match custom_selector {
+                Some(ref selector) => selector_text(&Html::parse_fragment(chunk_html), selector)
+                    .or_else(|| {
+                        page_selector_text
+                            .get_or_insert_with(|| {
+                                selector_text(&Html::parse_document(page_html), selector)
+                            })
+                            .clone()
+                    })
+                    .unwrap_or_default(),
+                None => continue,
+            },
+        };
+    }
+}
+
+/// Splits the html at its headings, and at `<hr>` elements when `split_on_horizontal_rule` is
+/// set. Returns `(heading, html, plain_text)` for each chunk.
+pub fn chunk_html(html: &str, split_on_horizontal_rule: bool) -> Vec<(String, String, String)> {
+    let re = if split_on_horizontal_rule {
+        Regex::new(r"(?i)<h[1-6].*?>|<hr\b[^>]*>").unwrap()
+    } else {
+        Regex::new(r"(?i)<h[1-6].*?>").unwrap()
+    };
     let mut chunks = Vec::new();
     let mut current_chunk = String::new();
     let mut last_end = 0;
     let mut short_chunk: Option<String> = None;
+    let mut last_heading = String::new();
 
     for cap in re.find_iter(html) {
         if last_end != cap.start() {
@@ -619,15 +4247,27 @@ pub fn chunk_html(html: &str) -> Vec<(String, String)> {
                     .is_empty()
                 {
                     short_chunk = Some(current_chunk);
+                } else if split_on_horizontal_rule {
+                    let heading = horizontal_rule_section_heading(
+                        headings_text,
+                        &chunk_text,
+                        &mut last_heading,
+                    );
+                    chunks.push((heading, current_chunk, chunk_text));
                 } else {
-                    chunks.push((headings_text, current_chunk));
+                    chunks.push((headings_text, current_chunk, chunk_text));
                 }
             } else {
                 short_chunk = Some(current_chunk);
             }
         }
 
-        current_chunk = cap.as_str().to_string();
+        // Horizontal rules only mark a boundary, they are not part of the next chunk
+        current_chunk = if cap.as_str()[1..].to_lowercase().starts_with("hr") {
+            String::new()
+        } else {
+            cap.as_str().to_string()
+        };
         last_end = cap.end();
     }
 
@@ -635,25 +4275,666 @@ pub fn chunk_html(html: &str) -> Vec<(String, String)> {
         current_chunk.push_str(&html[last_end..]);
     }
 
-    if !current_chunk.is_empty() {
+    let last_chunk = if !current_chunk.is_empty() {
         let trimmed_chunk = current_chunk.trim().to_string();
 
         if let Some(prev_short_chunk) = short_chunk.take() {
-            current_chunk = format!("{} {}", prev_short_chunk, trimmed_chunk);
+            Some(format!("{} {}", prev_short_chunk, trimmed_chunk))
         } else {
-            current_chunk = trimmed_chunk;
+            Some(trimmed_chunk)
         }
+    } else {
+        short_chunk
+    };
 
-        let headings_text = extract_all_headings(&current_chunk);
-        chunks.push((headings_text, current_chunk));
-    } else if let Some(last_short_chunk) = short_chunk {
-        let headings_text = extract_all_headings(&last_short_chunk);
-        chunks.push((headings_text, last_short_chunk));
+    if let Some(last_chunk) = last_chunk {
+        let headings_text = extract_all_headings(&last_chunk);
+        let chunk_text = convert_html_to_text(&last_chunk);
+        if split_on_horizontal_rule {
+            let heading =
+                horizontal_rule_section_heading(headings_text, &chunk_text, &mut last_heading);
+            chunks.push((heading, last_chunk, chunk_text));
+        } else {
+            chunks.push((headings_text, last_chunk, chunk_text));
+        }
     }
 
     chunks
 }
 
+/// ISO 639-1 code of the language of a chunk, the same format as [`detect_page_language`].
+/// `None` when whatlang is not reliable or the language has no ISO 639-1 code.
+pub fn detect_language(text: &str) -> Option<String> {
+    whatlang::detect(text)
+        .filter(|info| info.is_reliable())
+        .and_then(|info| iso_639_1_code(info.lang().code()))
+        .map(|code| code.to_string())
+}
+
+/// Number of characters from the start of a page's text used to detect its language
+const PAGE_LANGUAGE_DETECTION_CHARS: usize = 1000;
+/// Lowest whatlang confidence at which a detected page language is used
+const PAGE_LANGUAGE_MIN_CONFIDENCE: f64 = 0.75;
+
+/// ISO 639-1 code of a language detected by whatlang, which uses ISO 639-3 codes
+fn iso_639_1_code(iso_639_3_code: &str) -> Option<&'static str> {
+    let code = match iso_639_3_code {
+        "afr" => "af",
+        "aka" => "ak",
+        "amh" => "am",
+        "ara" => "ar",
+        "aze" => "az",
+        "bel" => "be",
+        "ben" => "bn",
+        "bul" => "bg",
+        "cat" => "ca",
+        "ces" => "cs",
+        "cmn" => "zh",
+        "dan" => "da",
+        "deu" => "de",
+        "ell" => "el",
+        "eng" => "en",
+        "epo" => "eo",
+        "est" => "et",
+        "fin" => "fi",
+        "fra" => "fr",
+        "guj" => "gu",
+        "heb" => "he",
+        "hin" => "hi",
+        "hrv" => "hr",
+        "hun" => "hu",
+        "hye" => "hy",
+        "ind" => "id",
+        "ita" => "it",
+        "jav" => "jv",
+        "jpn" => "ja",
+        "kan" => "kn",
+        "kat" => "ka",
+        "khm" => "km",
+        "kor" => "ko",
+        "lat" => "la",
+        "lav" => "lv",
+        "lit" => "lt",
+        "mal" => "ml",
+        "mar" => "mr",
+        "mkd" => "mk",
+        "mya" => "my",
+        "nep" => "ne",
+        "nld" => "nl",
+        "nob" => "nb",
+        "ori" => "or",
+        "pan" => "pa",
+        "pes" => "fa",
+        "pol" => "pl",
+        "por" => "pt",
+        "ron" => "ro",
+        "rus" => "ru",
+        "sin" => "si",
+        "slk" => "sk",
+        "slv" => "sl",
+        "sna" => "sn",
+        "spa" => "es",
+        "srp" => "sr",
+        "swe" => "sv",
+        "tam" => "ta",
+        "tel" => "te",
+        "tgl" => "tl",
+        "tha" => "th",
+        "tuk" => "tk",
+        "tur" => "tr",
+        "ukr" => "uk",
+        "urd" => "ur",
+        "uzb" => "uz",
+        "vie" => "vi",
+        "yid" => "yi",
+        "zul" => "zu",
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// ISO 639-1 code of the language of a page, detected from the start of its text. `None` when
+/// whatlang is not confident enough.
+pub fn detect_page_language(page_text: &str) -> Option<String> {
+    let text: String = page_text
+        .chars()
+        .take(PAGE_LANGUAGE_DETECTION_CHARS)
+        .collect();
+
+    whatlang::detect(&text)
+        .filter(|info| info.confidence() > PAGE_LANGUAGE_MIN_CONFIDENCE)
+        .and_then(|info| iso_639_1_code(info.lang().code()))
+        .map(|code| code.to_string())
+}
+
+/// Maximum number of concurrent OpenAI requests made while enriching the chunks of a crawl
+const MAX_CONCURRENT_ENRICHMENT_REQUESTS: usize = 10;
+
+const DEFAULT_SUMMARY_PROMPT: &str =
+    "Summarize the following text in at most three sentences. Reply with only the summary.";
+const DEFAULT_TAG_PROMPT: &str = "List up to five short topic tags for the following text. Reply with only the tags as a comma separated list.";
+
+/// Parses a comma separated list of tags generated by the model
+fn parse_generated_tags(completion: &str) -> Vec<String> {
+    let mut tags: Vec<String> = vec![];
+    for tag in completion.split([',', '\n']) {
+        let tag = tag
+            .trim()
+            .trim_start_matches(['-', '*', '#'])
+            .trim()
+            .trim_matches('"')
+            .to_lowercase();
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+async fn generate_enrichment_completion(
+    client: &Client,
+    config: &OpenAIEnrichConfig,
+    prompt: &str,
+    chunk_text: &str,
+    semaphore: &Semaphore,
+) -> Result<String, ServiceError> {
+    let _permit = semaphore
+        .acquire()
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?;
+
+    let parameters = ChatCompletionParameters {
+        model: config.model.clone(),
+        messages: vec![ChatMessage::User {
+            content: ChatMessageContent::Text(format!("{}\n\n{}", prompt, chunk_text)),
+            name: None,
+        }],
+        max_completion_tokens: Some(config.max_tokens),
+        ..Default::default()
+    };
+
+    let completion = client.chat().create(parameters).await.map_err(|err| {
+        ServiceError::BadRequest(format!("Bad response from LLM server provider: {}", err))
+    })?;
+
+    match completion.choices.first().map(|choice| &choice.message) {
+        Some(ChatMessage::Assistant {
+            content: Some(ChatMessageContent::Text(content)),
+            ..
+        }) => Ok(content.trim().to_string()),
+        _ => Err(ServiceError::InternalServerError(
+            "Failed to get response completion; no text in first choice".to_string(),
+        )),
+    }
+}
+
+/// Adds an AI generated summary and tags to each chunk. Chunks which could not be enriched are
+/// left unchanged.
+pub async fn enrich_chunks_with_openai(
+    chunks: Vec<ChunkReqPayload>,
+    config: &OpenAIEnrichConfig,
+    dataset_config: &DatasetConfiguration,
+) -> Result<Vec<ChunkReqPayload>, ServiceError> {
+    let base_url = if dataset_config.LLM_BASE_URL.is_empty() {
+        "https://api.openai.com/v1".to_string()
+    } else {
+        dataset_config.LLM_BASE_URL.clone()
+    };
+    // Same rule as for completions, the OpenAI key is only ever sent to OpenAI
+    let api_key = if !dataset_config.LLM_API_KEY.is_empty() {
+        dataset_config.LLM_API_KEY.clone()
+    } else if base_url.contains("openai.com") {
+        std::env::var("OPENAI_API_KEY").map_err(|_| {
+            ServiceError::BadRequest(
+                "OPENAI_API_KEY must be set to enrich crawled chunks".to_string(),
+            )
+        })?
+    } else {
+        std::env::var("LLM_API_KEY").map_err(|_| {
+            ServiceError::BadRequest(
+                "LLM_API_KEY must be set to enrich crawled chunks with openrouter or a self-hosted llm"
+                    .to_string(),
+            )
+        })?
+    };
+
+    let client = Client {
+        headers: None,
+        project: None,
+        api_key,
+        http_client: reqwest::Client::new(),
+        base_url,
+        organization: None,
+    };
+    let semaphore = Semaphore::new(MAX_CONCURRENT_ENRICHMENT_REQUESTS);
+    let summary_prompt = config
+        .summary_prompt
+        .as_deref()
+        .unwrap_or(DEFAULT_SUMMARY_PROMPT);
+    let tag_prompt = config.tag_prompt.as_deref().unwrap_or(DEFAULT_TAG_PROMPT);
+
+    let enriched_chunks = chunks.into_iter().map(|mut chunk| {
+        let client = &client;
+        let semaphore = &semaphore;
+        async move {
+            let chunk_text = convert_html_to_text(&chunk.chunk_html.clone().unwrap_or_default());
+            if chunk_text.trim().is_empty() {
+                return chunk;
+            }
+
+            let (summary, tags) = futures::future::join(
+                generate_enrichment_completion(
+                    client,
+                    config,
+                    summary_prompt,
+                    &chunk_text,
+                    semaphore,
+                ),
+                generate_enrichment_completion(client, config, tag_prompt, &chunk_text, semaphore),
+            )
+            .await;
+
+            match summary {
+                Ok(summary) => {
+                    let metadata = chunk.metadata.get_or_insert_with(|| serde_json::json!({}));
+                    metadata["ai_summary"] = serde_json::json!(summary);
+                }
+                Err(err) => log::error!("Failed to summarize chunk: {:?}", err),
+            }
+
+            match tags {
+                Ok(tags) => {
+                    let tag_set = chunk.tag_set.get_or_insert_with(Vec::new);
+                    for tag in parse_generated_tags(&tags) {
+                        if !tag_set.contains(&tag) {
+                            tag_set.push(tag);
+                        }
+                    }
+                }
+                Err(err) => log::error!("Failed to generate tags for chunk: {:?}", err),
+            }
+
+            chunk
+        }
+    });
+
+    Ok(futures::future::join_all(enriched_chunks).await)
+}
+
+#[derive(Debug, Clone)]
+pub struct FigureChunk {
+    pub heading: String,
+    pub caption_html: String,
+    pub image_url: Option<String>,
+}
+
+pub fn chunk_figures(html: &str) -> Vec<FigureChunk> {
+    let fragment = Html::parse_fragment(html);
+    let figure_selector = Selector::parse("figure").unwrap();
+    let caption_selector = Selector::parse("figcaption").unwrap();
+    let img_selector = Selector::parse("img").unwrap();
+
+    fragment
+        .select(&figure_selector)
+        .filter_map(|figure| {
+            let caption = figure.select(&caption_selector).next()?;
+            let caption_text = caption.text().collect::<String>().trim().to_string();
+            if caption_text.is_empty() {
+                return None;
+            }
+
+            let heading = caption_text
+                .split_whitespace()
+                .take(8)
+                .collect::<Vec<&str>>()
+                .join(" ");
+            let image_url = figure
+                .select(&img_selector)
+                .next()
+                .and_then(|img| img.value().attr("src"))
+                .map(|src| src.to_string());
+
+            Some(FigureChunk {
+                heading,
+                caption_html: caption.html(),
+                image_url,
+            })
+        })
+        .collect()
+}
+
+/// Weight of the question and answer chunks of FAQ sections, which are good matches for
+/// question-like queries
+pub const FAQ_CHUNK_WEIGHT: f64 = 1.5;
+
+#[derive(Debug, Clone)]
+pub struct FaqChunk {
+    pub question: String,
+    pub answer_html: String,
+}
+
+/// Question and answer pairs of the html, from `<details>` elements with a `<summary>` and from
+/// headings ending with `?` which are immediately followed by a paragraph.
+pub fn chunk_faqs(html: &str) -> Vec<FaqChunk> {
+    let fragment = Html::parse_fragment(html);
+    let details_selector = Selector::parse("details").unwrap();
+    let summary_selector = Selector::parse("summary").unwrap();
+    let heading_selector = Selector::parse("h1, h2, h3, h4, h5, h6").unwrap();
+    let element_text = |element: scraper::ElementRef| {
+        element
+            .text()
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ")
+    };
+
+    let details_faqs = fragment.select(&details_selector).filter_map(|details| {
+        let summary = details.select(&summary_selector).next()?;
+        let answer_html = details
+            .inner_html()
+            .replacen(&summary.html(), "", 1)
+            .trim()
+            .to_string();
+        Some(FaqChunk {
+            question: element_text(summary),
+            answer_html,
+        })
+    });
+
+    let heading_faqs = fragment
+        .select(&heading_selector)
+        .filter(|heading| element_text(*heading).ends_with('?'))
+        .filter_map(|heading| {
+            let answer = heading
+                .next_siblings()
+                .find_map(scraper::ElementRef::wrap)
+                .filter(|sibling| sibling.value().name() == "p")?;
+            Some(FaqChunk {
+                question: element_text(heading),
+                answer_html: answer.html(),
+            })
+        });
+
+    details_faqs
+        .chain(heading_faqs)
+        .filter(|faq| {
+            !faq.question.is_empty() && !convert_html_to_text(&faq.answer_html).trim().is_empty()
+        })
+        .collect()
+}
+
+/// Terms defined in the html and their definitions. Abbreviations are defined by the `title` of
+/// their `<abbr>` element, and `<dfn>` terms by the text of the paragraph they appear in. The first
+/// definition of a term is kept.
+pub fn extract_glossary(html: &str) -> HashMap<String, String> {
+    let fragment = Html::parse_fragment(html);
+    let abbr_selector = Selector::parse("abbr[title]").unwrap();
+    let dfn_selector = Selector::parse("dfn").unwrap();
+    let normalize = |text: &str| text.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+    let abbr_terms = fragment.select(&abbr_selector).map(|abbr| {
+        (
+            normalize(&abbr.text().collect::<String>()),
+            normalize(abbr.value().attr("title").unwrap_or_default()),
+        )
+    });
+
+    let dfn_terms = fragment.select(&dfn_selector).filter_map(|dfn| {
+        let term = dfn
+            .value()
+            .attr("title")
+            .map(|title| title.to_string())
+            .unwrap_or_else(|| dfn.text().collect::<String>());
+        let definition = dfn
+            .ancestors()
+            .filter_map(scraper::ElementRef::wrap)
+            .find(|ancestor| matches!(ancestor.value().name(), "p" | "dd" | "li" | "td"))
+            .or_else(|| dfn.parent().and_then(scraper::ElementRef::wrap))?;
+        Some((
+            normalize(&term),
+            normalize(&definition.text().collect::<String>()),
+        ))
+    });
+
+    let mut glossary = HashMap::new();
+    for (term, definition) in abbr_terms.chain(dfn_terms) {
+        if !term.is_empty() && !definition.is_empty() && term != definition {
+            glossary.entry(term).or_insert(definition);
+        }
+    }
+    glossary
+}
+
+/// Html of the glossary chunk of a page, a definition list sorted by term
+pub fn glossary_to_html(glossary: &HashMap<String, String>) -> String {
+    let mut terms = glossary.iter().collect::<Vec<_>>();
+    terms.sort();
+
+    let entries = terms
+        .into_iter()
+        .map(|(term, definition)| {
+            format!(
+                "<dt>{}</dt><dd>{}</dd>",
+                escape_html(term),
+                escape_html(definition)
+            )
+        })
+        .collect::<String>();
+    format!("<dl>{}</dl>", entries)
+}
+
+static MATHML_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<math\b[^>]*>.*?</math>").unwrap());
+
+const MATHML_FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+    "log", "ln", "exp", "lim", "max", "min", "sup", "inf", "det", "gcd",
+];
+
+fn mathml_symbol_to_latex(symbol: &str) -> String {
+    let latex = match symbol {
+        "α" => "\\alpha ",
+        "β" => "\\beta ",
+        "γ" => "\\gamma ",
+        "Γ" => "\\Gamma ",
+        "δ" => "\\delta ",
+        "Δ" => "\\Delta ",
+        "ε" | "ϵ" => "\\epsilon ",
+        "ζ" => "\\zeta ",
+        "η" => "\\eta ",
+        "θ" => "\\theta ",
+        "Θ" => "\\Theta ",
+        "κ" => "\\kappa ",
+        "λ" => "\\lambda ",
+        "Λ" => "\\Lambda ",
+        "μ" => "\\mu ",
+        "ν" => "\\nu ",
+        "ξ" => "\\xi ",
+        "π" => "\\pi ",
+        "Π" => "\\Pi ",
+        "ρ" => "\\rho ",
+        "σ" => "\\sigma ",
+        "Σ" => "\\Sigma ",
+        "τ" => "\\tau ",
+        "φ" | "ϕ" => "\\phi ",
+        "Φ" => "\\Phi ",
+        "χ" => "\\chi ",
+        "ψ" => "\\psi ",
+        "Ψ" => "\\Psi ",
+        "ω" => "\\omega ",
+        "Ω" => "\\Omega ",
+        "×" => "\\times ",
+        "·" | "⋅" => "\\cdot ",
+        "÷" => "\\div ",
+        "±" => "\\pm ",
+        "∓" => "\\mp ",
+        "−" => "-",
+        "≤" => "\\leq ",
+        "≥" => "\\geq ",
+        "≠" => "\\neq ",
+        "≈" => "\\approx ",
+        "≡" => "\\equiv ",
+        "∝" => "\\propto ",
+        "∞" => "\\infty ",
+        "∑" => "\\sum ",
+        "∏" => "\\prod ",
+        "∫" => "\\int ",
+        "∮" => "\\oint ",
+        "∂" => "\\partial ",
+        "∇" => "\\nabla ",
+        "√" => "\\surd ",
+        "∈" => "\\in ",
+        "∉" => "\\notin ",
+        "⊂" => "\\subset ",
+        "⊆" => "\\subseteq ",
+        "∪" => "\\cup ",
+        "∩" => "\\cap ",
+        "∅" => "\\emptyset ",
+        "∀" => "\\forall ",
+        "∃" => "\\exists ",
+        "¬" => "\\neg ",
+        "∧" => "\\wedge ",
+        "∨" => "\\vee ",
+        "→" => "\\to ",
+        "←" => "\\leftarrow ",
+        "⇒" => "\\Rightarrow ",
+        "⇔" => "\\Leftrightarrow ",
+        "…" | "⋯" => "\\dots ",
+        "′" => "'",
+        "{" => "\\{",
+        "}" => "\\}",
+        "%" => "\\%",
+        "#" => "\\#",
+        "&" => "\\&",
+        "_" => "\\_",
+        "\u{2061}" | "\u{2062}" | "\u{2063}" => "",
+        other => return other.to_string(),
+    };
+
+    latex.to_string()
+}
+
+fn mathml_text_to_latex(text: &str) -> String {
+    text.chars()
+        .map(|c| mathml_symbol_to_latex(c.encode_utf8(&mut [0; 4])))
+        .collect()
+}
+
+fn mathml_children_to_latex(element: scraper::ElementRef) -> Vec<String> {
+    element
+        .children()
+        .filter_map(scraper::ElementRef::wrap)
+        .map(mathml_element_to_latex)
+        .collect()
+}
+
+fn mathml_element_to_latex(element: scraper::ElementRef) -> String {
+    let text = || element.text().collect::<String>().trim().to_string();
+    // The children are converted at most once, and only by the elements which use them
+    let converted_children = std::cell::OnceCell::new();
+    let children = || converted_children.get_or_init(|| mathml_children_to_latex(element));
+    let child = |index: usize| children().get(index).cloned().unwrap_or_default();
+
+    match element.value().name() {
+        "mi" => {
+            let identifier = text();
+            if MATHML_FUNCTIONS.contains(&identifier.as_str()) {
+                format!("\\{} ", identifier)
+            } else if identifier.chars().count() > 1 {
+                format!("\\mathrm{{{}}}", mathml_text_to_latex(&identifier))
+            } else {
+                mathml_text_to_latex(&identifier)
+            }
+        }
+        "mn" => text(),
+        "mo" => mathml_text_to_latex(&text()),
+        "mtext" => format!("\\text{{{}}}", mathml_text_to_latex(&text())),
+        "ms" => format!("\\text{{\"{}\"}}", mathml_text_to_latex(&text())),
+        "mspace" => " ".to_string(),
+        "mfrac" => format!("\\frac{{{}}}{{{}}}", child(0), child(1)),
+        "msqrt" => format!("\\sqrt{{{}}}", children().concat()),
+        "mroot" => format!("\\sqrt[{}]{{{}}}", child(1), child(0)),
+        "msup" => format!("{{{}}}^{{{}}}", child(0), child(1)),
+        "msub" => format!("{{{}}}_{{{}}}", child(0), child(1)),
+        "msubsup" => format!("{{{}}}_{{{}}}^{{{}}}", child(0), child(1), child(2)),
+        "munder" => format!("{}_{{{}}}", child(0), child(1)),
+        "mover" => format!("\\overset{{{}}}{{{}}}", child(1), child(0)),
+        "munderover" => format!("{}_{{{}}}^{{{}}}", child(0), child(1), child(2)),
+        "mfenced" => {
+            let open = element.value().attr("open").unwrap_or("(");
+            let close = element.value().attr("close").unwrap_or(")");
+            let separator = element
+                .value()
+                .attr("separators")
+                .and_then(|separators| separators.trim().chars().next())
+                .unwrap_or(',');
+            format!(
+                "\\left{} {} \\right{}",
+                mathml_text_to_latex(open),
+                children().join(&separator.to_string()),
+                mathml_text_to_latex(close)
+            )
+        }
+        "mtable" => format!(
+            "\\begin{{matrix}} {} \\end{{matrix}}",
+            children().join(" \\\\ ")
+        ),
+        "mtr" | "mlabeledtr" => children().join(" & "),
+        "semantics" => {
+            let tex_annotation = element
+                .children()
+                .filter_map(scraper::ElementRef::wrap)
+                .find(|annotation| {
+                    annotation.value().name() == "annotation"
+                        && annotation.value().attr("encoding") == Some("application/x-tex")
+                });
+            match tex_annotation {
+                Some(annotation) => annotation.text().collect::<String>().trim().to_string(),
+                None => child(0),
+            }
+        }
+        "annotation" | "annotation-xml" | "mphantom" => String::new(),
+        _ => children().concat(),
+    }
+}
+
+/// Converts a MathML `<math>` element to LaTeX. Only the presentation elements commonly found
+/// on web pages are supported, unknown elements contribute their children.
+fn mathml_to_latex(math_ml: &str) -> String {
+    let fragment = Html::parse_fragment(math_ml);
+    let math_selector = Selector::parse("math").unwrap();
+
+    fragment
+        .select(&math_selector)
+        .next()
+        .map(mathml_element_to_latex)
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Replaces every `<math>` element in the html with its LaTeX wrapped in `$...$` so equations
+/// survive text extraction. Returns the new html and the original MathML of each equation.
+pub fn replace_mathml_with_latex(html: &str) -> (String, Vec<String>) {
+    let mut math_ml = vec![];
+
+    let replaced_html = MATHML_RE.replace_all(html, |captures: &regex::Captures| {
+        let equation = captures[0].to_string();
+        let latex = mathml_to_latex(&equation);
+        math_ml.push(equation);
+        format!(
+            "${}$",
+            latex
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+        )
+    });
+
+    (replaced_html.to_string(), math_ml)
+}
+
 fn extract_all_headings(html: &str) -> String {
     let fragment = Html::parse_fragment(html);
     let heading_selector = Selector::parse("h1, h2, h3, h4, h5, h6").unwrap();
@@ -664,3 +4945,658 @@ fn extract_all_headings(html: &str) -> String {
         .collect::<Vec<String>>()
         .join("\n")
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::operators::completion_operator::error_rate_above_threshold;
+
+    fn document(url: &str) -> Document {
+        serde_json::from_value(serde_json::json!({
+            "markdown": format!("# {}", url),
+            "html": format!("<h1>{}</h1><p>Content of {}</p>", url, url),
+            "metadata": {
+                "title": url,
+                "sourceURL": url,
+                "statusCode": 200,
+            },
+        }))
+        .unwrap()
+    }
+
+    fn crawl_options(site_url: &str) -> CrawlOptions {
+        serde_json::from_value(serde_json::json!({
+            "site_url": site_url,
+            "interval": "daily",
+            "limit": 10,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn chunk_page_html_uses_chunk_strategy() {
+        let html = "<p>First sentence here. Second one follows! Is this the third?</p>";
+
+        let mut options = crawl_options("https://example.com");
+
+        options.chunk_strategy = Some(ChunkStrategy::FixedSize { words: 4 });
+        let fixed_size = chunk_page_html(html, &options);
+        assert_eq!(
+            fixed_size
+                .iter()
+                .map(|chunk| chunk.1.as_str())
+                .collect::<Vec<&str>>(),
+            vec![
+                "<p>First sentence here. Second</p>",
+                "<p>one follows! Is this</p>",
+                "<p>the third?</p>",
+            ]
+        );
+
+        options.chunk_strategy = Some(ChunkStrategy::SentenceBased { max_sentences: 2 });
+        let sentence_based = chunk_page_html(html, &options);
+        assert_eq!(
+            sentence_based,
+            vec![
+                (
+                    "First sentence here.".to_string(),
+                    "<p>First sentence here. Second one follows!</p>".to_string(),
+                    "First sentence here. Second one follows!".to_string()
+                ),
+                (
+                    "Is this the third?".to_string(),
+                    "<p>Is this the third?</p>".to_string(),
+                    "Is this the third?".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn replace_mathml_with_latex_substitutes_equations() {
+        let html = r#"<p>Roots are <math><mi>x</mi><mo>=</mo><mfrac><mrow><mo>−</mo><mi>b</mi><mo>±</mo><msqrt><msup><mi>b</mi><mn>2</mn></msup><mo>−</mo><mn>4</mn><mi>a</mi><mi>c</mi></msqrt></mrow><mrow><mn>2</mn><mi>a</mi></mrow></mfrac></math> in general.</p>"#;
+
+        let (replaced_html, math_ml) = replace_mathml_with_latex(html);
+
+        assert_eq!(
+            replaced_html,
+            r"<p>Roots are $x=\frac{-b\pm \sqrt{{b}^{2}-4ac}}{2a}$ in general.</p>"
+        );
+        assert_eq!(math_ml.len(), 1);
+        assert!(math_ml[0].starts_with("<math>"));
+    }
+
+    #[test]
+    fn mathml_to_latex_prefers_tex_annotations() {
+        let math_ml = r#"<math><semantics><mrow><msub><mi>a</mi><mi>n</mi></msub></mrow><annotation encoding="application/x-tex">a_n</annotation></semantics></math>"#;
+
+        assert_eq!(mathml_to_latex(math_ml), "a_n");
+    }
+
+    #[test]
+    fn extract_favicon_url_prefers_32px_icons() {
+        let html = r#"<html><head>
+            <link rel="apple-touch-icon" href="/apple.png">
+            <link rel="icon" sizes="16x16" href="/icon-16.png">
+            <link rel="icon" sizes="32x32" href="icons/icon-32.png">
+            <link rel="shortcut icon" href="https://cdn.example.com/favicon.ico">
+        </head><body></body></html>"#;
+
+        assert_eq!(
+            extract_favicon_url(html, "https://example.com/docs/page"),
+            Some("https://example.com/docs/icons/icon-32.png".to_string())
+        );
+        assert_eq!(
+            extract_favicon_url(
+                r#"<link rel="Shortcut Icon" href="/favicon.ico">"#,
+                "https://example.com/docs/page"
+            ),
+            Some("https://example.com/favicon.ico".to_string())
+        );
+        assert_eq!(
+            extract_favicon_url("<p>No icons</p>", "https://example.com"),
+            None
+        );
+    }
+
+    #[test]
+    fn allowed_schemes_restrict_crawled_urls() {
+        let https_only = vec!["https".to_string()];
+
+        assert!(url_has_allowed_scheme("http://example.com", None));
+        assert!(!url_has_allowed_scheme("ftp://example.com", None));
+        assert!(url_has_allowed_scheme(
+            "https://example.com",
+            Some(&https_only)
+        ));
+        assert!(!url_has_allowed_scheme(
+            "http://example.com",
+            Some(&https_only)
+        ));
+
+        let mut options = crawl_options("http://example.com");
+        options.allowed_schemes = Some(https_only);
+        assert!(validate_crawl_options(&options).is_err());
+
+        options.site_url = Some("https://example.com".to_string());
+        assert!(validate_crawl_options(&options).is_ok());
+
+        options.allowed_schemes = Some(vec!["ftp".to_string()]);
+        assert!(validate_crawl_options(&options).is_err());
+    }
+
+    #[test]
+    fn chunk_page_html_splits_elements_by_id() {
+        let html = r#"<h1>Guide</h1><p>Welcome to the guide for this product.</p><section id="authentication"><h2>Auth</h2><p>Use an api key.</p><section id="nested"><p>Nested text</p></section></section><section id="errors"><p>Errors are returned as JSON.</p></section>"#;
+
+        let mut options = crawl_options("https://example.com");
+        options.chunk_by_element_id = Some("section[id]".to_string());
+        let chunks = chunk_page_html(html, &options);
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].0, "authentication");
+        assert!(chunks[0].1.contains("Use an api key.") && chunks[0].1.contains("Nested text"));
+        assert_eq!(chunks[1].0, "errors");
+        assert_eq!(chunks[1].1, "<p>Errors are returned as JSON.</p>");
+        assert_eq!(chunks[1].2.trim(), "Errors are returned as JSON.");
+        assert_eq!(chunks[2].0, "Guide");
+        assert!(!chunks[2].1.contains("section"));
+    }
+
+    #[test]
+    fn page_js_actions_are_sent_as_firecrawl_actions() {
+        let options: CrawlOptions = serde_json::from_value(serde_json::json!({
+            "site_url": "https://actions.example.com",
+            "page_js_actions": [
+                { "type": "click", "selector": "#show-more", "delay_ms": 500 },
+                { "type": "scroll_to_bottom" },
+                { "type": "wait_for_selector", "selector": ".results" },
+            ],
+        }))
+        .unwrap();
+        assert!(validate_crawl_options(&options).is_ok());
+
+        let request = serde_json::to_value(FirecrawlCrawlRequest::from(options)).unwrap();
+        assert_eq!(
+            request["scrapeOptions"]["actions"],
+            serde_json::json!([
+                { "type": "click", "selector": "#show-more" },
+                { "type": "wait", "milliseconds": 500 },
+                {
+                    "type": "executeJavascript",
+                    "script": "window.scrollTo(0, document.body.scrollHeight)",
+                },
+                { "type": "wait", "selector": ".results" },
+            ])
+        );
+
+        let missing_selector: CrawlOptions = serde_json::from_value(serde_json::json!({
+            "site_url": "https://actions.example.com",
+            "page_js_actions": [{ "type": "click" }],
+        }))
+        .unwrap();
+        assert!(validate_crawl_options(&missing_selector).is_err());
+    }
+
+    #[test]
+    fn structured_output_schema_is_sent_as_extract_schema() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": { "price": { "type": "number" } },
+        });
+        let options: CrawlOptions = serde_json::from_value(serde_json::json!({
+            "site_url": "https://shop.example.com",
+            "structured_output_schema": schema,
+        }))
+        .unwrap();
+        assert!(validate_crawl_options(&options).is_ok());
+
+        let request = serde_json::to_value(FirecrawlCrawlRequest::from(options)).unwrap();
+        assert_eq!(request["scrapeOptions"]["extract"]["schema"], schema);
+        assert!(request["scrapeOptions"]["formats"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!("extract")));
+
+        let invalid_schema: CrawlOptions = serde_json::from_value(serde_json::json!({
+            "site_url": "https://shop.example.com",
+            "structured_output_schema": "price",
+        }))
+        .unwrap();
+        assert!(validate_crawl_options(&invalid_schema).is_err());
+    }
+
+    #[test]
+    fn parse_generated_tags_splits_and_deduplicates() {
+        assert_eq!(
+            parse_generated_tags("Rust, \"Async\", rust\n- Tokio,  "),
+            vec!["rust", "async", "tokio"]
+        );
+    }
+
+    #[test]
+    fn get_document_kind_prefers_content_type_over_url() {
+        let mut page = document("https://docs.example.com/guide.pdf");
+        assert_eq!(get_document_kind(&page), DocumentKind::Pdf);
+
+        page.content_type = Some("text/html; charset=utf-8".to_string());
+        assert_eq!(get_document_kind(&page), DocumentKind::Html);
+
+        page.content_type = Some("application/ld+json".to_string());
+        assert_eq!(get_document_kind(&page), DocumentKind::Json);
+
+        page.content_type = Some("application/octet-stream".to_string());
+        assert_eq!(get_document_kind(&page), DocumentKind::Pdf);
+
+        assert_eq!(
+            get_document_kind(&document("https://docs.example.com/readme.md")),
+            DocumentKind::Markdown
+        );
+        assert_eq!(
+            get_document_kind(&document("https://docs.example.com/guide")),
+            DocumentKind::Html
+        );
+    }
+
+    #[test]
+    fn chunk_page_html_removes_noscript_content() {
+        let html = r#"<h1>Pricing</h1><noscript><p>Please enable JavaScript to view this page.</p></noscript><p>Every plan includes unlimited seats and email support.</p>"#;
+
+        let mut options = crawl_options("https://example.com");
+        let chunks = chunk_page_html(html, &options);
+        assert_eq!(chunks.len(), 1);
+        assert!(!chunks[0].2.contains("enable JavaScript"));
+        assert!(chunks[0].2.contains("unlimited seats"));
+
+        options.include_noscript_content = Some(true);
+        let chunks = chunk_page_html(html, &options);
+        assert!(chunks[0].2.contains("enable JavaScript"));
+    }
+
+    #[test]
+    fn seed_urls_are_crawled_separately_from_site_url() {
+        let mut options = crawl_options("https://docs.example.com");
+        options.seed_urls = Some(vec![
+            "https://docs.example.com/changelog".to_string(),
+            "https://docs.example.com/api-reference".to_string(),
+        ]);
+        assert!(validate_crawl_options(&options).is_ok());
+
+        // Firecrawl v1 has no startUrls, the crawl worker starts a crawl per seed url instead
+        let request = serde_json::to_value(FirecrawlCrawlRequest::from(options.clone())).unwrap();
+        assert_eq!(request["url"], "https://docs.example.com");
+        assert!(request.get("startUrls").is_none());
+
+        options.seed_urls = Some(vec!["docs.example.com/blog".to_string()]);
+        assert!(validate_crawl_options(&options).is_err());
+    }
+
+    #[test]
+    fn title_extraction_strategy_fills_in_missing_headings() {
+        let page_html = r#"<html><body><p class="lede">Welcome to the docs</p><h2>Setup</h2><p>Install the package with cargo and run it.</p></body></html>"#;
+        let chunks = || {
+            vec![
+                (
+                    String::new(),
+                    "<p>No heading here. And more.</p>".to_string(),
+                    "No heading here. And more.".to_string(),
+                ),
+                (
+                    "Setup".to_string(),
+                    "<h2>Setup</h2><p>Install it.</p>".to_string(),
+                    "Setup Install it.".to_string(),
+                ),
+            ]
+        };
+        let headings = |strategy: Option<TitleExtractionStrategy>| {
+            let mut options = crawl_options("https://titles.example.com");
+            options.title_extraction_strategy = strategy;
+            let mut chunks = chunks();
+            apply_title_extraction_strategy(
+                &mut chunks,
+                &options,
+                page_html,
+                "Docs",
+                "https://titles.example.com/guide/setup/",
+            );
+            chunks
+                .into_iter()
+                .map(|(heading, _, _)| heading)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(headings(None), vec!["", "Setup"]);
+        assert_eq!(
+            headings(Some(TitleExtractionStrategy::MetadataTitle)),
+            vec!["Docs", "Setup"]
+        );
+        assert_eq!(
+            headings(Some(TitleExtractionStrategy::UrlPath)),
+            vec!["guide/setup", "Setup"]
+        );
+        assert_eq!(
+            headings(Some(TitleExtractionStrategy::FirstSentence)),
+            vec!["No heading here.", "Setup"]
+        );
+        assert_eq!(
+            headings(Some(TitleExtractionStrategy::Custom("p.lede".to_string()))),
+            vec!["Welcome to the docs", "Setup"]
+        );
+    }
+
+    #[test]
+    fn chunk_faqs_pairs_questions_with_answers() {
+        let html = r#"
+            <h2>Pricing</h2><p>Plans start at $10.</p>
+            <h3>Can I cancel anytime?</h3><p>Yes, from the billing page.</p>
+            <h3>Is there a free trial?</h3><ul><li>Not a paragraph</li></ul>
+            <details><summary>Do you offer refunds?</summary><p>Within 30 days.</p></details>
+        "#;
+
+        let faqs: Vec<(String, String)> = chunk_faqs(html)
+            .into_iter()
+            .map(|faq| (faq.question, faq.answer_html))
+            .collect();
+        assert_eq!(
+            faqs,
+            vec![
+                (
+                    "Do you offer refunds?".to_string(),
+                    "<p>Within 30 days.</p>".to_string()
+                ),
+                (
+                    "Can I cancel anytime?".to_string(),
+                    "<p>Yes, from the billing page.</p>".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn extract_breadcrumbs_prefers_json_ld_then_nav_then_url_path() {
+        let json_ld_html = r#"<html><head><script type="application/ld+json">
+            {"@context": "https://schema.org", "@graph": [{"@type": "BreadcrumbList", "itemListElement": [
+                {"@type": "ListItem", "position": 2, "name": "Guides", "item": "https://crumbs.example.com/guides"},
+                {"@type": "ListItem", "position": 1, "name": "Docs", "item": {"@id": "/docs"}}
+            ]}]}
+        </script></head><body><nav aria-label="Breadcrumb"><a href="/nav">Nav</a></nav></body></html>"#;
+        assert_eq!(
+            extract_breadcrumbs(json_ld_html, "https://crumbs.example.com/guides/setup"),
+            vec![
+                BreadcrumbItem {
+                    name: "Docs".to_string(),
+                    url: Some("https://crumbs.example.com/docs".to_string()),
+                },
+                BreadcrumbItem {
+                    name: "Guides".to_string(),
+                    url: Some("https://crumbs.example.com/guides".to_string()),
+                },
+            ]
+        );
+
+        let nav_html = r#"<nav aria-label="breadcrumb"><ol><li><a href="/">Home</a></li><li aria-current="page">Setup</li></ol></nav>"#;
+        assert_eq!(
+            extract_breadcrumbs(nav_html, "https://crumbs.example.com/guides/setup"),
+            vec![
+                BreadcrumbItem {
+                    name: "Home".to_string(),
+                    url: Some("https://crumbs.example.com/".to_string()),
+                },
+                BreadcrumbItem {
+                    name: "Setup".to_string(),
+                    url: None,
+                },
+            ]
+        );
+
+        assert_eq!(
+            extract_breadcrumbs(
+                "<p>No breadcrumbs</p>",
+                "https://crumbs.example.com/guides/setup"
+            ),
+            vec![
+                BreadcrumbItem {
+                    name: "guides".to_string(),
+                    url: Some("https://crumbs.example.com/guides".to_string()),
+                },
+                BreadcrumbItem {
+                    name: "setup".to_string(),
+                    url: Some("https://crumbs.example.com/guides/setup".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn linked_document_urls_are_filtered_by_mime_type() {
+        let mut page = document("https://linked.example.com/docs");
+        page.links = Some(vec![
+            "https://linked.example.com/internal.pdf".to_string(),
+            "https://specs.example.org/spec.pdf".to_string(),
+            "https://specs.example.org/spec.pdf".to_string(),
+            "https://github.example.org/repo/README.md".to_string(),
+            "https://specs.example.org/image.png".to_string(),
+            "https://specs.example.org/about".to_string(),
+        ]);
+        let document_types = vec!["application/pdf".to_string(), "text/markdown".to_string()];
+
+        assert_eq!(
+            get_linked_document_urls(&[Some(page), None], &document_types),
+            vec![
+                "https://specs.example.org/spec.pdf",
+                "https://github.example.org/repo/README.md",
+                "https://specs.example.org/about",
+            ]
+        );
+        assert!(is_linked_document_type(
+            "https://specs.example.org/download",
+            Some("application/pdf; charset=binary"),
+            &document_types
+        ));
+        assert!(!is_linked_document_type(
+            "https://specs.example.org/spec.pdf",
+            Some("text/html"),
+            &document_types
+        ));
+    }
+
+    #[test]
+    fn extract_glossary_collects_abbreviations_and_definitions() {
+        let html = r#"
+            <p>Use <abbr title="Hypertext   Markup Language">HTML</abbr> for markup.</p>
+            <p>The <dfn>crawl interval</dfn> is how often a site is crawled again.</p>
+            <p><dfn title="TTL">Time to live</dfn> is how long a cached page is kept.</p>
+            <p>Later <abbr title="Something else">HTML</abbr> and <abbr>API</abbr> mentions.</p>
+        "#;
+
+        let glossary = extract_glossary(html);
+        assert_eq!(
+            glossary,
+            HashMap::from([
+                ("HTML".to_string(), "Hypertext Markup Language".to_string()),
+                (
+                    "crawl interval".to_string(),
+                    "The crawl interval is how often a site is crawled again.".to_string()
+                ),
+                (
+                    "TTL".to_string(),
+                    "Time to live is how long a cached page is kept.".to_string()
+                ),
+            ])
+        );
+        assert_eq!(
+            glossary_to_html(&HashMap::from([
+                ("b".to_string(), "2 < 3".to_string()),
+                ("a".to_string(), "1".to_string()),
+            ])),
+            "<dl><dt>a</dt><dd>1</dd><dt>b</dt><dd>2 &lt; 3</dd></dl>"
+        );
+    }
+
+    #[test]
+    fn split_chunks_by_dom_id_heads_each_part_by_its_id() {
+        let chunk_html = r#"<h2>Install</h2><p>Pick your platform.</p><div id="linux"><p>Run apt install.</p></div><div data-id="x" id='macos'><p>Run brew install.</p></div>"#;
+        let chunks = split_chunks_by_dom_id(vec![
+            (
+                "Install".to_string(),
+                chunk_html.to_string(),
+                "Install Pick your platform. Run apt install. Run brew install.".to_string(),
+            ),
+            (
+                "Usage".to_string(),
+                r#"<h2 id="usage">Usage</h2><p>Start the server.</p>"#.to_string(),
+                "Usage Start the server.".to_string(),
+            ),
+        ]);
+
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| (chunk.0.as_str(), chunk.1.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("Install", "<h2>Install</h2><p>Pick your platform.</p>"),
+                ("linux", r#"<div id="linux"><p>Run apt install.</p></div>"#),
+                (
+                    "macos",
+                    r#"<div data-id="x" id='macos'><p>Run brew install.</p></div>"#
+                ),
+                (
+                    "Usage",
+                    r#"<h2 id="usage">Usage</h2><p>Start the server.</p>"#
+                ),
+            ]
+        );
+        assert!(chunks[1].2.contains("Run apt install."));
+    }
+
+    #[test]
+    fn truncate_html_does_not_cut_characters_or_tags() {
+        assert_eq!(truncate_html("<p>short</p>", 64), "<p>short</p>");
+        assert_eq!(truncate_html("<p>héllo</p>", 5), "<p>h");
+        assert_eq!(truncate_html("<p>hello</p>", 10), "<p>hello");
+        assert_eq!(truncate_html("<p>hello</p>", 9), "<p>hello");
+        assert_eq!(truncate_html("<p>hello</p>", 12), "<p>hello</p>");
+        assert_eq!(truncate_html("<a href=\"https://example.com\">", 8), "");
+    }
+
+    #[test]
+    fn apply_chunk_overlap_adds_words_of_neighbouring_chunks() {
+        let chunks = vec![
+            (
+                "One".to_string(),
+                "<p>alpha beta gamma</p>".to_string(),
+                "alpha beta gamma".to_string(),
+            ),
+            (
+                "Two".to_string(),
+                "<p>delta epsilon</p>".to_string(),
+                "delta epsilon".to_string(),
+            ),
+            (
+                "Three".to_string(),
+                "<p>zeta & eta theta</p>".to_string(),
+                "zeta & eta theta".to_string(),
+            ),
+        ];
+        let overlap = |direction| {
+            apply_chunk_overlap(
+                chunks.clone(),
+                &OverlapStrategy {
+                    direction,
+                    words: 2,
+                },
+            )
+        };
+
+        let forward = overlap(OverlapDirection::Forward);
+        assert_eq!(forward[0], chunks[0]);
+        assert_eq!(
+            forward[1].1,
+            r#"<p class="trieve-overlap-forward">beta gamma</p><p>delta epsilon</p>"#
+        );
+        assert_eq!(forward[1].2, "beta gamma\n\ndelta epsilon");
+
+        let backward = overlap(OverlapDirection::Backward);
+        assert_eq!(
+            backward[1].1,
+            r#"<p>delta epsilon</p><p class="trieve-overlap-backward">zeta &amp;</p>"#
+        );
+        assert_eq!(backward[2], chunks[2]);
+
+        let bidirectional = overlap(OverlapDirection::Bidirectional);
+        assert_eq!(bidirectional[1].2, "beta gamma\n\ndelta epsilon\n\nzeta &");
+        assert_eq!(bidirectional[0].2, "alpha beta gamma\n\ndelta epsilon");
+        assert_eq!(bidirectional[2].2, "delta epsilon\n\nzeta & eta theta");
+        assert!(bidirectional
+            .iter()
+            .zip(&chunks)
+            .all(|(overlapped, chunk)| overlapped.0 == chunk.0));
+
+        let mut options = crawl_options("https://overlap.example.com");
+        options.chunk_overlap_strategy = Some(OverlapStrategy {
+            direction: OverlapDirection::Forward,
+            words: 0,
+        });
+        assert!(validate_crawl_options(&options).is_err());
+    }
+
+    #[test]
+    fn detect_page_language_returns_iso_639_1_codes() {
+        assert_eq!(
+            detect_page_language(
+                "The crawler indexes every page of the documentation site and splits each page into \
+                 chunks at its headings, so that search results link to the right section."
+            )
+            .as_deref(),
+            Some("en")
+        );
+        assert_eq!(
+            detect_page_language(
+                "Der Crawler indexiert jede Seite der Dokumentation und teilt jede Seite an ihren \
+                 Überschriften in Abschnitte auf, damit die Suchergebnisse auf den richtigen Abschnitt \
+                 verweisen."
+            )
+            .as_deref(),
+            Some("de")
+        );
+        assert_eq!(detect_page_language(""), None);
+        assert_eq!(
+            detect_language(
+                "The crawler indexes every page of the documentation site and splits each page into \
+                 chunks at its headings, so that search results link to the right section."
+            )
+            .as_deref(),
+            Some("en")
+        );
+    }
+
+    #[test]
+    fn error_rate_is_reported_above_notify_on_error_threshold() {
+        assert_eq!(error_rate_above_threshold(4, 10, Some(0.25)), Some(0.4));
+        assert_eq!(error_rate_above_threshold(2, 10, Some(0.25)), None);
+        assert_eq!(error_rate_above_threshold(4, 10, None), None);
+        assert_eq!(error_rate_above_threshold(0, 0, Some(0.0)), None);
+
+        let mut options = crawl_options("https://docs.example.com");
+        options.notify_on_error_threshold = Some(1.5);
+        assert!(validate_crawl_options(&options).is_err());
+    }
+
+    #[test]
+    fn every_page_metadata_field_is_known() {
+        let serde_json::Value::Object(metadata) =
+            serde_json::to_value(Metadata::default()).unwrap()
+        else {
+            panic!("page metadata is not serialized as an object");
+        };
+
+        for key in metadata.keys() {
+            assert!(
+                KNOWN_METADATA_FIELDS.contains(&key.as_str()),
+                "{} is missing from KNOWN_METADATA_FIELDS",
+                key
+            );
+        }
+    }
+}
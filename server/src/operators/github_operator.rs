@@ -0,0 +1,333 @@
+use super::crawl_operator::{Document, Metadata};
+use super::parse_operator::markdown_to_html;
+use crate::{data::models::CrawlGitHubOptions, errors::ServiceError};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Maximum number of times a request is retried after hitting the GitHub rate limit
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+/// Longest time to wait for the GitHub rate limit to reset before giving up
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60 * 5);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GitHubTreeEntry {
+    pub path: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub sha: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GitHubTree {
+    pub sha: String,
+    pub tree: Vec<GitHubTreeEntry>,
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+fn github_api_url() -> String {
+    std::env::var("GITHUB_API_URL").unwrap_or_else(|_| "https://api.github.com".to_string())
+}
+
+fn github_raw_url() -> String {
+    std::env::var("GITHUB_RAW_URL")
+        .unwrap_or_else(|_| "https://raw.githubusercontent.com".to_string())
+}
+
+/// Url of the page GitHub renders for a file of the repository
+pub fn github_blob_url(repo: &str, branch: &str, path: &str) -> String {
+    format!("https://github.com/{}/blob/{}/{}", repo, branch, path)
+}
+
+fn is_markdown_file(path: &str) -> bool {
+    let path = path.to_lowercase();
+    path.ends_with(".md") || path.ends_with(".markdown")
+}
+
+fn is_under_path_prefix(path: &str, path_prefix: Option<&str>) -> bool {
+    match path_prefix.map(|prefix| prefix.trim_matches('/')) {
+        None | Some("") => true,
+        Some(prefix) => {
+            path == prefix
+                || path
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        }
+    }
+}
+
+/// Paths of the markdown files in `entries` which are under `path_prefix`
+fn markdown_files_under_prefix(
+    entries: &[GitHubTreeEntry],
+    path_prefix: Option<&str>,
+) -> Vec<String> {
+    entries
+        .iter()
+        .filter(|entry| entry.type_ == "blob")
+        .filter(|entry| is_markdown_file(&entry.path))
+        .filter(|entry| is_under_path_prefix(&entry.path, path_prefix))
+        .map(|entry| entry.path.clone())
+        .collect()
+}
+
+/// How long to wait before retrying a rate limited response, `None` if the response was not rate
+/// limited.
+fn rate_limit_wait(response: &reqwest::Response) -> Option<Duration> {
+    let status = response.status();
+    if status != reqwest::StatusCode::FORBIDDEN && status != reqwest::StatusCode::TOO_MANY_REQUESTS
+    {
+        return None;
+    }
+
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+    };
+
+    if let Some(retry_after) = header("retry-after") {
+        return Some(Duration::from_secs(retry_after));
+    }
+
+    if header("x-ratelimit-remaining") == Some(0) {
+        let reset_at = header("x-ratelimit-reset").unwrap_or_default();
+        let now = chrono::Utc::now().timestamp() as u64;
+        return Some(Duration::from_secs(reset_at.saturating_sub(now) + 1));
+    }
+
+    None
+}
+
+/// Sends a GET request to GitHub, waiting for the rate limit to reset when it is hit
+async fn github_get(
+    client: &reqwest::Client,
+    url: &str,
+    token: Option<&str>,
+) -> Result<reqwest::Response, ServiceError> {
+    let mut attempt = 0;
+    loop {
+        let mut request = client
+            .get(url)
+            .header("User-Agent", "trieve-crawler")
+            .header("Accept", "application/vnd.github+json");
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+
+        let response = request.send().await.map_err(|e| {
+            log::error!("Error sending request to GitHub: {:?}", e);
+            ServiceError::InternalServerError("Error sending request to GitHub".to_string())
+        })?;
+
+        match rate_limit_wait(&response) {
+            Some(wait) if attempt < MAX_RATE_LIMIT_RETRIES && wait <= MAX_RATE_LIMIT_WAIT => {
+                log::warn!("GitHub rate limit hit, retrying {} in {:?}", url, wait);
+                tokio::time::sleep(wait).await;
+                attempt += 1;
+            }
+            Some(_) => {
+                return Err(ServiceError::ServiceUnavailable(
+                    "GitHub rate limit exceeded".to_string(),
+                ))
+            }
+            None if response.status().is_success() => return Ok(response),
+            None => {
+                log::error!(
+                    "Error getting response from GitHub {}: {:?}",
+                    url,
+                    response.text().await
+                );
+                return Err(ServiceError::BadRequest(format!(
+                    "Error getting {} from GitHub",
+                    url
+                )));
+            }
+        }
+    }
+}
+
+async fn get_github_tree(
+    client: &reqwest::Client,
+    repo: &str,
+    tree_sha: &str,
+    recursive: bool,
+    token: Option<&str>,
+) -> Result<GitHubTree, ServiceError> {
+    let mut url = format!("{}/repos/{}/git/trees/{}", github_api_url(), repo, tree_sha);
+    if recursive {
+        url.push_str("?recursive=1");
+    }
+
+    github_get(client, &url, token)
+        .await?
+        .json::<GitHubTree>()
+        .await
+        .map_err(|e| {
+            log::error!("Error parsing tree from GitHub: {:?}", e);
+            ServiceError::InternalServerError("Error parsing tree from GitHub".to_string())
+        })
+}
+
+/// Lists every file of the branch. GitHub truncates recursive tree listings of large
+/// repositories, in which case the tree is walked one directory at a time instead.
+async fn list_github_files(
+    client: &reqwest::Client,
+    options: &CrawlGitHubOptions,
+) -> Result<Vec<GitHubTreeEntry>, ServiceError> {
+    let token = options.token.as_deref();
+    let tree = get_github_tree(client, &options.repo, &options.branch, true, token).await?;
+    if !tree.truncated {
+        return Ok(tree.tree);
+    }
+
+    log::info!(
+        "Recursive tree of {} is truncated, listing directories one at a time",
+        options.repo
+    );
+
+    let path_prefix = options
+        .path_prefix
+        .as_deref()
+        .unwrap_or_default()
+        .trim_matches('/');
+    let mut files = vec![];
+    let mut directories = VecDeque::from([(String::new(), options.branch.clone())]);
+    while let Some((directory, tree_sha)) = directories.pop_front() {
+        let tree = get_github_tree(client, &options.repo, &tree_sha, false, token).await?;
+        for mut entry in tree.tree {
+            if !directory.is_empty() {
+                entry.path = format!("{}/{}", directory, entry.path);
+            }
+
+            // Skip directories which can't contain files under the path prefix
+            if entry.type_ == "tree" {
+                if is_under_path_prefix(&entry.path, Some(path_prefix))
+                    || path_prefix.starts_with(&format!("{}/", entry.path))
+                {
+                    directories.push_back((entry.path, entry.sha));
+                }
+            } else {
+                files.push(entry);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Converts a markdown file of the repository into a crawled [`Document`]
+fn convert_markdown_to_document(markdown: &str, repo: &str, branch: &str, path: &str) -> Document {
+    Document {
+        content_type: Some("text/markdown".to_string()),
+        markdown: Some(markdown.to_string()),
+        extract: None,
+        html: Some(markdown_to_html(markdown)),
+        raw_html: None,
+        links: None,
+        screenshot: None,
+        metadata: Metadata {
+            title: Some(path.to_string()),
+            og_title: Some(path.to_string()),
+            source_url: Some(github_blob_url(repo, branch, path)),
+            status_code: Some(200),
+            ..Default::default()
+        },
+    }
+}
+
+/// Crawls the markdown files of a GitHub repository. The files are listed with the Trees API and
+/// downloaded from their raw content url.
+pub async fn crawl_github_repo(
+    options: &CrawlGitHubOptions,
+) -> Result<Vec<Document>, ServiceError> {
+    let client = reqwest::Client::new();
+    let files = list_github_files(&client, options).await?;
+    let markdown_files = markdown_files_under_prefix(&files, options.path_prefix.as_deref());
+
+    log::info!(
+        "Found {} markdown files in {}",
+        markdown_files.len(),
+        options.repo
+    );
+
+    let mut documents = vec![];
+    for path in markdown_files {
+        let raw_url = format!(
+            "{}/{}/{}/{}",
+            github_raw_url(),
+            options.repo,
+            options.branch,
+            path
+        );
+        let markdown = match github_get(&client, &raw_url, options.token.as_deref()).await {
+            Ok(response) => response.text().await.map_err(|e| {
+                log::error!("Error reading {} from GitHub: {:?}", path, e);
+                ServiceError::InternalServerError("Error reading file from GitHub".to_string())
+            })?,
+            Err(ServiceError::BadRequest(_)) => {
+                log::error!("Skipping {} which could not be downloaded", path);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        documents.push(convert_markdown_to_document(
+            &markdown,
+            &options.repo,
+            &options.branch,
+            &path,
+        ));
+    }
+
+    Ok(documents)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn markdown_files_under_prefix_filters_github_tree() {
+        let entry = |path: &str, type_: &str| GitHubTreeEntry {
+            path: path.to_string(),
+            type_: type_.to_string(),
+            sha: uuid::Uuid::new_v4().to_string(),
+        };
+        let entries = vec![
+            entry("README.md", "blob"),
+            entry("docs", "tree"),
+            entry("docs/intro.md", "blob"),
+            entry("docs/guides/setup.MD", "blob"),
+            entry("docs/logo.png", "blob"),
+            entry("docs-old/intro.md", "blob"),
+        ];
+
+        assert_eq!(
+            markdown_files_under_prefix(&entries, Some("/docs/")),
+            vec!["docs/intro.md", "docs/guides/setup.MD"]
+        );
+        assert_eq!(markdown_files_under_prefix(&entries, None).len(), 4);
+    }
+
+    #[test]
+    fn convert_markdown_to_document_links_to_github_blob() {
+        let document = convert_markdown_to_document(
+            "# Setup\n\nRun `make`.",
+            "devflowinc/trieve",
+            "main",
+            "docs/setup.md",
+        );
+
+        assert_eq!(
+            document.metadata.source_url.as_deref(),
+            Some("https://github.com/devflowinc/trieve/blob/main/docs/setup.md")
+        );
+        assert_eq!(
+            document.html.as_deref(),
+            Some("<h1>Setup</h1>\n<p>Run <code>make</code>.</p>\n")
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use crate::errors::ServiceError;
+use futures::StreamExt;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+
+/// Longest time a request to a user supplied url may take
+const EXTERNAL_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Largest response body read from a user supplied url when `MAX_EXTERNAL_RESPONSE_BYTES` is not set
+const DEFAULT_MAX_EXTERNAL_RESPONSE_BYTES: u64 = 50 * 1024 * 1024;
+
+/// Whether `ip` is reachable on the public internet. Loopback, private, link local and other
+/// reserved ranges are refused so user supplied urls can't reach hosts inside the deployment.
+fn is_public_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                // Carrier grade NAT, 100.64.0.0/10
+                || (a == 100 && (64..128).contains(&b))
+                || a == 0)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_ip(&IpAddr::V4(ip));
+            }
+            let first_segment = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local, fc00::/7
+                || (first_segment & 0xfe00) == 0xfc00
+                // Link local, fe80::/10
+                || (first_segment & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/// Resolves the host of `url` and checks that every address it resolves to is public
+async fn resolve_public_addr(url: &reqwest::Url) -> Result<(String, SocketAddr), ServiceError> {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(ServiceError::BadRequest(format!(
+            "Url {} must use http or https",
+            url
+        )));
+    }
+
+    let host = url
+        .host_str()
+        .ok_or_else(|| ServiceError::BadRequest(format!("Url {} has no host", url)))?
+        .to_string();
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    let lookup_host = host.trim_matches(|c| c == '[' || c == ']').to_string();
+    let addrs = tokio::task::spawn_blocking(move || {
+        (lookup_host.as_str(), port)
+            .to_socket_addrs()
+            .map(|addrs| addrs.collect::<Vec<_>>())
+    })
+    .await
+    .map_err(|e| ServiceError::InternalServerError(e.to_string()))?
+    .map_err(|e| {
+        log::error!("Error resolving {}: {:?}", host, e);
+        ServiceError::BadRequest(format!("Could not resolve {}", host))
+    })?;
+
+    if addrs.is_empty() || addrs.iter().any(|addr| !is_public_ip(&addr.ip())) {
+        return Err(ServiceError::BadRequest(format!(
+            "Url {} does not resolve to a public address",
+            url
+        )));
+    }
+
+    Ok((host, addrs[0]))
+}
+
+/// Client for a single request to a user supplied url. The host is resolved up front and the
+/// client is pinned to the checked address, so the url can't be rebound to a private address
+/// between the check and the request. Redirects are not followed since they could point
+/// anywhere.
+pub async fn external_http_client(url: &str) -> Result<reqwest::Client, ServiceError> {
+    let parsed_url = reqwest::Url::parse(url)
+        .map_err(|_| ServiceError::BadRequest(format!("Invalid url {}", url)))?;
+    let (host, addr) = resolve_public_addr(&parsed_url).await?;
+
+    reqwest::Client::builder()
+        .timeout(EXTERNAL_REQUEST_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .resolve(&host, addr)
+        .build()
+        .map_err(|e| {
+            log::error!("Error building http client: {:?}", e);
+            ServiceError::InternalServerError("Error building http client".to_string())
+        })
+}
+
+/// Largest response body read from a user supplied url, configured by
+/// `MAX_EXTERNAL_RESPONSE_BYTES`
+pub fn max_external_response_bytes() -> u64 {
+    std::env::var("MAX_EXTERNAL_RESPONSE_BYTES")
+        .ok()
+        .and_then(|max_bytes| max_bytes.parse().ok())
+        .filter(|max_bytes| *max_bytes > 0)
+        .unwrap_or(DEFAULT_MAX_EXTERNAL_RESPONSE_BYTES)
+}
+
+/// Reads the body of a response from a user supplied url. Bodies larger than
+/// [`max_external_response_bytes`] are refused, up front when the response declares its length and
+/// otherwise as soon as the streamed body passes the limit, so an endless body can't exhaust memory.
+pub async fn read_external_response_body(
+    response: reqwest::Response,
+    url: &str,
+) -> Result<Vec<u8>, ServiceError> {
+    let max_bytes = max_external_response_bytes();
+    let too_large = || {
+        ServiceError::BadRequest(format!(
+            "Response of {} is larger than {} bytes",
+            url, max_bytes
+        ))
+    };
+
+    if response
+        .content_length()
+        .is_some_and(|content_length| content_length > max_bytes)
+    {
+        return Err(too_large());
+    }
+
+    let mut body = Vec::new();
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| {
+            log::error!("Error reading response of {}: {:?}", url, e);
+            ServiceError::BadRequest(format!("Error reading response of {}", url))
+        })?;
+        if body.len() as u64 + chunk.len() as u64 > max_bytes {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(body)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_public_addresses_are_allowed() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public_ip(&ip.parse().unwrap()), "{} is not public", ip);
+        }
+
+        for ip in ["93.184.216.34", "1.1.1.1", "2606:4700::1111"] {
+            assert!(is_public_ip(&ip.parse().unwrap()), "{} is public", ip);
+        }
+    }
+}
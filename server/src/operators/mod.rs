@@ -1,18 +1,22 @@
 pub mod analytics_operator;
 pub mod chunk_operator;
 pub mod clickhouse_operator;
+pub mod completion_operator;
 pub mod crawl_operator;
 pub mod dataset_operator;
 pub mod dittofeed_operator;
 pub mod email_operator;
 pub mod event_operator;
 pub mod file_operator;
+pub mod github_operator;
 pub mod group_operator;
+pub mod http_operator;
 pub mod invitation_operator;
 pub mod message_operator;
 pub mod model_operator;
 pub mod organization_operator;
 pub mod parse_operator;
+pub mod pdf_operator;
 pub mod qdrant_operator;
 pub mod search_operator;
 pub mod stripe_operator;
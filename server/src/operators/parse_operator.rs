@@ -1,10 +1,18 @@
 use ndarray::Array2;
+use once_cell::sync::Lazy;
+use pulldown_cmark::{Options, Parser};
 use regex::Regex;
 use regex_split::RegexSplit;
 use scraper::{Html, Selector};
 use std::cmp;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
 
-use crate::errors::ServiceError;
+use crate::{
+    data::models::{CrawlOptions, SimilarityMethod, SmartDedupStrategy},
+    errors::ServiceError,
+};
 
 #[tracing::instrument]
 pub fn convert_html_to_text(html: &str) -> String {
@@ -26,6 +34,481 @@ pub fn extract_text_from_html(html: &str) -> String {
         .join(" ")
 }
 
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Renders markdown as html, with tables, strikethrough, task lists and footnotes enabled
+pub fn markdown_to_html(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES;
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, Parser::new_ext(markdown, options));
+    html
+}
+
+static MULTIPLE_SPACES_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"[^\S\n]+").unwrap());
+static MULTIPLE_NEWLINES_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\n{3,}").unwrap());
+
+/// Collapses runs of spaces to one, trims every line and collapses three or more newlines to two.
+pub fn normalize_whitespace_in_text(text: &str) -> String {
+    let text = text.replace("\r\n", "\n");
+    let lines = text
+        .split('\n')
+        .map(|line| MULTIPLE_SPACES_RE.replace_all(line.trim(), " "))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    MULTIPLE_NEWLINES_RE
+        .replace_all(&lines, "\n\n")
+        .trim()
+        .to_string()
+}
+
+/// Tags removed along with their content by [`sanitize_html`], even when they are allowed
+const SANITIZE_REMOVED_CONTENT_TAGS: &[&str] = &[
+    "script", "style", "iframe", "object", "embed", "svg", "canvas", "template", "title",
+];
+
+/// Tags kept by [`sanitize_html`] on top of ammonia's default allowlist, since the chunking steps
+/// rely on them
+const SANITIZE_EXTRA_ALLOWED_TAGS: &[&str] = &[
+    "section",
+    "main",
+    "math",
+    "semantics",
+    "annotation",
+    "annotation-xml",
+    "mi",
+    "mn",
+    "mo",
+    "ms",
+    "mtext",
+    "mspace",
+    "mrow",
+    "mfrac",
+    "msqrt",
+    "mroot",
+    "msub",
+    "msup",
+    "msubsup",
+    "mover",
+    "munder",
+    "munderover",
+    "mtable",
+    "mtr",
+    "mlabeledtr",
+    "mtd",
+    "mfenced",
+    "mphantom",
+    "mstyle",
+];
+
+/// Attributes kept on every tag by [`sanitize_html`] when no allowlist is configured
+pub const DEFAULT_SANITIZE_ALLOWED_ATTRIBUTES: &[&str] = &[
+    "id",
+    "class",
+    "lang",
+    "title",
+    "encoding",
+    "open",
+    "close",
+    "separators",
+];
+
+/// Allowlist of the tags and attributes kept by [`sanitize_html`]
+#[derive(Debug, Clone, Default)]
+pub struct SanitizeConfig {
+    /// Tags to keep, defaults to ammonia's allowlist plus `section`, `main` and the MathML tags
+    pub allowed_tags: Option<Vec<String>>,
+    /// Attributes to keep on every tag, defaults to [`DEFAULT_SANITIZE_ALLOWED_ATTRIBUTES`]
+    pub allowed_attributes: Option<Vec<String>>,
+    /// Keep `<noscript>` elements instead of removing them with their content
+    pub keep_noscript: bool,
+}
+
+impl From<&CrawlOptions> for SanitizeConfig {
+    fn from(crawl_options: &CrawlOptions) -> Self {
+        Self {
+            allowed_tags: crawl_options.sanitize_allowed_tags.clone(),
+            allowed_attributes: crawl_options.sanitize_allowed_attributes.clone(),
+            keep_noscript: crawl_options.include_noscript_content.unwrap_or(false),
+        }
+    }
+}
+
+/// Removes the tags and attributes which are not in the allowlist of `config`, keeping the text
+/// of removed tags. `<script>`, `<style>`, `<iframe>`, `<object>` and similar elements are
+/// removed along with their content so it does not end up in chunk text.
+pub fn sanitize_html(html: &str, config: &SanitizeConfig) -> String {
+    let mut builder = ammonia::Builder::default();
+
+    let mut tags: HashSet<&str> = match config.allowed_tags {
+        Some(ref allowed_tags) => allowed_tags.iter().map(String::as_str).collect(),
+        None => {
+            let mut tags = builder.clone_tags();
+            tags.extend(SANITIZE_EXTRA_ALLOWED_TAGS);
+            tags
+        }
+    };
+    let mut removed_content_tags: HashSet<&str> =
+        SANITIZE_REMOVED_CONTENT_TAGS.iter().copied().collect();
+    if config.keep_noscript {
+        tags.insert("noscript");
+    } else {
+        removed_content_tags.insert("noscript");
+    }
+    // ammonia panics if a tag is both allowed and removed with its content
+    tags.retain(|tag| !removed_content_tags.contains(tag));
+
+    let attributes: HashSet<&str> = match config.allowed_attributes {
+        Some(ref allowed_attributes) => allowed_attributes.iter().map(String::as_str).collect(),
+        None => DEFAULT_SANITIZE_ALLOWED_ATTRIBUTES
+            .iter()
+            .copied()
+            .collect(),
+    };
+
+    builder
+        .tags(tags)
+        .clean_content_tags(removed_content_tags)
+        .generic_attributes(attributes)
+        .link_rel(None)
+        .clean(html)
+        .to_string()
+}
+
+/// Pages with fewer words than this which show a subscription call to action are treated as paywalled
+const PAYWALL_MAX_TEASER_WORDS: usize = 250;
+
+const PAYWALL_CALLS_TO_ACTION: &[&str] = &[
+    "subscribe to continue",
+    "subscribe to read",
+    "subscribe to keep reading",
+    "subscribe now to",
+    "already a subscriber",
+    "become a member to",
+    "sign in to continue reading",
+    "log in to continue reading",
+    "this article is for subscribers",
+    "this content is for subscribers",
+    "for members only",
+];
+
+static PAYWALL_OVERLAY_SELECTOR: Lazy<Selector> = Lazy::new(|| {
+    Selector::parse(
+        "[class*=paywall], [id*=paywall], [class*=regwall], [id*=regwall], [class*=subscription-wall], [class*=meteredContent], [data-testid*=paywall]",
+    )
+    .unwrap()
+});
+
+static CONTENT_TIER_SELECTOR: Lazy<Selector> = Lazy::new(|| {
+    Selector::parse("meta[name=\"article:content_tier\"], meta[property=\"article:content_tier\"]")
+        .unwrap()
+});
+
+static IS_ACCESSIBLE_FOR_FREE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)"isAccessibleForFree"\s*:\s*"?false"?"#).unwrap());
+
+/// Heuristically detects pages whose content is hidden behind a paywall. A page is considered
+/// paywalled when its `article:content_tier` meta tag is anything but free, its structured data
+/// marks it as not accessible for free, it contains a paywall overlay, or it has very little body
+/// text next to a subscription call to action.
+pub fn detect_paywall(html: &str) -> bool {
+    let document = Html::parse_document(html);
+
+    let has_restricted_content_tier = document.select(&CONTENT_TIER_SELECTOR).any(|element| {
+        element
+            .value()
+            .attr("content")
+            .map(|tier| !tier.trim().eq_ignore_ascii_case("free"))
+            .unwrap_or(false)
+    });
+    if has_restricted_content_tier || IS_ACCESSIBLE_FOR_FREE_RE.is_match(html) {
+        return true;
+    }
+
+    if document.select(&PAYWALL_OVERLAY_SELECTOR).next().is_some() {
+        return true;
+    }
+
+    let body_text = extract_text_from_html(html).to_lowercase();
+    body_text.split_whitespace().count() < PAYWALL_MAX_TEASER_WORDS
+        && PAYWALL_CALLS_TO_ACTION
+            .iter()
+            .any(|call_to_action| body_text.contains(call_to_action))
+}
+
+/// Maximum number of chunks remembered by the near-duplicate detectors of a crawl, the oldest
+/// chunks are forgotten past it
+pub const MAX_DEDUP_INDEX_ENTRIES: usize = 100_000;
+
+const MINHASH_PERMUTATIONS: u64 = 64;
+const MINHASH_SHINGLE_WORDS: usize = 3;
+
+fn hash_feature(seed: u64, feature: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    feature.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn dedup_words(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+/// Signatures of the chunks seen by a near-duplicate detector, bucketed by bands of the
+/// signature so that a new chunk is only compared to the chunks sharing one of its bands
+struct LshIndex<S> {
+    /// Signature, bands and key of each remembered chunk, oldest first
+    entries: VecDeque<(S, Vec<u64>, usize)>,
+    /// Position of the oldest remembered chunk among all chunks ever remembered
+    first_entry: usize,
+    /// Positions of the chunks by band number and band value
+    buckets: HashMap<(usize, u64), VecDeque<usize>>,
+}
+
+impl<S> LshIndex<S> {
+    fn new() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            first_entry: 0,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Key of the first remembered chunk sharing a band with `bands` whose signature matches
+    fn find(&self, bands: &[u64], is_match: impl Fn(&S) -> bool) -> Option<usize> {
+        bands
+            .iter()
+            .enumerate()
+            .filter_map(|(band, value)| self.buckets.get(&(band, *value)))
+            .flatten()
+            .map(|position| &self.entries[position - self.first_entry])
+            .find(|(signature, _, _)| is_match(signature))
+            .map(|(_, _, key)| *key)
+    }
+
+    fn insert(&mut self, signature: S, bands: Vec<u64>, key: usize) {
+        if self.entries.len() >= MAX_DEDUP_INDEX_ENTRIES {
+            if let Some((_, evicted_bands, _)) = self.entries.pop_front() {
+                for (band, value) in evicted_bands.into_iter().enumerate() {
+                    if let Some(bucket) = self.buckets.get_mut(&(band, value)) {
+                        // Buckets are in insertion order, so the oldest chunk is first
+                        bucket.pop_front();
+                        if bucket.is_empty() {
+                            self.buckets.remove(&(band, value));
+                        }
+                    }
+                }
+                self.first_entry += 1;
+            }
+        }
+
+        let position = self.first_entry + self.entries.len();
+        for (band, value) in bands.iter().enumerate() {
+            self.buckets
+                .entry((band, *value))
+                .or_default()
+                .push_back(position);
+        }
+        self.entries.push_back((signature, bands, key));
+    }
+}
+
+/// 64 bit SimHash of the words of the text
+pub fn simhash(text: &str) -> u64 {
+    let mut weights = [0i64; 64];
+    for word in dedup_words(text) {
+        let hash = hash_feature(0, &word);
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if hash & (1 << bit) != 0 {
+                *weight += 1;
+            } else {
+                *weight -= 1;
+            }
+        }
+    }
+
+    weights
+        .iter()
+        .enumerate()
+        .filter(|(_, weight)| **weight > 0)
+        .fold(0, |hash, (bit, _)| hash | (1 << bit))
+}
+
+/// Detects chunks whose SimHash differs from the SimHash of a chunk seen before by at most
+/// `(1 - similarity_threshold) * 64` bits. The hashes are split into `max_distance + 1` bands of
+/// bits: two hashes within `max_distance` bits of each other have at least one identical band,
+/// so only the chunks sharing a band are compared.
+pub struct SimHashDeduplicator {
+    max_distance: u32,
+    band_count: u32,
+    index: LshIndex<u64>,
+}
+
+impl SimHashDeduplicator {
+    pub fn new(similarity_threshold: f32) -> Self {
+        let max_distance = ((1.0 - similarity_threshold.clamp(0.0, 1.0)) * 64.0).floor() as u32;
+        Self {
+            max_distance,
+            band_count: (max_distance + 1).min(64),
+            index: LshIndex::new(),
+        }
+    }
+
+    fn bands(&self, hash: u64) -> Vec<u64> {
+        (0..self.band_count)
+            .map(|band| {
+                let start = band * 64 / self.band_count;
+                let end = (band + 1) * 64 / self.band_count;
+                let mask = if end - start == 64 {
+                    u64::MAX
+                } else {
+                    (1 << (end - start)) - 1
+                };
+                (hash >> start) & mask
+            })
+            .collect()
+    }
+
+    /// Key of the text seen before which the text is a near-duplicate of. Texts which are not are
+    /// remembered under `key`.
+    pub fn find_duplicate(&mut self, text: &str, key: usize) -> Option<usize> {
+        if text.split_whitespace().next().is_none() {
+            return None;
+        }
+
+        let hash = simhash(text);
+        let bands = self.bands(hash);
+        let duplicate_of = self.index.find(&bands, |seen| {
+            (seen ^ hash).count_ones() <= self.max_distance
+        });
+        if duplicate_of.is_none() {
+            self.index.insert(hash, bands, key);
+        }
+        duplicate_of
+    }
+}
+
+/// MinHash signature of the 3 word shingles of the text
+pub fn minhash(text: &str) -> Vec<u64> {
+    let words = dedup_words(text);
+    let shingles: Vec<String> = words
+        .windows(MINHASH_SHINGLE_WORDS.min(words.len()).max(1))
+        .map(|shingle| shingle.join(" "))
+        .collect();
+
+    (0..MINHASH_PERMUTATIONS)
+        .map(|seed| {
+            shingles
+                .iter()
+                .map(|shingle| hash_feature(seed, shingle))
+                .min()
+                .unwrap_or(u64::MAX)
+        })
+        .collect()
+}
+
+/// Detects chunks whose estimated Jaccard similarity to a chunk seen before, from their MinHash
+/// signatures, is at least `similarity_threshold`. The signatures are split into bands of rows
+/// sized so that chunks around the threshold very likely share a band, and only the chunks
+/// sharing a band are compared.
+pub struct MinHashDeduplicator {
+    similarity_threshold: f32,
+    rows_per_band: usize,
+    index: LshIndex<Vec<u64>>,
+}
+
+impl MinHashDeduplicator {
+    pub fn new(similarity_threshold: f32) -> Self {
+        // Two signatures with similarity s share a band of r rows out of b bands with probability
+        // 1 - (1 - s^r)^b, which rises steeply around (1 / b)^(1 / r). Take the longest bands
+        // whose rise is comfortably below the threshold, so near-duplicates are rarely missed.
+        let rows_per_band = [64, 32, 16, 8, 4, 2, 1]
+            .into_iter()
+            .find(|rows| {
+                let bands = MINHASH_PERMUTATIONS as f32 / *rows as f32;
+                (1.0 / bands).powf(1.0 / *rows as f32) <= similarity_threshold * 0.9
+            })
+            .unwrap_or(1);
+        Self {
+            similarity_threshold,
+            rows_per_band,
+            index: LshIndex::new(),
+        }
+    }
+
+    fn bands(&self, signature: &[u64]) -> Vec<u64> {
+        signature
+            .chunks(self.rows_per_band)
+            .map(|rows| {
+                let mut hasher = DefaultHasher::new();
+                rows.hash(&mut hasher);
+                hasher.finish()
+            })
+            .collect()
+    }
+
+    /// Key of the text seen before which the text is a near-duplicate of. Texts which are not are
+    /// remembered under `key`.
+    pub fn find_duplicate(&mut self, text: &str, key: usize) -> Option<usize> {
+        if text.split_whitespace().next().is_none() {
+            return None;
+        }
+
+        let signature = minhash(text);
+        let bands = self.bands(&signature);
+        let duplicate_of = self.index.find(&bands, |seen| {
+            let matching = seen
+                .iter()
+                .zip(signature.iter())
+                .filter(|(a, b)| a == b)
+                .count();
+            matching as f32 / MINHASH_PERMUTATIONS as f32 >= self.similarity_threshold
+        });
+        if duplicate_of.is_none() {
+            self.index.insert(signature, bands, key);
+        }
+        duplicate_of
+    }
+}
+
+/// Near-duplicate detector for the chunks of a crawl, see `CrawlOptions::smart_dedup_strategy`
+pub enum ChunkDeduplicator {
+    SimHash(SimHashDeduplicator),
+    MinHash(MinHashDeduplicator),
+}
+
+impl From<&SmartDedupStrategy> for ChunkDeduplicator {
+    fn from(strategy: &SmartDedupStrategy) -> Self {
+        match strategy.method {
+            SimilarityMethod::SimHash => {
+                ChunkDeduplicator::SimHash(SimHashDeduplicator::new(strategy.similarity_threshold))
+            }
+            SimilarityMethod::MinHash => {
+                ChunkDeduplicator::MinHash(MinHashDeduplicator::new(strategy.similarity_threshold))
+            }
+        }
+    }
+}
+
+impl ChunkDeduplicator {
+    pub fn find_duplicate(&mut self, text: &str, key: usize) -> Option<usize> {
+        match self {
+            ChunkDeduplicator::SimHash(deduplicator) => deduplicator.find_duplicate(text, key),
+            ChunkDeduplicator::MinHash(deduplicator) => deduplicator.find_duplicate(text, key),
+        }
+    }
+}
+
 #[tracing::instrument]
 pub fn coarse_remove_large_chunks(cur_chunks: Vec<String>) -> Vec<String> {
     let max_chunk_len = 10000;
@@ -174,4 +657,100 @@ mod test {
         let result = average_embeddings(embeddings).unwrap();
         assert!(result == vec![2.0, 2.5, 1.0]);
     }
+
+    #[test]
+    pub fn test_normalize_whitespace_in_text() {
+        let text = "  Title  \n\n\n\n  first   line\t\tof text \r\n second line\n\n\nEnd  ";
+
+        assert_eq!(
+            normalize_whitespace_in_text(text),
+            "Title\n\nfirst line of text\nsecond line\n\nEnd"
+        );
+    }
+
+    #[test]
+    pub fn test_detect_paywall() {
+        let metered = r#"<html><head><meta name="article:content_tier" content="metered"></head><body><p>Story</p></body></html>"#;
+        assert!(detect_paywall(metered));
+
+        let overlay = r#"<html><body><article>Story</article><div class="tp-modal paywall-overlay"></div></body></html>"#;
+        assert!(detect_paywall(overlay));
+
+        let teaser = r#"<html><body><p>The first paragraph.</p><button>Subscribe to continue reading</button></body></html>"#;
+        assert!(detect_paywall(teaser));
+
+        let free = r#"<html><head><meta name="article:content_tier" content="free"></head><body><p>Subscribe to read more of our free newsletters.</p>"#
+            .to_string()
+            + &"word ".repeat(300)
+            + "</body></html>";
+        assert!(!detect_paywall(&free));
+    }
+
+    #[test]
+    pub fn test_sanitize_html() {
+        let html = r#"<section id="intro" onclick="track()"><h2>Intro</h2><script>var a = 1;</script><iframe src="https://ads.example.com">Ad</iframe><p>Read the <a href="/docs" target="_blank">docs</a>.</p><noscript>Enable JavaScript</noscript><custom-widget>Widget text</custom-widget></section>"#;
+
+        let sanitized = sanitize_html(html, &SanitizeConfig::default());
+        assert!(sanitized.starts_with(r#"<section id="intro"><h2>Intro</h2>"#));
+        assert!(sanitized.contains(r#"<a href="/docs">docs</a>"#));
+        assert!(sanitized.contains("Widget text"));
+        for removed in [
+            "onclick",
+            "var a",
+            "<iframe",
+            "Ad",
+            "Enable JavaScript",
+            "custom-widget",
+        ] {
+            assert!(!sanitized.contains(removed), "{} was not removed", removed);
+        }
+
+        let config = SanitizeConfig {
+            allowed_tags: Some(vec!["p".to_string(), "script".to_string()]),
+            allowed_attributes: Some(vec![]),
+            keep_noscript: false,
+        };
+        assert_eq!(
+            sanitize_html(html, &config),
+            "Intro<p>Read the docs.</p>Widget text"
+        );
+    }
+
+    #[test]
+    pub fn test_chunk_deduplicator() {
+        let article = "The quick brown fox jumps over the lazy dog while the farmer watches from the porch and drinks his morning coffee before starting work in the fields";
+        let edited_article = "The quick brown fox jumps over the lazy dog while the farmer watches from the porch and drinks his morning tea before starting work in the fields";
+        let other_article = "Rust is a systems programming language focused on safety, speed and concurrency, with a rich type system and an ownership model";
+
+        for method in [SimilarityMethod::SimHash, SimilarityMethod::MinHash] {
+            let mut deduplicator = ChunkDeduplicator::from(&SmartDedupStrategy {
+                similarity_threshold: 0.8,
+                method,
+            });
+            assert_eq!(deduplicator.find_duplicate(article, 0), None);
+            assert_eq!(deduplicator.find_duplicate(other_article, 1), None);
+            assert_eq!(deduplicator.find_duplicate(article, 2), Some(0));
+            assert_eq!(deduplicator.find_duplicate(other_article, 3), Some(1));
+            assert_eq!(deduplicator.find_duplicate("", 4), None);
+        }
+
+        let mut deduplicator = SimHashDeduplicator::new(0.9);
+        assert_eq!(deduplicator.find_duplicate(article, 0), None);
+        assert_eq!(deduplicator.find_duplicate(edited_article, 1), Some(0));
+    }
+
+    #[test]
+    pub fn test_dedup_index_forgets_oldest_chunks() {
+        let mut index = LshIndex::new();
+        for key in 0..MAX_DEDUP_INDEX_ENTRIES + 1 {
+            index.insert(key as u64, vec![key as u64, 0], key);
+        }
+
+        assert_eq!(index.find(&[0, 0], |_| true), Some(1));
+        assert_eq!(index.find(&[0, 2], |seen| *seen == 0), None);
+        assert_eq!(
+            index.find(&[MAX_DEDUP_INDEX_ENTRIES as u64, 2], |_| true),
+            Some(MAX_DEDUP_INDEX_ENTRIES)
+        );
+    }
 }
@@ -0,0 +1,350 @@
+use super::crawl_operator::{Document, Metadata};
+use super::http_operator::{external_http_client, read_external_response_body};
+use super::parse_operator::escape_html;
+use crate::errors::ServiceError;
+use once_cell::sync::Lazy;
+use pdfium_render::prelude::*;
+use std::ffi::{c_ulong, c_void};
+
+/// Default maximum number of words merged into a single chunk from consecutive pdf pages,
+/// overridden with `PDF_MAX_CHUNK_WORDS`
+const DEFAULT_MAX_PDF_CHUNK_WORDS: usize = 500;
+
+fn max_pdf_chunk_words() -> usize {
+    std::env::var("PDF_MAX_CHUNK_WORDS")
+        .ok()
+        .and_then(|words| words.parse().ok())
+        .filter(|words| *words > 0)
+        .unwrap_or(DEFAULT_MAX_PDF_CHUNK_WORDS)
+}
+
+fn is_pdf_resource(url: &str, content_type: Option<&str>) -> bool {
+    let is_pdf_content_type = content_type
+        .map(|content_type| {
+            content_type
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .eq_ignore_ascii_case("application/pdf")
+        })
+        .unwrap_or(false);
+
+    let is_pdf_extension = reqwest::Url::parse(url)
+        .map(|url| url.path().to_lowercase().ends_with(".pdf"))
+        .unwrap_or(false);
+
+    is_pdf_content_type || is_pdf_extension
+}
+
+/// Merges the text of consecutive pages into chunks of at most `max_chunk_words` words, a page
+/// longer than the limit becomes a chunk of its own. Returns the chunks as `(first_page, last_page, text)`
+/// with 1-based page numbers.
+fn merge_pdf_pages(pages: &[String], max_chunk_words: usize) -> Vec<(usize, usize, String)> {
+    let mut chunks: Vec<(usize, usize, String)> = vec![];
+    let mut current_words = 0;
+
+    for (index, page_text) in pages.iter().enumerate() {
+        let page_text = page_text.trim();
+        let page_words = page_text.split_whitespace().count();
+        if page_words == 0 {
+            continue;
+        }
+
+        match chunks.last_mut() {
+            Some((_, last_page, text)) if current_words + page_words <= max_chunk_words => {
+                *last_page = index + 1;
+                text.push_str("\n\n");
+                text.push_str(page_text);
+                current_words += page_words;
+            }
+            _ => {
+                chunks.push((index + 1, index + 1, page_text.to_string()));
+                current_words = page_words;
+            }
+        }
+    }
+
+    chunks
+}
+
+/// Pdfium, bound once per process. Pdf support is opt in, `PDFIUM_LIBRARY_PATH` has to point to
+/// the directory containing the pdfium library.
+static PDFIUM: Lazy<Result<Pdfium, String>> = Lazy::new(|| {
+    let library_path = std::env::var("PDFIUM_LIBRARY_PATH").map_err(|_| {
+        "Pdf support is disabled, set PDFIUM_LIBRARY_PATH to the directory containing the pdfium library to enable it".to_string()
+    })?;
+
+    let bindings =
+        Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path(&library_path))
+            .map_err(|e| format!("Error loading pdfium from {}: {:?}", library_path, e))?;
+
+    Ok(Pdfium::new(bindings))
+});
+
+/// Binds pdfium if it is not bound yet, failing when pdf support is disabled or the library
+/// can't be loaded
+pub fn pdfium() -> Result<&'static Pdfium, ServiceError> {
+    PDFIUM
+        .as_ref()
+        .map_err(|e| ServiceError::InternalServerError(e.clone()))
+}
+
+/// Formats the description of an image found in a pdf for the chunk text
+fn format_pdf_image_description(description: &str) -> String {
+    format!(
+        "[Image: {}]",
+        description.split_whitespace().collect::<Vec<_>>().join(" ")
+    )
+}
+
+/// Reads a string from a pdfium function which writes UTF-16LE into a buffer and returns the
+/// number of bytes needed, including the trailing NUL
+fn read_pdfium_string(read: impl Fn(*mut c_void, c_ulong) -> c_ulong) -> Option<String> {
+    let length = read(std::ptr::null_mut(), 0);
+    if length <= 2 {
+        return None;
+    }
+
+    let mut buffer = vec![0u8; length as usize];
+    read(buffer.as_mut_ptr() as *mut c_void, length);
+    let utf16 = buffer
+        .chunks_exact(2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .take_while(|code_unit| *code_unit != 0)
+        .collect::<Vec<u16>>();
+
+    Some(String::from_utf16_lossy(&utf16).trim().to_string()).filter(|text| !text.is_empty())
+}
+
+/// Descriptions of the images of each page of the pdf, read from the alt text of the `Figure`
+/// elements and the text of the `Caption` elements of the page's structure tree. Untagged pdfs
+/// have no structure tree and give no descriptions. The structure tree has no high level api, so
+/// it is read through the raw handles of the already loaded pages.
+fn extract_pdf_image_descriptions(pdfium: &Pdfium, document: &PdfDocument) -> Vec<Vec<String>> {
+    let bindings = pdfium.bindings();
+
+    let mut descriptions = vec![];
+    for page in document.pages().iter() {
+        let mut page_descriptions = vec![];
+        let struct_tree = bindings.FPDF_StructTree_GetForPage(bindings.get_handle_from_page(&page));
+        if !struct_tree.is_null() {
+            let mut elements = (0..bindings.FPDF_StructTree_CountChildren(struct_tree))
+                .map(|index| bindings.FPDF_StructTree_GetChildAtIndex(struct_tree, index))
+                .collect::<Vec<_>>();
+
+            while let Some(element) = elements.pop() {
+                if element.is_null() {
+                    continue;
+                }
+
+                let alt_text = || {
+                    read_pdfium_string(|buffer, length| {
+                        bindings.FPDF_StructElement_GetAltText(element, buffer, length)
+                    })
+                };
+                let actual_text = || {
+                    read_pdfium_string(|buffer, length| {
+                        bindings.FPDF_StructElement_GetActualText(element, buffer, length)
+                    })
+                };
+
+                let element_type = read_pdfium_string(|buffer, length| {
+                    bindings.FPDF_StructElement_GetType(element, buffer, length)
+                });
+                let description = match element_type.as_deref() {
+                    Some("Figure") => alt_text().or_else(actual_text),
+                    Some("Caption") => actual_text().or_else(alt_text),
+                    _ => None,
+                };
+                if let Some(description) = description {
+                    page_descriptions.push(description);
+                }
+
+                // Children are pushed in reverse so the elements are visited in reading order
+                for index in (0..bindings.FPDF_StructElement_CountChildren(element)).rev() {
+                    elements.push(bindings.FPDF_StructElement_GetChildAtIndex(element, index));
+                }
+            }
+
+            bindings.FPDF_StructTree_Close(struct_tree);
+        }
+
+        descriptions.push(page_descriptions);
+    }
+
+    descriptions
+}
+
+/// Converts a pdf into a crawled [`Document`]. Consecutive pages are merged into sections of at
+/// most `PDF_MAX_CHUNK_WORDS` words, each starting with a heading so the sections are kept
+/// apart by `chunk_html`. When `include_images` is set, the descriptions of the images of each
+/// page are added to its text as `[Image: description]`.
+pub fn convert_pdf_to_document(
+    pdf_bytes: &[u8],
+    source_url: &str,
+    include_images: bool,
+) -> Result<Document, ServiceError> {
+    let pdfium = pdfium()?;
+    let document = pdfium
+        .load_pdf_from_byte_slice(pdf_bytes, None)
+        .map_err(|e| {
+            log::error!("Error loading pdf {}: {:?}", source_url, e);
+            ServiceError::BadRequest("Error loading pdf".to_string())
+        })?;
+
+    let title = document
+        .metadata()
+        .get(PdfDocumentMetadataTagType::Title)
+        .map(|tag| tag.value().trim().to_string())
+        .filter(|title| !title.is_empty());
+
+    let mut pages = document
+        .pages()
+        .iter()
+        .map(|page| {
+            page.text().map(|text| text.all()).map_err(|e| {
+                log::error!("Error extracting text from pdf {}: {:?}", source_url, e);
+                ServiceError::BadRequest("Error extracting text from pdf".to_string())
+            })
+        })
+        .collect::<Result<Vec<String>, ServiceError>>()?;
+
+    let pdf_image_counts = if include_images {
+        let image_descriptions = extract_pdf_image_descriptions(pdfium, &document);
+        for (page_text, page_descriptions) in pages.iter_mut().zip(image_descriptions.iter()) {
+            for description in page_descriptions {
+                page_text.push_str("\n\n");
+                page_text.push_str(&format_pdf_image_description(description));
+            }
+        }
+
+        Some(
+            image_descriptions
+                .iter()
+                .map(|page_descriptions| page_descriptions.len())
+                .collect(),
+        )
+    } else {
+        None
+    };
+
+    let section_title = title.clone().unwrap_or(source_url.to_string());
+    let html = merge_pdf_pages(&pages, max_pdf_chunk_words())
+        .into_iter()
+        .map(|(first_page, last_page, text)| {
+            let page_range = if first_page == last_page {
+                format!("page {}", first_page)
+            } else {
+                format!("pages {}-{}", first_page, last_page)
+            };
+            let paragraphs = text
+                .split("\n\n")
+                .filter(|paragraph| !paragraph.trim().is_empty())
+                .map(|paragraph| format!("<p>{}</p>", escape_html(paragraph.trim())))
+                .collect::<String>();
+
+            format!(
+                "<h2>{} ({})</h2>{}",
+                escape_html(&section_title),
+                page_range,
+                paragraphs
+            )
+        })
+        .collect::<String>();
+
+    Ok(Document {
+        content_type: Some("application/pdf".to_string()),
+        markdown: Some(pages.join("\n\n")),
+        extract: None,
+        html: Some(html),
+        raw_html: None,
+        links: None,
+        screenshot: None,
+        metadata: Metadata {
+            og_title: title.clone(),
+            title,
+            source_url: Some(source_url.to_string()),
+            status_code: Some(200),
+            pdf_image_counts,
+            ..Default::default()
+        },
+    })
+}
+
+/// Downloads the pdf at `url` and converts it with [`convert_pdf_to_document`]
+pub async fn fetch_pdf_document(url: &str, include_images: bool) -> Result<Document, ServiceError> {
+    let response = external_http_client(url)
+        .await?
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| {
+            log::error!("Error downloading pdf {}: {:?}", url, e);
+            ServiceError::BadRequest("Error downloading pdf".to_string())
+        })?;
+
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .map(|content_type| content_type.to_string());
+
+    if !is_pdf_resource(url, content_type.as_deref()) {
+        return Err(ServiceError::BadRequest(format!("{} is not a pdf", url)));
+    }
+
+    let pdf_bytes = read_external_response_body(response, url).await?;
+
+    let url = url.to_string();
+    tokio::task::spawn_blocking(move || convert_pdf_to_document(&pdf_bytes, &url, include_images))
+        .await
+        .map_err(|e| ServiceError::InternalServerError(e.to_string()))?
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merge_pdf_pages_respects_max_chunk_words() {
+        let pages = vec![
+            "one two three".to_string(),
+            "four five".to_string(),
+            "   ".to_string(),
+            "six seven eight nine".to_string(),
+        ];
+
+        assert_eq!(
+            merge_pdf_pages(&pages, 5),
+            vec![
+                (1, 2, "one two three\n\nfour five".to_string()),
+                (4, 4, "six seven eight nine".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn is_pdf_resource_checks_extension_and_content_type() {
+        assert!(is_pdf_resource(
+            "https://example.com/guide.PDF?download=1",
+            None
+        ));
+        assert!(is_pdf_resource(
+            "https://example.com/download",
+            Some("application/pdf; charset=binary")
+        ));
+        assert!(!is_pdf_resource(
+            "https://example.com/pdf",
+            Some("text/html")
+        ));
+    }
+
+    #[test]
+    fn pdf_image_descriptions_are_formatted_for_chunk_text() {
+        assert_eq!(
+            format_pdf_image_description("  Revenue by quarter,\n  2023 to 2024 "),
+            "[Image: Revenue by quarter, 2023 to 2024]"
+        );
+    }
+}
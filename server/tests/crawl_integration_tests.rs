@@ -0,0 +1,1837 @@
+//! Integration tests for the crawl operator.
+//!
+//! Firecrawl is replaced by [`MockFirecrawl`], an in-process actix-web server serving canned
+//! `IngestResult` pages, and Redis by [`FakeRedisPool`], a small in-process server speaking
+//! enough of the Redis protocol for the commands used by the crawl operator. Tests which touch
+//! crawl requests additionally need a migrated Postgres database at `DATABASE_URL` and are
+//! skipped when it is not set.
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    sync::{Arc, Mutex as StdMutex},
+};
+
+use actix_web::{web, App, HttpResponse, HttpServer};
+use diesel::prelude::*;
+use diesel_async::{pooled_connection::AsyncDieselConnectionManager, RunQueryDsl};
+use once_cell::sync::Lazy;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+};
+use trieve_server::{
+    data::models::{
+        CrawlCostEstimate, CrawlEventType, CrawlIndexedPage, CrawlOptions, CrawlRequest,
+        CrawlStatus, Dataset, DatasetConfiguration, Pool, RedisPool,
+    },
+    errors::ServiceError,
+    operators::{
+        crawl_operator::{
+            clear_firecrawl_progress, compile_url_patterns, crawl, crawl_site, crawled_url_hash,
+            create_crawl_request, delete_chunks_for_crawl, delete_crawl_request_query,
+            estimate_crawl_cost, force_crawl_now, get_audit_crawl_events,
+            get_cached_firecrawl_docs, get_crawl_events, get_crawl_from_firecrawl,
+            get_crawl_maintenance_mode, get_crawl_request, get_crawl_request_by_id,
+            get_crawl_requests_to_rerun, get_crawl_summary, get_last_processed_firecrawl_page,
+            get_urls_indexed_in_other_datasets, is_crawl_reindex, list_dataset_urls,
+            migrate_crawl_to_new_url, record_crawl_indexed_pages, record_crawled_urls,
+            reindex_crawl, replace_base_url, restore_crawl_documents, resume_interrupted_crawl,
+            save_firecrawl_exclude_url_patterns, set_crawl_maintenance_mode, update_crawl_duration,
+            update_crawl_status, update_scrape_id, CircuitBreaker, CircuitBreakerState, Document,
+            FirecrawlConfig, IngestResult, Status,
+        },
+        dataset_operator::create_dataset_query,
+        organization_operator::create_organization_query,
+    },
+};
+
+const SCRAPE_QUEUE: &str = "scrape_queue";
+
+/// A response served by [`MockFirecrawl`] for one page of a crawl.
+#[derive(Clone)]
+enum MockPage {
+    Result(IngestResult),
+    Error(u16),
+}
+
+#[derive(Default)]
+struct MockFirecrawlState {
+    crawls: HashMap<String, uuid::Uuid>,
+    pages: HashMap<uuid::Uuid, Vec<MockPage>>,
+    maps: HashMap<String, Vec<String>>,
+}
+
+/// In-process stand-in for the Firecrawl API. A single server is shared by all tests since
+/// `FIRECRAWL_URL` is read from the environment, tests keep apart by using their own urls and
+/// scrape ids.
+#[derive(Clone)]
+struct MockFirecrawl {
+    base_url: String,
+    state: Arc<StdMutex<MockFirecrawlState>>,
+}
+
+static MOCK_FIRECRAWL: Lazy<MockFirecrawl> = Lazy::new(MockFirecrawl::start);
+
+#[derive(serde::Deserialize)]
+struct PageQuery {
+    page: Option<usize>,
+}
+
+async fn mock_start_crawl(
+    body: web::Json<serde_json::Value>,
+    state: web::Data<Arc<StdMutex<MockFirecrawlState>>>,
+) -> HttpResponse {
+    let url = body
+        .get("url")
+        .and_then(|url| url.as_str())
+        .unwrap_or_default();
+
+    match state.lock().unwrap().crawls.get(url) {
+        Some(scrape_id) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "id": scrape_id,
+        })),
+        None => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "error": "Unexpected crawl url",
+        })),
+    }
+}
+
+async fn mock_map(
+    body: web::Json<serde_json::Value>,
+    state: web::Data<Arc<StdMutex<MockFirecrawlState>>>,
+) -> HttpResponse {
+    let url = body
+        .get("url")
+        .and_then(|url| url.as_str())
+        .unwrap_or_default();
+
+    match state.lock().unwrap().maps.get(url) {
+        Some(links) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "links": links,
+        })),
+        None => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "error": "Unexpected map url",
+        })),
+    }
+}
+
+async fn mock_get_crawl(
+    scrape_id: web::Path<uuid::Uuid>,
+    query: web::Query<PageQuery>,
+    state: web::Data<Arc<StdMutex<MockFirecrawlState>>>,
+) -> HttpResponse {
+    let page = state
+        .lock()
+        .unwrap()
+        .pages
+        .get(&scrape_id.into_inner())
+        .and_then(|pages| pages.get(query.page.unwrap_or(0)).cloned());
+
+    match page {
+        Some(MockPage::Result(ingest_result)) => HttpResponse::Ok().json(ingest_result),
+        Some(MockPage::Error(status)) => {
+            HttpResponse::build(actix_web::http::StatusCode::from_u16(status).unwrap())
+                .body("Mock Firecrawl error")
+        }
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+impl MockFirecrawl {
+    fn start() -> Self {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let state = Arc::new(StdMutex::new(MockFirecrawlState::default()));
+
+        let server_state = state.clone();
+        std::thread::spawn(move || {
+            actix_web::rt::System::new().block_on(async move {
+                HttpServer::new(move || {
+                    App::new()
+                        .app_data(web::Data::new(server_state.clone()))
+                        .route("/v1/crawl", web::post().to(mock_start_crawl))
+                        .route("/v1/map", web::post().to(mock_map))
+                        .route("/v1/crawl/{scrape_id}", web::get().to(mock_get_crawl))
+                })
+                .workers(1)
+                .listen(listener)
+                .unwrap()
+                .run()
+                .await
+            })
+        });
+
+        std::env::set_var("FIRECRAWL_URL", &base_url);
+
+        MockFirecrawl { base_url, state }
+    }
+
+    fn page_url(&self, scrape_id: uuid::Uuid, page: usize) -> String {
+        if page == 0 {
+            format!("{}/v1/crawl/{}", self.base_url, scrape_id)
+        } else {
+            format!("{}/v1/crawl/{}?page={}", self.base_url, scrape_id, page)
+        }
+    }
+
+    /// Makes `POST /v1/crawl` for `site_url` start a crawl with the given scrape id.
+    fn register_crawl(&self, site_url: &str, scrape_id: uuid::Uuid) {
+        self.state
+            .lock()
+            .unwrap()
+            .crawls
+            .insert(site_url.to_string(), scrape_id);
+    }
+
+    /// Makes `POST /v1/map` for `site_url` return the given links.
+    fn register_map(&self, site_url: &str, links: &[&str]) {
+        self.state.lock().unwrap().maps.insert(
+            site_url.to_string(),
+            links.iter().map(|link| link.to_string()).collect(),
+        );
+    }
+
+    /// Serves the given pages for the crawl, linking each page to the next one like Firecrawl's
+    /// pagination does.
+    fn register_pages(
+        &self,
+        scrape_id: uuid::Uuid,
+        status: Status,
+        pages: Vec<Option<Vec<Document>>>,
+    ) {
+        let page_count = pages.len();
+        let mock_pages = pages
+            .into_iter()
+            .enumerate()
+            .map(|(i, docs)| match docs {
+                Some(docs) => MockPage::Result(IngestResult {
+                    status: status.clone(),
+                    completed: docs.len() as u32,
+                    total: docs.len() as u32,
+                    credits_used: docs.len() as u32,
+                    expires_at: "2100-01-01T00:00:00.000Z".to_string(),
+                    next: (i + 1 < page_count).then(|| self.page_url(scrape_id, i + 1)),
+                    data: Some(docs.into_iter().map(Some).collect()),
+                    failed_urls: None,
+                }),
+                None => MockPage::Error(500),
+            })
+            .collect();
+
+        self.state
+            .lock()
+            .unwrap()
+            .pages
+            .insert(scrape_id, mock_pages);
+    }
+}
+
+fn mock_firecrawl() -> &'static MockFirecrawl {
+    &MOCK_FIRECRAWL
+}
+
+fn document(url: &str) -> Document {
+    serde_json::from_value(serde_json::json!({
+        "markdown": format!("# {}", url),
+        "html": format!("<h1>{}</h1><p>Content of {}</p>", url, url),
+        "metadata": {
+            "title": url,
+            "sourceURL": url,
+            "statusCode": 200,
+        },
+    }))
+    .unwrap()
+}
+
+fn firecrawl_circuit_breaker() -> web::Data<CircuitBreaker> {
+    web::Data::new(CircuitBreaker::new("Firecrawl"))
+}
+
+fn crawl_options(site_url: &str) -> CrawlOptions {
+    serde_json::from_value(serde_json::json!({
+        "site_url": site_url,
+        "interval": "daily",
+        "limit": 10,
+    }))
+    .unwrap()
+}
+
+/// Reply to a fake Redis command, encoded as RESP2.
+enum Reply {
+    Simple(&'static str),
+    Bulk(Option<String>),
+    Integer(i64),
+    Array(Vec<Reply>),
+    Error(String),
+}
+
+impl Reply {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Reply::Simple(value) => buf.extend(format!("+{}\r\n", value).as_bytes()),
+            Reply::Bulk(Some(value)) => {
+                buf.extend(format!("${}\r\n{}\r\n", value.len(), value).as_bytes())
+            }
+            Reply::Bulk(None) => buf.extend(b"$-1\r\n"),
+            Reply::Integer(value) => buf.extend(format!(":{}\r\n", value).as_bytes()),
+            Reply::Array(values) => {
+                buf.extend(format!("*{}\r\n", values.len()).as_bytes());
+                for value in values {
+                    value.encode(buf);
+                }
+            }
+            Reply::Error(message) => buf.extend(format!("-{}\r\n", message).as_bytes()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct FakeRedisState {
+    strings: HashMap<String, String>,
+    lists: HashMap<String, Vec<String>>,
+    streams: HashMap<String, Vec<(String, Vec<String>)>>,
+    next_stream_id: u64,
+}
+
+/// A [`RedisPool`] connected to an in-process fake Redis. The `scrape_queue` list is kept in
+/// `queue` so tests can inspect what the crawl operator enqueued.
+struct FakeRedisPool {
+    pool: web::Data<RedisPool>,
+    queue: Arc<Mutex<Vec<String>>>,
+    state: Arc<Mutex<FakeRedisState>>,
+}
+
+impl FakeRedisPool {
+    async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        let queue = Arc::new(Mutex::new(Vec::new()));
+        let state = Arc::new(Mutex::new(FakeRedisState::default()));
+
+        let (server_queue, server_state) = (queue.clone(), state.clone());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(Self::serve(
+                    stream,
+                    server_queue.clone(),
+                    server_state.clone(),
+                ));
+            }
+        });
+
+        let manager = bb8_redis::RedisConnectionManager::new(format!("redis://{}", addr)).unwrap();
+        let pool = bb8_redis::bb8::Pool::builder()
+            .max_size(2)
+            .build(manager)
+            .await
+            .unwrap();
+
+        FakeRedisPool {
+            pool: web::Data::new(pool),
+            queue,
+            state,
+        }
+    }
+
+    async fn queued_crawl_requests(&self) -> Vec<CrawlRequest> {
+        self.queue
+            .lock()
+            .await
+            .iter()
+            .map(|message| serde_json::from_str(message).unwrap())
+            .collect()
+    }
+
+    async fn read_command(reader: &mut BufReader<TcpStream>) -> Option<Vec<String>> {
+        let mut line = String::new();
+        reader.read_line(&mut line).await.ok()?;
+        let arg_count: usize = line.trim_end().strip_prefix('*')?.parse().ok()?;
+
+        let mut args = Vec::with_capacity(arg_count);
+        for _ in 0..arg_count {
+            line.clear();
+            reader.read_line(&mut line).await.ok()?;
+            let len: usize = line.trim_end().strip_prefix('$')?.parse().ok()?;
+
+            let mut arg = vec![0; len + 2];
+            reader.read_exact(&mut arg).await.ok()?;
+            arg.truncate(len);
+            args.push(String::from_utf8(arg).ok()?);
+        }
+
+        Some(args)
+    }
+
+    async fn serve(
+        stream: TcpStream,
+        queue: Arc<Mutex<Vec<String>>>,
+        state: Arc<Mutex<FakeRedisState>>,
+    ) {
+        let mut reader = BufReader::new(stream);
+        let mut transaction: Option<Vec<Vec<String>>> = None;
+
+        while let Some(args) = Self::read_command(&mut reader).await {
+            let name = args.first().cloned().unwrap_or_default().to_uppercase();
+
+            let reply = match name.as_str() {
+                "MULTI" => {
+                    transaction = Some(vec![]);
+                    Reply::Simple("OK")
+                }
+                "EXEC" => match transaction.take() {
+                    Some(queued) => {
+                        let mut replies = vec![];
+                        for queued_args in queued {
+                            replies.push(Self::execute(&queued_args, &queue, &state).await);
+                        }
+                        Reply::Array(replies)
+                    }
+                    None => Reply::Error("ERR EXEC without MULTI".to_string()),
+                },
+                _ => match transaction.as_mut() {
+                    Some(queued) => {
+                        queued.push(args);
+                        Reply::Simple("QUEUED")
+                    }
+                    None => Self::execute(&args, &queue, &state).await,
+                },
+            };
+
+            let mut buf = vec![];
+            reply.encode(&mut buf);
+            if reader.get_mut().write_all(&buf).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    fn list<'a>(
+        key: &str,
+        queue: &'a mut Vec<String>,
+        state: &'a mut FakeRedisState,
+    ) -> &'a mut Vec<String> {
+        if key == SCRAPE_QUEUE {
+            queue
+        } else {
+            state.lists.entry(key.to_string()).or_default()
+        }
+    }
+
+    async fn execute(
+        args: &[String],
+        queue: &Mutex<Vec<String>>,
+        state: &Mutex<FakeRedisState>,
+    ) -> Reply {
+        let mut queue = queue.lock().await;
+        let mut state = state.lock().await;
+        let name = args[0].to_uppercase();
+        let key = args.get(1).cloned().unwrap_or_default();
+
+        match name.as_str() {
+            "PING" => Reply::Simple("PONG"),
+            "CLIENT" | "SELECT" => Reply::Simple("OK"),
+            "LPUSH" => {
+                let list = Self::list(&key, &mut queue, &mut state);
+                for value in &args[2..] {
+                    list.insert(0, value.clone());
+                }
+                Reply::Integer(list.len() as i64)
+            }
+            "RPUSH" => {
+                let list = Self::list(&key, &mut queue, &mut state);
+                list.extend(args[2..].iter().cloned());
+                Reply::Integer(list.len() as i64)
+            }
+            "RPOP" => Reply::Bulk(Self::list(&key, &mut queue, &mut state).pop()),
+            "LRANGE" => {
+                let list = Self::list(&key, &mut queue, &mut state);
+                let len = list.len() as i64;
+                let index = |arg: &String| {
+                    let index: i64 = arg.parse().unwrap_or(0);
+                    if index < 0 {
+                        (len + index).max(0)
+                    } else {
+                        index
+                    }
+                };
+                let (start, stop) = (index(&args[2]), index(&args[3]).min(len - 1));
+                Reply::Array(
+                    (start..=stop)
+                        .map(|i| Reply::Bulk(Some(list[i as usize].clone())))
+                        .collect(),
+                )
+            }
+            "XADD" => {
+                // XADD key [MAXLEN [~|=] count] * field value [field value ...]
+                let mut rest = &args[2..];
+                if rest
+                    .first()
+                    .is_some_and(|arg| arg.eq_ignore_ascii_case("MAXLEN"))
+                {
+                    rest = &rest[1..];
+                    if rest.first().is_some_and(|arg| arg == "~" || arg == "=") {
+                        rest = &rest[1..];
+                    }
+                    rest = &rest[1..];
+                }
+                state.next_stream_id += 1;
+                let id = format!("{}-0", state.next_stream_id);
+                state
+                    .streams
+                    .entry(key)
+                    .or_default()
+                    .push((id.clone(), rest[1..].to_vec()));
+                Reply::Bulk(Some(id))
+            }
+            "XRANGE" => Reply::Array(
+                state
+                    .streams
+                    .get(&key)
+                    .cloned()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(id, fields)| {
+                        Reply::Array(vec![
+                            Reply::Bulk(Some(id)),
+                            Reply::Array(
+                                fields.into_iter().map(|f| Reply::Bulk(Some(f))).collect(),
+                            ),
+                        ])
+                    })
+                    .collect(),
+            ),
+            "EXPIRE" => Reply::Integer(1),
+            "GET" => Reply::Bulk(state.strings.get(&key).cloned()),
+            "SET" => {
+                let nx = args[3..].iter().any(|arg| arg.eq_ignore_ascii_case("NX"));
+                if nx && state.strings.contains_key(&key) {
+                    Reply::Bulk(None)
+                } else {
+                    state.strings.insert(key, args[2].clone());
+                    Reply::Simple("OK")
+                }
+            }
+            "DEL" => {
+                let mut deleted = 0;
+                for key in &args[1..] {
+                    let removed_list = if key == SCRAPE_QUEUE {
+                        let had_items = !queue.is_empty();
+                        queue.clear();
+                        had_items
+                    } else {
+                        state.lists.remove(key).is_some_and(|list| !list.is_empty())
+                    };
+                    if state.strings.remove(key).is_some() || removed_list {
+                        deleted += 1;
+                    }
+                }
+                Reply::Integer(deleted)
+            }
+            _ => Reply::Error(format!("ERR unknown command '{}'", name)),
+        }
+    }
+}
+
+async fn test_pool() -> Option<web::Data<Pool>> {
+    let database_url = std::env::var("DATABASE_URL").ok()?;
+    let manager =
+        AsyncDieselConnectionManager::<diesel_async::AsyncPgConnection>::new(database_url);
+    let pool = Pool::builder(manager).max_size(2).build().ok()?;
+
+    Some(web::Data::new(pool))
+}
+
+/// Creates an organization with a single dataset to attach crawl requests to.
+async fn create_test_dataset(pool: web::Data<Pool>) -> Dataset {
+    let organization = create_organization_query(
+        &format!("crawl-integration-tests-{}", uuid::Uuid::new_v4()),
+        pool.clone(),
+    )
+    .await
+    .unwrap();
+
+    create_dataset_query(
+        Dataset::from_details(
+            "crawl integration test".to_string(),
+            organization.id,
+            None,
+            DatasetConfiguration::default(),
+        ),
+        pool,
+    )
+    .await
+    .unwrap()
+}
+
+async fn delete_test_dataset(dataset: Dataset, pool: web::Data<Pool>) {
+    use trieve_server::data::schema::organizations::dsl as organizations_columns;
+
+    let mut conn = pool.get().await.unwrap();
+    diesel::delete(
+        organizations_columns::organizations
+            .filter(organizations_columns::id.eq(dataset.organization_id)),
+    )
+    .execute(&mut conn)
+    .await
+    .unwrap();
+}
+
+macro_rules! require_database {
+    () => {
+        match test_pool().await {
+            Some(pool) => pool,
+            None => {
+                eprintln!("Skipping test, DATABASE_URL is not set");
+                return;
+            }
+        }
+    };
+}
+
+#[actix_web::test]
+async fn crawl_site_returns_scrape_id_from_firecrawl() {
+    let firecrawl = mock_firecrawl();
+    let scrape_id = uuid::Uuid::new_v4();
+    firecrawl.register_crawl("https://crawl-site.example.com", scrape_id);
+
+    let crawl_id = crawl_site(crawl_options("https://crawl-site.example.com"))
+        .await
+        .unwrap();
+
+    assert_eq!(crawl_id, scrape_id);
+}
+
+#[actix_web::test]
+async fn crawl_site_surfaces_firecrawl_errors() {
+    mock_firecrawl();
+
+    let result = crawl_site(crawl_options("https://unregistered.example.com")).await;
+
+    assert!(result.is_err());
+}
+
+#[actix_web::test]
+async fn get_crawl_from_firecrawl_follows_pagination() {
+    let firecrawl = mock_firecrawl();
+    let redis = FakeRedisPool::start().await;
+    let scrape_id = uuid::Uuid::new_v4();
+    firecrawl.register_pages(
+        scrape_id,
+        Status::Completed,
+        vec![
+            Some(vec![
+                document("https://paginated.example.com/a"),
+                document("https://paginated.example.com/b"),
+            ]),
+            Some(vec![document("https://paginated.example.com/c")]),
+            Some(vec![document("https://paginated.example.com/d")]),
+        ],
+    );
+
+    let ingest_result = get_crawl_from_firecrawl(
+        scrape_id,
+        None,
+        &[],
+        firecrawl_circuit_breaker(),
+        redis.pool.clone(),
+    )
+    .await
+    .unwrap();
+
+    let urls: Vec<String> = ingest_result
+        .data
+        .unwrap_or_default()
+        .into_iter()
+        .flatten()
+        .filter_map(|doc| doc.metadata.source_url)
+        .collect();
+    assert_eq!(
+        urls,
+        vec![
+            "https://paginated.example.com/a",
+            "https://paginated.example.com/b",
+            "https://paginated.example.com/c",
+            "https://paginated.example.com/d",
+        ]
+    );
+    assert_eq!(ingest_result.status, Status::Completed);
+    assert!(ingest_result.next.is_none());
+    assert!(
+        get_last_processed_firecrawl_page(scrape_id, redis.pool.clone())
+            .await
+            .is_none()
+    );
+}
+
+#[actix_web::test]
+async fn get_crawl_from_firecrawl_drops_excluded_urls() {
+    let firecrawl = mock_firecrawl();
+    let redis = FakeRedisPool::start().await;
+    let scrape_id = uuid::Uuid::new_v4();
+    firecrawl.register_pages(
+        scrape_id,
+        Status::Completed,
+        vec![Some(vec![
+            document("https://excluded.example.com/docs"),
+            document("https://excluded.example.com/user/profile/1"),
+        ])],
+    );
+
+    let exclude_url_patterns = compile_url_patterns(&["/user/profile/.*".to_string()]).unwrap();
+    let ingest_result = get_crawl_from_firecrawl(
+        scrape_id,
+        None,
+        &exclude_url_patterns,
+        firecrawl_circuit_breaker(),
+        redis.pool.clone(),
+    )
+    .await
+    .unwrap();
+
+    let urls: Vec<String> = ingest_result
+        .data
+        .unwrap_or_default()
+        .into_iter()
+        .flatten()
+        .filter_map(|doc| doc.metadata.source_url)
+        .collect();
+    assert_eq!(urls, vec!["https://excluded.example.com/docs"]);
+}
+
+#[actix_web::test]
+async fn get_crawl_from_firecrawl_returns_in_progress_crawls() {
+    let firecrawl = mock_firecrawl();
+    let redis = FakeRedisPool::start().await;
+    let scrape_id = uuid::Uuid::new_v4();
+    firecrawl.register_pages(
+        scrape_id,
+        Status::Scraping,
+        vec![Some(vec![document("https://in-progress.example.com")])],
+    );
+
+    let ingest_result = get_crawl_from_firecrawl(
+        scrape_id,
+        None,
+        &[],
+        firecrawl_circuit_breaker(),
+        redis.pool.clone(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(ingest_result.status, Status::Scraping);
+}
+
+#[actix_web::test]
+async fn crawls_with_failed_pages_are_partial() {
+    let firecrawl = mock_firecrawl();
+    let redis = FakeRedisPool::start().await;
+    let scrape_id = uuid::Uuid::new_v4();
+    let mut missing_page = document("https://partial.example.com/missing");
+    missing_page.metadata.status_code = Some(404);
+    firecrawl.register_pages(
+        scrape_id,
+        Status::Completed,
+        vec![Some(vec![
+            document("https://partial.example.com"),
+            missing_page,
+        ])],
+    );
+
+    let ingest_result = get_crawl_from_firecrawl(
+        scrape_id,
+        None,
+        &[],
+        firecrawl_circuit_breaker(),
+        redis.pool.clone(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(ingest_result.status, Status::Partial);
+    assert_eq!(
+        ingest_result.failed_urls,
+        Some(vec!["https://partial.example.com/missing".to_string()])
+    );
+    assert_eq!(CrawlStatus::from_page_counts(2, 0).as_str(), "completed");
+    assert_eq!(CrawlStatus::from_page_counts(2, 1).as_str(), "partial");
+    assert_eq!(CrawlStatus::from_page_counts(2, 2).as_str(), "failed");
+}
+
+#[actix_web::test]
+async fn get_crawl_from_firecrawl_surfaces_firecrawl_errors() {
+    let firecrawl = mock_firecrawl();
+    let redis = FakeRedisPool::start().await;
+    let scrape_id = uuid::Uuid::new_v4();
+    firecrawl.register_pages(scrape_id, Status::Completed, vec![None]);
+
+    let result = get_crawl_from_firecrawl(
+        scrape_id,
+        None,
+        &[],
+        firecrawl_circuit_breaker(),
+        redis.pool.clone(),
+    )
+    .await;
+
+    assert!(result.is_err());
+}
+
+#[actix_web::test]
+async fn firecrawl_circuit_breaker_opens_after_repeated_failures() {
+    let firecrawl = mock_firecrawl();
+    let redis = FakeRedisPool::start().await;
+    let scrape_id = uuid::Uuid::new_v4();
+    firecrawl.register_pages(scrape_id, Status::Completed, vec![None]);
+    let circuit_breaker = firecrawl_circuit_breaker();
+
+    for _ in 0..5 {
+        assert!(matches!(
+            get_crawl_from_firecrawl(
+                scrape_id,
+                None,
+                &[],
+                circuit_breaker.clone(),
+                redis.pool.clone()
+            )
+            .await,
+            Err(ServiceError::InternalServerError(_))
+        ));
+    }
+
+    assert!(matches!(
+        circuit_breaker.state().await,
+        CircuitBreakerState::Open { .. }
+    ));
+    assert!(matches!(
+        get_crawl_from_firecrawl(scrape_id, None, &[], circuit_breaker, redis.pool.clone()).await,
+        Err(ServiceError::ServiceUnavailable(_))
+    ));
+}
+
+#[actix_web::test]
+async fn resume_interrupted_crawl_continues_from_last_page() {
+    let firecrawl = mock_firecrawl();
+    let redis = FakeRedisPool::start().await;
+    let scrape_id = uuid::Uuid::new_v4();
+    let pages = |second_page| {
+        vec![
+            Some(vec![document("https://resumed.example.com/a")]),
+            second_page,
+            Some(vec![document("https://resumed.example.com/c")]),
+        ]
+    };
+
+    firecrawl.register_pages(scrape_id, Status::Completed, pages(None));
+    assert!(get_crawl_from_firecrawl(
+        scrape_id,
+        None,
+        &[],
+        firecrawl_circuit_breaker(),
+        redis.pool.clone()
+    )
+    .await
+    .is_err());
+    assert_eq!(
+        get_last_processed_firecrawl_page(scrape_id, redis.pool.clone()).await,
+        Some(firecrawl.page_url(scrape_id, 1))
+    );
+
+    firecrawl.register_pages(
+        scrape_id,
+        Status::Completed,
+        pages(Some(vec![
+            document("https://resumed.example.com/b"),
+            document("https://resumed.example.com/user/profile/1"),
+        ])),
+    );
+    let mut options = crawl_options("https://resumed.example.com");
+    options.exclude_url_patterns = Some(vec!["/user/profile/.*".to_string()]);
+    save_firecrawl_exclude_url_patterns(scrape_id, &options, redis.pool.clone())
+        .await
+        .unwrap();
+    let ingest_result =
+        resume_interrupted_crawl(scrape_id, firecrawl_circuit_breaker(), redis.pool.clone())
+            .await
+            .unwrap();
+
+    let urls: Vec<String> = ingest_result
+        .data
+        .unwrap_or_default()
+        .into_iter()
+        .flatten()
+        .filter_map(|doc| doc.metadata.source_url)
+        .collect();
+    assert_eq!(
+        urls,
+        vec![
+            "https://resumed.example.com/a",
+            "https://resumed.example.com/b",
+            "https://resumed.example.com/c",
+        ]
+    );
+    assert_eq!(
+        redis
+            .state
+            .lock()
+            .await
+            .strings
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>(),
+        vec![format!("firecrawl_exclude_url_patterns:{}", scrape_id)]
+    );
+}
+
+#[actix_web::test]
+async fn crawl_creates_and_queues_crawl_request() {
+    let pool = require_database!();
+    let firecrawl = mock_firecrawl();
+    let redis = FakeRedisPool::start().await;
+    let dataset = create_test_dataset(pool.clone()).await;
+    let scrape_id = uuid::Uuid::new_v4();
+    firecrawl.register_crawl("https://end-to-end.example.com", scrape_id);
+
+    let crawl_id = crawl(
+        crawl_options("https://end-to-end.example.com"),
+        pool.clone(),
+        redis.pool.clone(),
+        dataset.id,
+        None,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(crawl_id, scrape_id);
+
+    let queued = redis.queued_crawl_requests().await;
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].scrape_id, scrape_id);
+    assert_eq!(queued[0].dataset_id, dataset.id);
+
+    let crawl_request = get_crawl_request(scrape_id, pool.clone()).await.unwrap();
+    assert_eq!(crawl_request.url, "https://end-to-end.example.com");
+    assert!(matches!(crawl_request.status, CrawlStatus::Pending));
+
+    delete_test_dataset(dataset, pool).await;
+}
+
+#[actix_web::test]
+async fn crawl_status_updates_are_persisted() {
+    let pool = require_database!();
+    let redis = FakeRedisPool::start().await;
+    let dataset = create_test_dataset(pool.clone()).await;
+    let scrape_id = uuid::Uuid::new_v4();
+
+    create_crawl_request(
+        crawl_options("https://status.example.com"),
+        dataset.id,
+        scrape_id,
+        None,
+        pool.clone(),
+        redis.pool.clone(),
+    )
+    .await
+    .unwrap();
+
+    update_crawl_status(
+        scrape_id,
+        CrawlStatus::GotResponseBackFromFirecrawl,
+        pool.clone(),
+        redis.pool.clone(),
+    )
+    .await
+    .unwrap();
+    let crawl_request = get_crawl_request(scrape_id, pool.clone()).await.unwrap();
+    assert!(matches!(
+        crawl_request.status,
+        CrawlStatus::GotResponseBackFromFirecrawl
+    ));
+
+    update_crawl_status(
+        scrape_id,
+        CrawlStatus::Completed,
+        pool.clone(),
+        redis.pool.clone(),
+    )
+    .await
+    .unwrap();
+    let crawl_request = get_crawl_request(scrape_id, pool.clone()).await.unwrap();
+    assert!(matches!(crawl_request.status, CrawlStatus::Completed));
+
+    let transitions: Vec<(CrawlEventType, Option<String>, String)> =
+        get_crawl_events(scrape_id, redis.pool.clone())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|event| (event.event, event.from, event.to))
+            .collect();
+    assert_eq!(
+        transitions,
+        vec![
+            (CrawlEventType::Created, None, "pending".to_string()),
+            (
+                CrawlEventType::StatusChanged,
+                Some("pending".to_string()),
+                "got_response_back_from_firecrawl".to_string()
+            ),
+            (
+                CrawlEventType::StatusChanged,
+                Some("got_response_back_from_firecrawl".to_string()),
+                "completed".to_string()
+            ),
+        ]
+    );
+
+    delete_test_dataset(dataset, pool).await;
+}
+
+#[actix_web::test]
+async fn duplicate_create_crawl_request_creates_separate_requests() {
+    let pool = require_database!();
+    let redis = FakeRedisPool::start().await;
+    let dataset = create_test_dataset(pool.clone()).await;
+    let scrape_id = uuid::Uuid::new_v4();
+
+    for _ in 0..2 {
+        create_crawl_request(
+            crawl_options("https://duplicate.example.com"),
+            dataset.id,
+            scrape_id,
+            None,
+            pool.clone(),
+            redis.pool.clone(),
+        )
+        .await
+        .unwrap();
+    }
+
+    let queued = redis.queued_crawl_requests().await;
+    assert_eq!(queued.len(), 2);
+    assert_ne!(queued[0].id, queued[1].id);
+    assert!(queued.iter().all(|request| request.scrape_id == scrape_id));
+
+    let crawl_request = get_crawl_request(scrape_id, pool.clone()).await.unwrap();
+    assert_eq!(crawl_request.dataset_id, dataset.id);
+
+    delete_test_dataset(dataset, pool).await;
+}
+
+#[actix_web::test]
+async fn crawled_urls_are_deduplicated_across_datasets_of_an_organization() {
+    let pool = require_database!();
+    let dataset = create_test_dataset(pool.clone()).await;
+    let other_dataset = create_dataset_query(
+        Dataset::from_details(
+            "crawl integration test other".to_string(),
+            dataset.organization_id,
+            None,
+            DatasetConfiguration::default(),
+        ),
+        pool.clone(),
+    )
+    .await
+    .unwrap();
+
+    record_crawled_urls(
+        dataset.organization_id,
+        dataset.id,
+        vec!["https://dedup.example.com/docs/".to_string()],
+        pool.clone(),
+    )
+    .await
+    .unwrap();
+    // Recording the same url twice is a no-op
+    record_crawled_urls(
+        dataset.organization_id,
+        dataset.id,
+        vec!["https://dedup.example.com/docs".to_string()],
+        pool.clone(),
+    )
+    .await
+    .unwrap();
+
+    let url_hashes = vec![
+        crawled_url_hash("https://dedup.example.com/docs"),
+        crawled_url_hash("https://dedup.example.com/blog"),
+    ];
+
+    let indexed_elsewhere = get_urls_indexed_in_other_datasets(
+        dataset.organization_id,
+        other_dataset.id,
+        url_hashes.clone(),
+        pool.clone(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        indexed_elsewhere,
+        HashSet::from([crawled_url_hash("https://dedup.example.com/docs")])
+    );
+
+    let indexed_in_same_dataset = get_urls_indexed_in_other_datasets(
+        dataset.organization_id,
+        dataset.id,
+        url_hashes,
+        pool.clone(),
+    )
+    .await
+    .unwrap();
+    assert!(indexed_in_same_dataset.is_empty());
+
+    delete_test_dataset(dataset, pool).await;
+}
+
+#[actix_web::test]
+async fn estimate_crawl_cost_counts_mapped_urls() {
+    let firecrawl = mock_firecrawl();
+    firecrawl.register_map(
+        "https://estimate.example.com",
+        &[
+            "https://estimate.example.com/",
+            "https://estimate.example.com/docs/intro",
+            "https://estimate.example.com/docs/guides/setup",
+            "https://estimate.example.com/blog/launch",
+            "https://estimate.example.com/docs/internal/notes",
+        ],
+    );
+    let config = FirecrawlConfig {
+        url: firecrawl.base_url.clone(),
+        api_key: "".to_string(),
+        credits_per_page: 2,
+        pages_per_minute: 2,
+    };
+
+    let mut options = crawl_options("https://estimate.example.com");
+    options.include_paths = Some(vec!["/docs/.*".to_string()]);
+    options.skip_urls_matching = Some(vec!["internal".to_string()]);
+
+    let estimate = estimate_crawl_cost(&options, &config).await.unwrap();
+    assert_eq!(
+        estimate,
+        CrawlCostEstimate {
+            estimated_pages: 2,
+            estimated_credits: 4,
+            estimated_duration_minutes: 1,
+        }
+    );
+
+    options.max_depth = Some(2);
+    let estimate = estimate_crawl_cost(&options, &config).await.unwrap();
+    assert_eq!(estimate.estimated_pages, 1);
+
+    let unmapped =
+        estimate_crawl_cost(&crawl_options("https://unmapped.example.com"), &config).await;
+    assert!(unmapped.is_err());
+}
+
+#[actix_web::test]
+async fn crawl_requests_can_be_looked_up_by_row_id() {
+    let pool = require_database!();
+    let redis = FakeRedisPool::start().await;
+    let dataset = create_test_dataset(pool.clone()).await;
+
+    create_crawl_request(
+        crawl_options("https://by-id.example.com"),
+        dataset.id,
+        uuid::Uuid::nil(),
+        None,
+        pool.clone(),
+        redis.pool.clone(),
+    )
+    .await
+    .unwrap();
+    let queued = redis.queued_crawl_requests().await;
+
+    let crawl_request = get_crawl_request_by_id(queued[0].id, pool.clone())
+        .await
+        .unwrap();
+    assert_eq!(crawl_request.id, queued[0].id);
+    assert_eq!(crawl_request.scrape_id, uuid::Uuid::nil());
+    assert_eq!(crawl_request.dataset_id, dataset.id);
+
+    assert!(matches!(
+        get_crawl_request_by_id(uuid::Uuid::new_v4(), pool.clone()).await,
+        Err(ServiceError::NotFound(_))
+    ));
+    assert!(matches!(
+        get_crawl_request(uuid::Uuid::new_v4(), pool.clone()).await,
+        Err(ServiceError::NotFound(_))
+    ));
+
+    delete_test_dataset(dataset, pool).await;
+}
+
+#[actix_web::test]
+async fn crawl_durations_are_recorded_and_summarized() {
+    let pool = require_database!();
+    let redis = FakeRedisPool::start().await;
+    let dataset = create_test_dataset(pool.clone()).await;
+    let scrape_id = uuid::Uuid::new_v4();
+
+    create_crawl_request(
+        crawl_options("https://duration.example.com"),
+        dataset.id,
+        scrape_id,
+        None,
+        pool.clone(),
+        redis.pool.clone(),
+    )
+    .await
+    .unwrap();
+    let queued = redis.queued_crawl_requests().await;
+    assert_eq!(queued[0].crawl_duration_ms, None);
+
+    update_crawl_duration(queued[0].id, 1500, pool.clone())
+        .await
+        .unwrap();
+
+    let crawl_request = get_crawl_request(scrape_id, pool.clone()).await.unwrap();
+    assert_eq!(crawl_request.crawl_duration_ms, Some(1500));
+
+    let summary = get_crawl_summary(pool.clone()).await.unwrap();
+    assert!(summary.total_crawls >= 1);
+    assert!(summary.p50_duration_ms.is_some());
+    assert!(summary.p95_duration_ms >= summary.p50_duration_ms);
+
+    delete_test_dataset(dataset, pool).await;
+}
+
+#[actix_web::test]
+async fn force_crawl_now_requeues_with_a_new_scrape_id() {
+    let pool = require_database!();
+    let firecrawl = mock_firecrawl();
+    let redis = FakeRedisPool::start().await;
+    let dataset = create_test_dataset(pool.clone()).await;
+    let scrape_id = uuid::Uuid::new_v4();
+    firecrawl.register_crawl("https://force-crawl.example.com", scrape_id);
+
+    crawl(
+        crawl_options("https://force-crawl.example.com"),
+        pool.clone(),
+        redis.pool.clone(),
+        dataset.id,
+        None,
+    )
+    .await
+    .unwrap();
+    update_crawl_status(
+        scrape_id,
+        CrawlStatus::Completed,
+        pool.clone(),
+        redis.pool.clone(),
+    )
+    .await
+    .unwrap();
+
+    let forced_scrape_id = uuid::Uuid::new_v4();
+    firecrawl.register_crawl("https://force-crawl.example.com", forced_scrape_id);
+    force_crawl_now(scrape_id, pool.clone(), redis.pool.clone())
+        .await
+        .unwrap();
+
+    let queued = redis.queued_crawl_requests().await;
+    assert_eq!(queued.len(), 2);
+    assert_eq!(queued[0].scrape_id, forced_scrape_id);
+
+    let crawl_request = get_crawl_request(forced_scrape_id, pool.clone())
+        .await
+        .unwrap();
+    assert!(matches!(crawl_request.status, CrawlStatus::Pending));
+
+    // A second forced crawl within the cooldown is rejected
+    assert!(
+        force_crawl_now(forced_scrape_id, pool.clone(), redis.pool.clone())
+            .await
+            .is_err()
+    );
+    assert_eq!(redis.queued_crawl_requests().await.len(), 2);
+
+    delete_test_dataset(dataset, pool).await;
+}
+
+#[actix_web::test]
+async fn crawls_of_suspended_organizations_are_not_rerun() {
+    use trieve_server::data::schema::organizations::dsl as organizations_columns;
+
+    let pool = require_database!();
+    let redis = FakeRedisPool::start().await;
+    let active_dataset = create_test_dataset(pool.clone()).await;
+    let suspended_dataset = create_test_dataset(pool.clone()).await;
+
+    let mut conn = pool.get().await.unwrap();
+    diesel::update(
+        organizations_columns::organizations
+            .filter(organizations_columns::id.eq(suspended_dataset.organization_id)),
+    )
+    .set(organizations_columns::status.eq("suspended"))
+    .execute(&mut conn)
+    .await
+    .unwrap();
+
+    let active_scrape_id = uuid::Uuid::new_v4();
+    let suspended_scrape_id = uuid::Uuid::new_v4();
+    for (dataset, scrape_id) in [
+        (&active_dataset, active_scrape_id),
+        (&suspended_dataset, suspended_scrape_id),
+    ] {
+        create_crawl_request(
+            crawl_options("https://rerun.example.com"),
+            dataset.id,
+            scrape_id,
+            None,
+            pool.clone(),
+            redis.pool.clone(),
+        )
+        .await
+        .unwrap();
+    }
+
+    let to_rerun = get_crawl_requests_to_rerun(pool.clone(), redis.pool.clone())
+        .await
+        .unwrap();
+    assert!(to_rerun
+        .iter()
+        .any(|request| request.scrape_id == active_scrape_id));
+    assert!(!to_rerun
+        .iter()
+        .any(|request| request.scrape_id == suspended_scrape_id));
+
+    delete_test_dataset(active_dataset, pool.clone()).await;
+    delete_test_dataset(suspended_dataset, pool).await;
+}
+
+#[actix_web::test]
+async fn deleted_crawl_requests_are_not_found() {
+    let pool = require_database!();
+    let redis = FakeRedisPool::start().await;
+    let dataset = create_test_dataset(pool.clone()).await;
+    let scrape_id = uuid::Uuid::new_v4();
+
+    create_crawl_request(
+        crawl_options("https://delete.example.com"),
+        dataset.id,
+        scrape_id,
+        None,
+        pool.clone(),
+        redis.pool.clone(),
+    )
+    .await
+    .unwrap();
+
+    // The crawl has not indexed any pages yet, so there are no chunks to delete
+    assert_eq!(
+        delete_chunks_for_crawl(scrape_id, dataset.id, pool.clone())
+            .await
+            .unwrap(),
+        0
+    );
+
+    delete_crawl_request_query(scrape_id, None, pool.clone())
+        .await
+        .unwrap();
+    assert!(matches!(
+        get_crawl_request(scrape_id, pool.clone()).await,
+        Err(ServiceError::NotFound(_))
+    ));
+
+    delete_test_dataset(dataset, pool).await;
+}
+
+#[actix_web::test]
+async fn deleting_a_crawl_removes_its_urls_from_the_crawled_url_index() {
+    let pool = require_database!();
+    let redis = FakeRedisPool::start().await;
+    let dataset = create_test_dataset(pool.clone()).await;
+    let scrape_id = uuid::Uuid::new_v4();
+
+    create_crawl_request(
+        crawl_options("https://unindex.example.com"),
+        dataset.id,
+        scrape_id,
+        None,
+        pool.clone(),
+        redis.pool.clone(),
+    )
+    .await
+    .unwrap();
+    record_crawl_indexed_pages(
+        scrape_id,
+        dataset.id,
+        vec![CrawlIndexedPage::from_details(
+            scrape_id,
+            dataset.id,
+            "https://unindex.example.com/docs".to_string(),
+            "hash".to_string(),
+        )],
+        pool.clone(),
+    )
+    .await
+    .unwrap();
+    record_crawled_urls(
+        dataset.organization_id,
+        dataset.id,
+        vec!["https://unindex.example.com/docs/".to_string()],
+        pool.clone(),
+    )
+    .await
+    .unwrap();
+
+    let url_hashes = vec![crawled_url_hash("https://unindex.example.com/docs")];
+    let other_dataset_id = uuid::Uuid::new_v4();
+    assert_eq!(
+        get_urls_indexed_in_other_datasets(
+            dataset.organization_id,
+            other_dataset_id,
+            url_hashes.clone(),
+            pool.clone(),
+        )
+        .await
+        .unwrap()
+        .len(),
+        1
+    );
+
+    delete_crawl_request_query(scrape_id, None, pool.clone())
+        .await
+        .unwrap();
+    assert!(get_urls_indexed_in_other_datasets(
+        dataset.organization_id,
+        other_dataset_id,
+        url_hashes,
+        pool.clone(),
+    )
+    .await
+    .unwrap()
+    .is_empty());
+
+    delete_test_dataset(dataset, pool).await;
+}
+
+#[actix_web::test]
+async fn list_dataset_urls_pages_through_distinct_urls() {
+    use trieve_server::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+
+    let pool = require_database!();
+    let dataset = create_test_dataset(pool.clone()).await;
+
+    let mut conn = pool.get().await.unwrap();
+    for url in [
+        "https://urls.example.com/b",
+        "https://urls.example.com/a",
+        "https://urls.example.com/a",
+        "https://urls.example.com/c",
+    ] {
+        diesel::insert_into(chunk_metadata_columns::chunk_metadata)
+            .values((
+                chunk_metadata_columns::id.eq(uuid::Uuid::new_v4()),
+                chunk_metadata_columns::qdrant_point_id.eq(uuid::Uuid::new_v4()),
+                chunk_metadata_columns::created_at.eq(chrono::Utc::now().naive_utc()),
+                chunk_metadata_columns::updated_at.eq(chrono::Utc::now().naive_utc()),
+                chunk_metadata_columns::metadata.eq(Some(serde_json::json!({ "url": url }))),
+                chunk_metadata_columns::dataset_id.eq(dataset.id),
+                chunk_metadata_columns::weight.eq(0.0),
+            ))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+    }
+
+    let (urls, total) = list_dataset_urls(dataset.id, 1, 2, pool.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        urls,
+        vec!["https://urls.example.com/a", "https://urls.example.com/b"]
+    );
+    assert_eq!(total, 3);
+
+    let (urls, total) = list_dataset_urls(dataset.id, 3, 2, pool.clone())
+        .await
+        .unwrap();
+    assert!(urls.is_empty());
+    assert_eq!(total, 3);
+
+    diesel::delete(
+        chunk_metadata_columns::chunk_metadata
+            .filter(chunk_metadata_columns::dataset_id.eq(dataset.id)),
+    )
+    .execute(&mut conn)
+    .await
+    .unwrap();
+    drop(conn);
+    delete_test_dataset(dataset, pool).await;
+}
+
+#[actix_web::test]
+async fn migrate_crawl_to_new_url_rewrites_chunk_urls_and_recrawls() {
+    use trieve_server::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+
+    assert_eq!(
+        replace_base_url(
+            "https://example.com/docs/a",
+            "https://example.com",
+            "https://docs.example.com"
+        ),
+        Some("https://docs.example.com/docs/a".to_string())
+    );
+    assert_eq!(
+        replace_base_url(
+            "https://other.com/a",
+            "https://example.com",
+            "https://docs.example.com"
+        ),
+        None
+    );
+    assert_eq!(
+        replace_base_url(
+            "https://example.com.evil.org/a",
+            "https://example.com",
+            "https://docs.example.com"
+        ),
+        None
+    );
+    assert_eq!(
+        replace_base_url(
+            "https://example.com",
+            "https://example.com",
+            "https://docs.example.com"
+        ),
+        Some("https://docs.example.com".to_string())
+    );
+
+    let pool = require_database!();
+    let firecrawl = mock_firecrawl();
+    let redis = FakeRedisPool::start().await;
+    let dataset = create_test_dataset(pool.clone()).await;
+    let scrape_id = uuid::Uuid::new_v4();
+    firecrawl.register_crawl("https://migrate.example.com", scrape_id);
+
+    crawl(
+        crawl_options("https://migrate.example.com"),
+        pool.clone(),
+        redis.pool.clone(),
+        dataset.id,
+        None,
+    )
+    .await
+    .unwrap();
+
+    let mut conn = pool.get().await.unwrap();
+    for url in [
+        "https://migrate.example.com/a",
+        "https://migrate.example.com/b",
+        "https://migrate.example.community/d",
+        "https://elsewhere.example.com/c",
+    ] {
+        diesel::insert_into(chunk_metadata_columns::chunk_metadata)
+            .values((
+                chunk_metadata_columns::id.eq(uuid::Uuid::new_v4()),
+                chunk_metadata_columns::link.eq(Some(url)),
+                chunk_metadata_columns::qdrant_point_id.eq(uuid::Uuid::new_v4()),
+                chunk_metadata_columns::created_at.eq(chrono::Utc::now().naive_utc()),
+                chunk_metadata_columns::updated_at.eq(chrono::Utc::now().naive_utc()),
+                chunk_metadata_columns::metadata.eq(Some(serde_json::json!({ "url": url }))),
+                chunk_metadata_columns::dataset_id.eq(dataset.id),
+                chunk_metadata_columns::weight.eq(0.0),
+            ))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+    }
+
+    for old_base_url in ["https://", "https://elsewhere.example.com"] {
+        assert!(migrate_crawl_to_new_url(
+            scrape_id,
+            old_base_url,
+            "https://docs.migrate.example.com",
+            pool.clone(),
+            redis.pool.clone(),
+        )
+        .await
+        .is_err());
+    }
+
+    let new_scrape_id = uuid::Uuid::new_v4();
+    firecrawl.register_crawl("https://docs.migrate.example.com", new_scrape_id);
+    let result = migrate_crawl_to_new_url(
+        scrape_id,
+        "https://migrate.example.com",
+        "https://docs.migrate.example.com",
+        pool.clone(),
+        redis.pool.clone(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.updated_chunks, 2);
+    assert_eq!(result.new_scrape_id, new_scrape_id);
+    assert_eq!(
+        get_audit_crawl_events(new_scrape_id, dataset.id, pool.clone())
+            .await
+            .unwrap()
+            .last()
+            .map(|event| event.action.clone()),
+        Some("url_migrated".to_string())
+    );
+
+    let crawl_request = get_crawl_request(new_scrape_id, pool.clone())
+        .await
+        .unwrap();
+    assert_eq!(crawl_request.url, "https://docs.migrate.example.com");
+    assert_eq!(
+        crawl_request.crawl_options.site_url.as_deref(),
+        Some("https://docs.migrate.example.com")
+    );
+    assert_eq!(
+        redis.queued_crawl_requests().await[0].scrape_id,
+        new_scrape_id
+    );
+
+    let (urls, _) = list_dataset_urls(dataset.id, 1, 10, pool.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        urls,
+        vec![
+            "https://docs.migrate.example.com/a",
+            "https://docs.migrate.example.com/b",
+            "https://elsewhere.example.com/c",
+            "https://migrate.example.community/d",
+        ]
+    );
+
+    diesel::delete(
+        chunk_metadata_columns::chunk_metadata
+            .filter(chunk_metadata_columns::dataset_id.eq(dataset.id)),
+    )
+    .execute(&mut conn)
+    .await
+    .unwrap();
+    drop(conn);
+    delete_test_dataset(dataset, pool).await;
+}
+
+#[actix_web::test]
+async fn crawls_are_not_rerun_in_maintenance_mode() {
+    let redis = FakeRedisPool::start().await;
+
+    assert!(
+        !get_crawl_maintenance_mode(redis.pool.clone())
+            .await
+            .unwrap()
+            .enabled
+    );
+
+    set_crawl_maintenance_mode(true, "Database migration", redis.pool.clone())
+        .await
+        .unwrap();
+    let maintenance_mode = get_crawl_maintenance_mode(redis.pool.clone())
+        .await
+        .unwrap();
+    assert!(maintenance_mode.enabled);
+    assert_eq!(
+        maintenance_mode.reason.as_deref(),
+        Some("Database migration")
+    );
+    assert!(maintenance_mode.enabled_at.is_some());
+
+    let pool = require_database!();
+    let dataset = create_test_dataset(pool.clone()).await;
+    create_crawl_request(
+        crawl_options("https://maintenance.example.com"),
+        dataset.id,
+        uuid::Uuid::new_v4(),
+        None,
+        pool.clone(),
+        redis.pool.clone(),
+    )
+    .await
+    .unwrap();
+
+    assert!(
+        get_crawl_requests_to_rerun(pool.clone(), redis.pool.clone())
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    set_crawl_maintenance_mode(false, "", redis.pool.clone())
+        .await
+        .unwrap();
+    assert!(
+        !get_crawl_maintenance_mode(redis.pool.clone())
+            .await
+            .unwrap()
+            .enabled
+    );
+    assert!(
+        !get_crawl_requests_to_rerun(pool.clone(), redis.pool.clone())
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    delete_test_dataset(dataset, pool).await;
+}
+
+#[actix_web::test]
+async fn restored_crawl_documents_are_used_until_progress_is_cleared() {
+    let redis = FakeRedisPool::start().await;
+    let scrape_id = uuid::Uuid::new_v4();
+    let docs = vec![
+        Some(document("https://reindex.example.com/docs")),
+        None,
+        Some(document("https://reindex.example.com/guides")),
+    ];
+
+    assert!(!is_crawl_reindex(scrape_id, redis.pool.clone())
+        .await
+        .unwrap());
+
+    restore_crawl_documents(scrape_id, &docs, redis.pool.clone())
+        .await
+        .unwrap();
+    assert!(is_crawl_reindex(scrape_id, redis.pool.clone())
+        .await
+        .unwrap());
+
+    let restored = get_cached_firecrawl_docs(scrape_id, redis.pool.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        restored
+            .iter()
+            .map(|doc| doc.as_ref().and_then(|doc| doc.metadata.source_url.clone()))
+            .collect::<Vec<_>>(),
+        vec![
+            Some("https://reindex.example.com/docs".to_string()),
+            None,
+            Some("https://reindex.example.com/guides".to_string()),
+        ]
+    );
+
+    clear_firecrawl_progress(scrape_id, redis.pool.clone())
+        .await
+        .unwrap();
+    assert!(!is_crawl_reindex(scrape_id, redis.pool.clone())
+        .await
+        .unwrap());
+    assert!(get_cached_firecrawl_docs(scrape_id, redis.pool.clone())
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[actix_web::test]
+async fn only_finished_crawls_can_be_reindexed() {
+    let pool = require_database!();
+    let firecrawl = mock_firecrawl();
+    let redis = FakeRedisPool::start().await;
+    let dataset = create_test_dataset(pool.clone()).await;
+    let scrape_id = uuid::Uuid::new_v4();
+    firecrawl.register_crawl("https://reindex-running.example.com", scrape_id);
+
+    crawl(
+        crawl_options("https://reindex-running.example.com"),
+        pool.clone(),
+        redis.pool.clone(),
+        dataset.id,
+        None,
+    )
+    .await
+    .unwrap();
+
+    assert!(matches!(
+        reindex_crawl(scrape_id, pool.clone(), redis.pool.clone()).await,
+        Err(ServiceError::BadRequest(_))
+    ));
+
+    delete_test_dataset(dataset, pool).await;
+}
+
+#[actix_web::test]
+async fn crawl_request_changes_are_recorded_in_the_audit_log() {
+    let pool = require_database!();
+    let redis = FakeRedisPool::start().await;
+    let dataset = create_test_dataset(pool.clone()).await;
+    let scrape_id = uuid::Uuid::new_v4();
+    let user_id = uuid::Uuid::new_v4();
+
+    create_crawl_request(
+        crawl_options("https://audit.example.com"),
+        dataset.id,
+        scrape_id,
+        Some(user_id),
+        pool.clone(),
+        redis.pool.clone(),
+    )
+    .await
+    .unwrap();
+    let crawl_request_id = get_crawl_request(scrape_id, pool.clone()).await.unwrap().id;
+
+    update_crawl_status(
+        scrape_id,
+        CrawlStatus::Completed,
+        pool.clone(),
+        redis.pool.clone(),
+    )
+    .await
+    .unwrap();
+    let new_scrape_id = uuid::Uuid::new_v4();
+    update_scrape_id(scrape_id, new_scrape_id, pool.clone())
+        .await
+        .unwrap();
+    delete_crawl_request_query(new_scrape_id, Some(user_id), pool.clone())
+        .await
+        .unwrap();
+
+    // The audit log outlives the crawl request it belongs to and is found by any of its scrape ids
+    let events = get_audit_crawl_events(scrape_id, dataset.id, pool.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        events
+            .iter()
+            .map(|event| event.action.as_str())
+            .collect::<Vec<_>>(),
+        vec!["created", "status_changed", "scrape_id_changed", "deleted"]
+    );
+    assert!(events
+        .iter()
+        .all(|event| event.crawl_request_id == crawl_request_id && event.dataset_id == dataset.id));
+    assert_eq!(
+        get_audit_crawl_events(new_scrape_id, dataset.id, pool.clone())
+            .await
+            .unwrap()
+            .len(),
+        4
+    );
+    assert!(
+        get_audit_crawl_events(scrape_id, uuid::Uuid::new_v4(), pool.clone())
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(events[0].user_id, Some(user_id));
+    assert_eq!(
+        events[0].new_value.as_ref().unwrap()["site_url"],
+        "https://audit.example.com"
+    );
+    assert_eq!(events[1].user_id, None);
+    assert_eq!(events[1].old_value, Some(serde_json::json!("pending")));
+    assert_eq!(events[1].new_value, Some(serde_json::json!("completed")));
+    assert_eq!(events[2].old_value, Some(serde_json::json!(scrape_id)));
+    assert_eq!(events[2].new_value, Some(serde_json::json!(new_scrape_id)));
+    assert_eq!(events[3].user_id, Some(user_id));
+    assert!(events[3].old_value.is_some());
+    assert_eq!(events[3].new_value, None);
+
+    delete_test_dataset(dataset, pool).await;
+}